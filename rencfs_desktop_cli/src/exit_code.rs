@@ -0,0 +1,148 @@
+use tonic::Code;
+
+use rencfs_desktop_common::vault_handler::VaultHandlerError;
+use rencfs_desktop_common::vault_service_error::VaultServiceError;
+
+use crate::Error;
+
+/// Shown in `--help`.
+pub const HELP: &str = "Exit codes:
+  0   success
+  1   failure
+  2   invalid usage
+  3   daemon not running or rejected the connection
+  4   vault not found
+  5   invalid argument
+  6   wrong or missing password
+  7   mount point busy or not empty
+  8   rencfs missing, incompatible or exited while unlocking
+  9   timeout
+  10  cannot access keyring
+  11  cancelled
+  12  another operation is running on the vault";
+
+// keep them stable, scripts rely on them
+pub const FAILURE: u8 = 1;
+/// bad arguments, clap also uses it
+pub const USAGE: u8 = 2;
+pub const DAEMON_UNAVAILABLE: u8 = 3;
+pub const NOT_FOUND: u8 = 4;
+pub const INVALID_ARGUMENT: u8 = 5;
+/// wrong or missing password
+pub const PASSWORD: u8 = 6;
+/// mount point busy or not empty
+pub const MOUNT_POINT: u8 = 7;
+/// rencfs missing, incompatible or exited while unlocking
+pub const RENCFS: u8 = 8;
+pub const TIMEOUT: u8 = 9;
+pub const KEYRING: u8 = 10;
+pub const CANCELLED: u8 = 11;
+/// another operation is running on the vault, retrying later can succeed
+pub const BUSY: u8 = 12;
+
+pub fn from_error(err: &Error) -> u8 {
+    match err {
+        Error::Connect(_) => DAEMON_UNAVAILABLE,
+        Error::Usage(_) => USAGE,
+        Error::Status(status) => match status.code() {
+            Code::Unavailable | Code::Unauthenticated => DAEMON_UNAVAILABLE,
+            Code::NotFound => NOT_FOUND,
+            Code::InvalidArgument => INVALID_ARGUMENT,
+            _ => FAILURE,
+        },
+        Error::Service(err) => from_service_error(err),
+    }
+}
+
+fn from_service_error(err: &VaultServiceError) -> u8 {
+    use VaultHandlerError::*;
+
+    match err {
+        VaultServiceError::VaultHandlerError(err) => match err {
+            InvalidPassword | PasswordRequired => PASSWORD,
+            MountPointBusy { .. } | MountPointNotEmpty { .. } => MOUNT_POINT,
+            RencfsBinaryNotFound | IncompatibleRencfsVersion { .. } | ProcessExited { .. } => RENCFS,
+            UnlockTimeout { .. } => TIMEOUT,
+            CannotAccessKeyring => KEYRING,
+            Cancelled => CANCELLED,
+            CannotLockVault | CannotUnlockVault | CannotChangeMountPoint | CannotChangeDataDir | CannotChangePassword |
+            Database { .. } | InvalidTransition { .. } => FAILURE,
+        },
+        VaultServiceError::VaultNotFound | VaultServiceError::ScheduleNotFound => NOT_FOUND,
+        VaultServiceError::NotTimeLimited => FAILURE,
+        VaultServiceError::VaultBusy => BUSY,
+        VaultServiceError::InvalidName | VaultServiceError::DuplicateName(_) | VaultServiceError::InvalidMountPoint(_) |
+        VaultServiceError::InvalidDataDir(_) | VaultServiceError::InvalidRestartPolicy(_) |
+        VaultServiceError::InvalidIdleTimeout(_) | VaultServiceError::InvalidSchedule(_) => INVALID_ARGUMENT,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::Status;
+
+    use super::*;
+
+    /// Goes through a status like errors from daemon do.
+    fn code_of(err: impl Into<VaultServiceError>) -> u8 {
+        from_error(&Error::from(Status::from(err.into())))
+    }
+
+    #[test]
+    fn vault_handler_errors() {
+        use VaultHandlerError::*;
+
+        let codes = [
+            (InvalidPassword, PASSWORD),
+            (PasswordRequired, PASSWORD),
+            (MountPointBusy { mount_point: "/mnt".to_string(), processes: "bash (1)".to_string() }, MOUNT_POINT),
+            (MountPointNotEmpty { mount_point: "/mnt".to_string() }, MOUNT_POINT),
+            (RencfsBinaryNotFound, RENCFS),
+            (IncompatibleRencfsVersion { found: "0.1.0".to_string(), required: ">=0.13.0".to_string() }, RENCFS),
+            (ProcessExited { cause: "exit status: 1".to_string() }, RENCFS),
+            (UnlockTimeout { secs: 30 }, TIMEOUT),
+            (CannotAccessKeyring, KEYRING),
+            (Cancelled, CANCELLED),
+            (CannotLockVault, FAILURE),
+            (CannotUnlockVault, FAILURE),
+            (CannotChangeMountPoint, FAILURE),
+            (CannotChangeDataDir, FAILURE),
+            (CannotChangePassword, FAILURE),
+            (Database { cause: "locked".to_string() }, FAILURE),
+            (InvalidTransition { from: "locking".to_string(), to: "unlocking".to_string() }, FAILURE),
+        ];
+        for (err, code) in codes {
+            assert_eq!(code_of(err.clone()), code, "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn service_errors() {
+        let codes = [
+            (VaultServiceError::VaultNotFound, NOT_FOUND),
+            (VaultServiceError::ScheduleNotFound, NOT_FOUND),
+            (VaultServiceError::VaultBusy, BUSY),
+            (VaultServiceError::NotTimeLimited, FAILURE),
+            (VaultServiceError::InvalidName, INVALID_ARGUMENT),
+            (VaultServiceError::DuplicateName("work".to_string()), INVALID_ARGUMENT),
+            (VaultServiceError::InvalidMountPoint("not absolute".to_string()), INVALID_ARGUMENT),
+            (VaultServiceError::InvalidDataDir("not a dir".to_string()), INVALID_ARGUMENT),
+            (VaultServiceError::InvalidRestartPolicy("sometimes".to_string()), INVALID_ARGUMENT),
+            (VaultServiceError::InvalidIdleTimeout(1_000_000), INVALID_ARGUMENT),
+            (VaultServiceError::InvalidSchedule("no days".to_string()), INVALID_ARGUMENT),
+        ];
+        for (err, code) in codes {
+            assert_eq!(code_of(err.clone()), code, "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn other_statuses() {
+        assert_eq!(from_error(&Error::from(Status::unavailable("down"))), DAEMON_UNAVAILABLE);
+        assert_eq!(from_error(&Error::from(Status::unauthenticated("bad token"))), DAEMON_UNAVAILABLE);
+        assert_eq!(from_error(&Error::from(Status::not_found("gone"))), NOT_FOUND);
+        assert_eq!(from_error(&Error::from(Status::invalid_argument("bad"))), INVALID_ARGUMENT);
+        assert_eq!(from_error(&Error::from(Status::internal("boom"))), FAILURE);
+        assert_eq!(from_error(&Error::Usage("no vault".to_string())), USAGE);
+    }
+}
@@ -0,0 +1,301 @@
+use std::io::{self, BufRead, IsTerminal};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use tonic::Status;
+
+use rencfs_desktop_common::supervisor::RestartPolicy;
+use rencfs_desktop_common::transport::{self, AuthChannel};
+use rencfs_desktop_common::vault_service_error::VaultServiceError;
+
+use crate::daemon_service::{ChangePasswordRequest, CreateVaultRequest, EmptyRequest, ExtendUnlockRequest, IdRequest, LockRequest, StringIdRequest, UnlockRequest, UpdateVaultRequest, VaultInfo};
+use crate::daemon_service::vault_service_client::VaultServiceClient;
+use crate::output::Output;
+
+mod daemon_service {
+    tonic::include_proto!("rencfs_desktop");
+}
+
+mod exit_code;
+mod output;
+
+type Client = VaultServiceClient<AuthChannel>;
+
+/// Manage rencfs vaults through the rencfs_desktop daemon.
+#[derive(Parser)]
+#[command(version, after_help = exit_code::HELP)]
+struct Cli {
+    /// Print results and errors as JSON.
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List all vaults.
+    List,
+    /// Show one vault, by id or name.
+    Status {
+        vault: String,
+    },
+    /// Add a new vault, mount point and data dir must be empty dirs.
+    Create {
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        mount_point: String,
+        #[arg(long)]
+        data_dir: String,
+        /// Save the password in the system keyring on next unlock.
+        #[arg(long)]
+        remember_password: bool,
+        /// never, on-failure or always
+        #[arg(long, default_value = "never", value_parser = parse_restart_policy)]
+        restart_policy: RestartPolicy,
+        /// Lock the vault after this many minutes without use, 0 to never lock.
+        #[arg(long, default_value_t = 0)]
+        idle_timeout_mins: u32,
+        /// Lock the vault when the session is locked, the system sleeps or shuts down.
+        #[arg(long)]
+        lock_with_session: bool,
+    },
+    /// Unlock a vault. The password is asked on the terminal, or read from the first line of stdin when it's not a
+    /// terminal.
+    Unlock {
+        vault: String,
+        /// Use the password saved in the system keyring.
+        #[arg(long, conflicts_with = "password_stdin")]
+        keyring: bool,
+        /// Read the password from stdin even if it's a terminal.
+        #[arg(long)]
+        password_stdin: bool,
+        /// Lock it automatically after this many minutes.
+        #[arg(long, value_name = "MINS")]
+        for_mins: Option<u32>,
+    },
+    /// Change the password of a vault, it's remounted if unlocked. The passwords are asked on the terminal, or read
+    /// from the first two lines of stdin, old and then new, when it's not a terminal.
+    ChangePassword {
+        vault: String,
+        /// Read the passwords from stdin even if it's a terminal.
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Keep a vault unlocked with --for-mins some more minutes.
+    Extend {
+        vault: String,
+        mins: u32,
+    },
+    Lock {
+        vault: String,
+        /// Lock even if files are open in the vault, programs using them lose unsaved changes.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Lock all unlocked vaults.
+    LockAll {
+        /// Lock even if files are open in the vaults, programs using them lose unsaved changes.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Move the mount point, the vault is remounted if unlocked.
+    SetMountPoint {
+        vault: String,
+        path: String,
+    },
+    /// Lock the vault after this many minutes without use, 0 to never lock.
+    SetIdleTimeout {
+        vault: String,
+        mins: u32,
+    },
+    /// Lock the vault when the session is locked, the system sleeps or shuts down.
+    SetLockWithSession {
+        vault: String,
+        #[arg(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+    /// Move the encrypted content to a new dir, the vault is locked meanwhile.
+    SetDataDir {
+        vault: String,
+        path: String,
+    },
+}
+
+/// What can go wrong running a command.
+enum Error {
+    Connect(tonic::transport::Error),
+    Service(VaultServiceError),
+    Status(Status),
+    Usage(String),
+}
+
+impl From<Status> for Error {
+    fn from(status: Status) -> Self {
+        match VaultServiceError::try_from(status.clone()) {
+            Ok(err) => Error::Service(err),
+            Err(_) => Error::Status(status),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let out = Output::new(cli.json);
+
+    match run(cli.command, &out).await {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => {
+            out.error(&err);
+            ExitCode::from(exit_code::from_error(&err))
+        }
+    }
+}
+
+async fn run(command: Command, out: &Output) -> Result<(), Error> {
+    let mut client = VaultServiceClient::new(transport::connect().await.map_err(Error::Connect)?);
+
+    match command {
+        Command::List => {
+            let vaults = client.list_vaults(EmptyRequest {}).await?.into_inner().vaults;
+            out.vaults(&vaults);
+        }
+        Command::Status { vault } => {
+            let vault = find_vault(&mut client, &vault).await?;
+            out.vault(&vault);
+        }
+        Command::Create { name, mount_point, data_dir, remember_password, restart_policy, idle_timeout_mins, lock_with_session } => {
+            let vault = client.create_vault(CreateVaultRequest {
+                name,
+                mount_point,
+                data_dir,
+                remember_password,
+                restart_policy: restart_policy.as_str().to_string(),
+                idle_timeout_mins,
+                lock_with_session,
+            }).await?.into_inner();
+            out.vault(&vault);
+        }
+        Command::Unlock { vault, keyring, password_stdin, for_mins } => {
+            let vault = find_vault(&mut client, &vault).await?;
+            let password = if keyring { None } else { Some(read_password(&vault, password_stdin)?) };
+            let duration_secs = for_mins.map(|m| m.saturating_mul(60));
+            client.unlock(UnlockRequest { id: vault.id, password, duration_secs }).await?;
+            match for_mins {
+                Some(mins) if mins > 0 => out.done(&format!("vault {} unlocked for {} min", vault.name, mins)),
+                _ => out.done(&format!("vault {} unlocked", vault.name)),
+            }
+        }
+        Command::ChangePassword { vault, password_stdin } => {
+            let vault = find_vault(&mut client, &vault).await?;
+            let (old_password, new_password) = read_new_password(&vault, password_stdin)?;
+            client.change_password(ChangePasswordRequest { id: vault.id, old_password, new_password }).await?;
+            out.done(&format!("vault {} password changed", vault.name));
+        }
+        Command::Extend { vault, mins } => {
+            let vault = find_vault(&mut client, &vault).await?;
+            client.extend_unlock(ExtendUnlockRequest { id: vault.id, secs: mins.saturating_mul(60) }).await?;
+            out.done(&format!("vault {} unlock extended by {} min", vault.name, mins));
+        }
+        Command::Lock { vault, force } => {
+            let vault = find_vault(&mut client, &vault).await?;
+            client.lock(LockRequest { id: vault.id, force }).await?;
+            out.done(&format!("vault {} locked", vault.name));
+        }
+        Command::LockAll { force } => {
+            let vaults = client.list_vaults(EmptyRequest {}).await?.into_inner().vaults;
+            // try all of them, the first failure decides the exit code and is printed last
+            let mut res = Ok(());
+            for vault in vaults.iter().filter(|v| !v.locked) {
+                match client.lock(LockRequest { id: vault.id, force }).await {
+                    Ok(_) => out.done(&format!("vault {} locked", vault.name)),
+                    Err(status) if res.is_ok() => res = Err(Error::from(status)),
+                    Err(status) => out.error(&Error::from(status)),
+                }
+            }
+            return res;
+        }
+        Command::SetMountPoint { vault, path } => {
+            let vault = find_vault(&mut client, &vault).await?;
+            client.change_mount_point(StringIdRequest { id: vault.id, value: path }).await?;
+            out.done(&format!("vault {} mount point changed", vault.name));
+        }
+        Command::SetIdleTimeout { vault, mins } => {
+            let vault = find_vault(&mut client, &vault).await?;
+            client.update_vault(UpdateVaultRequest { id: vault.id, idle_timeout_mins: Some(mins), ..Default::default() }).await?;
+            out.done(&format!("vault {} idle timeout changed", vault.name));
+        }
+        Command::SetLockWithSession { vault, enabled } => {
+            let vault = find_vault(&mut client, &vault).await?;
+            client.update_vault(UpdateVaultRequest { id: vault.id, lock_with_session: Some(enabled), ..Default::default() }).await?;
+            out.done(&format!("vault {} lock with session {}", vault.name, if enabled { "on" } else { "off" }));
+        }
+        Command::SetDataDir { vault, path } => {
+            let vault = find_vault(&mut client, &vault).await?;
+            client.change_data_dir(StringIdRequest { id: vault.id, value: path }).await?;
+            out.done(&format!("vault {} data dir changed", vault.name));
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_restart_policy(s: &str) -> Result<RestartPolicy, String> {
+    s.parse().map_err(|_| format!("expected one of: {}", RestartPolicy::ALL.map(|p| p.as_str()).join(", ")))
+}
+
+/// `vault` is an id or a name, a number not matching any id is taken as a name.
+async fn find_vault(client: &mut Client, vault: &str) -> Result<VaultInfo, Error> {
+    if let Ok(id) = vault.parse() {
+        match client.get_vault(IdRequest { id }).await.map_err(Error::from) {
+            Err(Error::Service(VaultServiceError::VaultNotFound)) => {}
+            res => return Ok(res?.into_inner()),
+        }
+    }
+    client.list_vaults(EmptyRequest {}).await?.into_inner().vaults
+        .into_iter()
+        .find(|v| v.name == vault)
+        .ok_or(Error::Service(VaultServiceError::VaultNotFound))
+}
+
+fn read_password(vault: &VaultInfo, from_stdin: bool) -> Result<String, Error> {
+    if from_stdin || !io::stdin().is_terminal() {
+        read_password_line()
+    } else {
+        prompt_password(&format!("Password for {}: ", vault.name))
+    }
+}
+
+/// Returns the old and the new one, on the terminal the new one is asked twice.
+fn read_new_password(vault: &VaultInfo, from_stdin: bool) -> Result<(String, String), Error> {
+    if from_stdin || !io::stdin().is_terminal() {
+        return Ok((read_password_line()?, read_password_line()?));
+    }
+    let old_password = prompt_password(&format!("Current password for {}: ", vault.name))?;
+    let new_password = prompt_password("New password: ")?;
+    if prompt_password("Repeat new password: ")? != new_password {
+        return Err(Error::Usage("passwords don't match".to_string()));
+    }
+    Ok((old_password, new_password))
+}
+
+fn read_password_line() -> Result<String, Error> {
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line).map_err(|err| Error::Usage(format!("cannot read password: {}", err)))?;
+    non_empty(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn prompt_password(prompt: &str) -> Result<String, Error> {
+    let password = rpassword::prompt_password(prompt).map_err(|err| Error::Usage(format!("cannot read password: {}", err)))?;
+    non_empty(password)
+}
+
+fn non_empty(password: String) -> Result<String, Error> {
+    if password.is_empty() {
+        return Err(Error::Usage("empty password".to_string()));
+    }
+    Ok(password)
+}
@@ -0,0 +1,126 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::daemon_service::VaultInfo;
+use crate::Error;
+
+/// Prints results to stdout and errors to stderr, as text or JSON.
+pub struct Output {
+    json: bool,
+}
+
+#[derive(Serialize)]
+struct VaultJson<'a> {
+    id: u32,
+    name: &'a str,
+    mount_point: &'a str,
+    data_dir: &'a str,
+    locked: bool,
+    remember_password: bool,
+    restart_policy: &'a str,
+    last_exit_status: Option<i32>,
+    idle_timeout_mins: u32,
+    lock_with_session: bool,
+    lock_at: Option<i64>,
+    state: String,
+}
+
+impl<'a> From<&'a VaultInfo> for VaultJson<'a> {
+    fn from(v: &'a VaultInfo) -> Self {
+        Self {
+            id: v.id,
+            name: &v.name,
+            mount_point: &v.mount_point,
+            data_dir: &v.data_dir,
+            locked: v.locked,
+            remember_password: v.remember_password,
+            restart_policy: &v.restart_policy,
+            last_exit_status: v.last_exit_status,
+            idle_timeout_mins: v.idle_timeout_mins,
+            lock_with_session: v.lock_with_session,
+            lock_at: v.lock_at,
+            state: state(v),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorJson<'a> {
+    /// like `INVALID_PASSWORD`, stable for scripts
+    reason: &'a str,
+    message: String,
+}
+
+impl Output {
+    pub fn new(json: bool) -> Self {
+        Self { json }
+    }
+
+    pub fn vaults(&self, vaults: &[VaultInfo]) {
+        if self.json {
+            let vaults: Vec<VaultJson> = vaults.iter().map(VaultJson::from).collect();
+            println!("{}", serde_json::to_string_pretty(&vaults).unwrap());
+            return;
+        }
+        println!("{:<4} {:<20} {:<10} {}", "ID", "NAME", "STATE", "MOUNT POINT");
+        for v in vaults {
+            println!("{:<4} {:<20} {:<10} {}", v.id, v.name, state(v), v.mount_point);
+        }
+    }
+
+    pub fn vault(&self, v: &VaultInfo) {
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&VaultJson::from(v)).unwrap());
+            return;
+        }
+        println!("id:                {}", v.id);
+        println!("name:              {}", v.name);
+        println!("state:             {}", state(v));
+        println!("mount point:       {}", v.mount_point);
+        println!("data dir:          {}", v.data_dir);
+        println!("remember password: {}", v.remember_password);
+        println!("restart policy:    {}", v.restart_policy);
+        if v.idle_timeout_mins > 0 {
+            println!("lock after:        {} min idle", v.idle_timeout_mins);
+        }
+        println!("lock with session: {}", v.lock_with_session);
+        if let Some(lock_at) = v.lock_at.filter(|_| !v.locked) {
+            println!("locks in:          {}s", (lock_at - now()).max(0));
+        }
+        if let Some(code) = v.last_exit_status {
+            println!("last exit status:  {}", code);
+        }
+    }
+
+    pub fn done(&self, message: &str) {
+        if self.json {
+            println!("{}", serde_json::json!({ "message": message }));
+        } else {
+            println!("{}", message);
+        }
+    }
+
+    pub fn error(&self, err: &Error) {
+        let (reason, message) = match err {
+            Error::Connect(err) => ("DAEMON_UNAVAILABLE", format!("cannot connect to daemon, make sure it's running: {}", err)),
+            Error::Service(err) => (err.reason(), err.to_string()),
+            Error::Status(status) => ("DAEMON_ERROR", status.message().to_string()),
+            Error::Usage(message) => ("USAGE", message.clone()),
+        };
+        if self.json {
+            eprintln!("{}", serde_json::to_string(&ErrorJson { reason, message }).unwrap());
+        } else {
+            eprintln!("error: {}", message);
+        }
+    }
+}
+
+/// Like `unlocking`.
+fn state(v: &VaultInfo) -> String {
+    v.state().as_str_name().to_lowercase()
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
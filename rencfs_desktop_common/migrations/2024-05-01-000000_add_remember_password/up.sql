@@ -0,0 +1 @@
+ALTER TABLE vaults ADD COLUMN remember_password INTEGER NOT NULL default 0;
@@ -0,0 +1,2 @@
+ALTER TABLE vaults DROP COLUMN last_exit_status;
+ALTER TABLE vaults DROP COLUMN restart_policy;
@@ -0,0 +1,2 @@
+ALTER TABLE vaults ADD COLUMN restart_policy VARCHAR NOT NULL default 'never';
+ALTER TABLE vaults ADD COLUMN last_exit_status INTEGER;
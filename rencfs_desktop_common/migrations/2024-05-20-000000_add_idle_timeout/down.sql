@@ -0,0 +1 @@
+ALTER TABLE vaults DROP COLUMN idle_timeout_mins;
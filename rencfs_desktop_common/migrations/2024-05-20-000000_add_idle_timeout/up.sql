@@ -0,0 +1 @@
+ALTER TABLE vaults ADD COLUMN idle_timeout_mins INTEGER NOT NULL default 0;
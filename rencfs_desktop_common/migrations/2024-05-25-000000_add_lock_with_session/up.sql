@@ -0,0 +1 @@
+ALTER TABLE vaults ADD COLUMN lock_with_session INTEGER NOT NULL default 0;
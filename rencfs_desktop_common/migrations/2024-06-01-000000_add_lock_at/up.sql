@@ -0,0 +1 @@
+ALTER TABLE vaults ADD COLUMN lock_at BIGINT;
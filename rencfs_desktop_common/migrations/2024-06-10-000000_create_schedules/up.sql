@@ -0,0 +1,11 @@
+CREATE TABLE schedules
+(
+    id            INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    vault_id      INTEGER NOT NULL REFERENCES vaults (id) ON DELETE CASCADE,
+    action        VARCHAR NOT NULL,
+    minute_of_day INTEGER NOT NULL,
+    days          INTEGER NOT NULL,
+    enabled       INTEGER NOT NULL default 1,
+    last_run      BIGINT
+);
+CREATE INDEX schedules_vault_id ON schedules (vault_id);
@@ -0,0 +1,4 @@
+-- DROP COLUMN needs SQLite 3.35 or newer, we build it bundled
+ALTER TABLE vaults ADD COLUMN locked INTEGER NOT NULL default 1;
+UPDATE vaults SET locked = CASE state WHEN 'unlocked' THEN 0 ELSE 1 END;
+ALTER TABLE vaults DROP COLUMN state;
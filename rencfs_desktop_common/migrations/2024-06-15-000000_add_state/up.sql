@@ -0,0 +1,4 @@
+-- DROP COLUMN needs SQLite 3.35 or newer, we build it bundled
+ALTER TABLE vaults ADD COLUMN state VARCHAR NOT NULL default 'locked';
+UPDATE vaults SET state = CASE locked WHEN 0 THEN 'unlocked' ELSE 'locked' END;
+ALTER TABLE vaults DROP COLUMN locked;
@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use tracing::{instrument, warn};
+
+use crate::process_info;
+
+/// Where rencfs keeps the content of files and the entries of dirs, they are modified on every write.
+const CONTENTS_DIR: &str = "contents";
+
+/// When the vault mounted at `mount_point` with its files in `data_dir` was last used, `None` if we can't tell.
+///
+/// A process having its cwd or a file open under the mount point counts as using it now. Otherwise it's the latest of
+/// the access and modification times of the mount root, the modification times of `data_dir` and the dirs right in it,
+/// and of everything under its `contents` dir, so a file written anywhere in the vault is noticed. Only the encrypted
+/// files are looked at, not the mount, so checking doesn't keep rencfs busy. A file only read and closed between
+/// checks is not noticed.
+#[instrument]
+pub fn last_activity(mount_point: &Path, data_dir: &Path) -> Option<SystemTime> {
+    if !process_info::processes_using(mount_point).is_empty() {
+        return Some(SystemTime::now());
+    }
+
+    let mut times = vec![];
+    match fs::metadata(mount_point) {
+        Ok(metadata) => times.extend([metadata.accessed().ok(), metadata.modified().ok()]),
+        Err(err) => warn!(err = %err, "Cannot read mount point"),
+    }
+    match fs::read_dir(data_dir) {
+        Ok(entries) => {
+            times.push(fs::metadata(data_dir).and_then(|m| m.modified()).ok());
+            times.extend(entries.flatten()
+                .filter_map(|e| e.metadata().ok())
+                .filter(|m| m.is_dir())
+                .map(|m| m.modified().ok()));
+            times.push(latest_modified(&data_dir.join(CONTENTS_DIR)));
+        }
+        Err(err) => warn!(err = %err, "Cannot read data dir"),
+    }
+
+    times.into_iter().flatten().max()
+}
+
+/// Latest modification time of `dir` and everything under it.
+fn latest_modified(dir: &Path) -> Option<SystemTime> {
+    let mut latest = fs::metadata(dir).and_then(|m| m.modified()).ok();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return latest;
+    };
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else { continue; };
+        let modified = if metadata.is_dir() { latest_modified(&entry.path()) } else { metadata.modified().ok() };
+        latest = latest.max(modified);
+    }
+    latest
+}
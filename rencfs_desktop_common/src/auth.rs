@@ -0,0 +1,64 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+use rand::RngCore;
+use tonic::{Request, Status};
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+
+use crate::storage::get_config_dir;
+
+const TOKEN_FILE: &str = "daemon.token";
+pub const TOKEN_METADATA: &str = "authorization";
+
+pub fn token_path() -> PathBuf {
+    get_config_dir().join(TOKEN_FILE)
+}
+
+/// 32 random bytes, hex encoded.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Replaces the token file atomically, so clients never read a partial token. Only the owner can read it.
+pub fn write_token(token: &str) -> io::Result<()> {
+    let path = token_path();
+    let tmp = path.with_extension("tmp");
+    let _ = fs::remove_file(&tmp);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)?;
+    file.write_all(token.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp, &path)
+}
+
+pub fn read_token() -> io::Result<String> {
+    Ok(fs::read_to_string(token_path())?.trim().to_string())
+}
+
+/// Compares in constant time so the token can't be guessed byte by byte.
+pub fn token_matches(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len() &&
+        expected.bytes().zip(actual.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Adds the token to each request. It's read every time so clients pick up rotated tokens without reconnecting.
+#[derive(Clone, Default)]
+pub struct AuthInterceptor;
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let token = read_token().map_err(|err| Status::unauthenticated(format!("cannot read daemon token: {}", err)))?;
+        let value = MetadataValue::try_from(format!("Bearer {}", token))
+            .map_err(|_| Status::unauthenticated("invalid daemon token"))?;
+        request.metadata_mut().insert(TOKEN_METADATA, value);
+        Ok(request)
+    }
+}
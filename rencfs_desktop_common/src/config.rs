@@ -0,0 +1,74 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+
+use crate::storage::get_config_dir;
+
+const CONFIG_FILE: &str = "config.json";
+
+/// Settings read from `config.json` in the config dir, missing fields take their default value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Path to the `rencfs` binary, takes precedence over `RENCFS_BIN` env var and `$PATH`.
+    pub rencfs_bin: Option<PathBuf>,
+    /// How long to wait for the mount to show up when unlocking.
+    pub unlock_timeout_secs: u64,
+    /// Leave unlocked vaults mounted when daemon exits, they are adopted on next start. Only with the process engine,
+    /// in-process mounts go away with the daemon.
+    pub keep_mounted_on_exit: bool,
+    /// How vaults are mounted, `in-process` needs the daemon built with the `in-process` feature.
+    pub engine: EngineMode,
+    /// Also listen on this TCP address, like `[::1]:50051`. Any local user can connect to it, so it's off by default
+    /// and clients always use the unix socket.
+    pub tcp_address: Option<String>,
+    /// D-Bus address where we find `org.freedesktop.login1`, the system bus if not set. Point it to a session bus with
+    /// a fake login1 service to test locking on sleep and session lock.
+    pub logind_address: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rencfs_bin: None,
+            unlock_timeout_secs: 30,
+            keep_mounted_on_exit: false,
+            engine: EngineMode::default(),
+            tcp_address: None,
+            logind_address: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EngineMode {
+    /// a `rencfs` process per vault
+    #[default]
+    Process,
+    /// the daemon mounts vaults itself with the rencfs library
+    InProcess,
+}
+
+impl Config {
+    #[instrument]
+    pub fn load() -> Self {
+        let path = get_config_dir().join(CONFIG_FILE);
+        if !path.exists() {
+            return Self::default();
+        }
+        match fs::read_to_string(&path).map(|s| serde_json::from_str(&s)) {
+            Ok(Ok(config)) => config,
+            Ok(Err(err)) => {
+                warn!(err = %err, path = %path.display(), "Invalid config file, using defaults");
+                Self::default()
+            }
+            Err(err) => {
+                warn!(err = %err, path = %path.display(), "Cannot read config file, using defaults");
+                Self::default()
+            }
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Time left until `lock_at`, unix time in seconds, like `1:05:09` or `4:59`.
+pub fn time_left(lock_at: i64) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+    format_secs((lock_at - now).max(0))
+}
+
+fn format_secs(secs: i64) -> String {
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format() {
+        assert_eq!(format_secs(0), "0:00");
+        assert_eq!(format_secs(299), "4:59");
+        assert_eq!(format_secs(3600), "1:00:00");
+        assert_eq!(format_secs(3909), "1:05:09");
+    }
+
+    #[test]
+    fn past_is_zero() {
+        assert_eq!(time_left(0), "0:00");
+    }
+}
@@ -1,10 +1,11 @@
-use diesel::{AsChangeset, Connection, delete, EqAll, insert_into, QueryDsl, QueryResult, RunQueryDsl, SelectableHelper, SqliteConnection, update};
+use diesel::{AsChangeset, Connection, delete, EqAll, ExpressionMethods, insert_into, QueryDsl, QueryResult, RunQueryDsl, SelectableHelper, SqliteConnection, update};
 use diesel::query_builder::QueryFragment;
 use diesel::sqlite::Sqlite;
 
-use crate::models::{NewVault, Vault};
+use crate::models::{NewSchedule, NewVault, Schedule, Vault};
+use crate::schema::schedules;
 use crate::schema::vaults::dsl::vaults;
-use crate::schema::vaults::id;
+use crate::schema::vaults::{id, name};
 
 pub struct VaultDao<'a>(&'a mut SqliteConnection);
 
@@ -35,6 +36,12 @@ impl<'a> VaultDao<'a> {
             .first(self.0)
     }
 
+    pub fn get_by_name(&mut self, name_v: &str) -> QueryResult<Vault> {
+        vaults.filter(name.eq_all(name_v))
+            .select(Vault::as_select())
+            .first(self.0)
+    }
+
     pub fn update<V>(&mut self, id_v: i32, value: V) -> QueryResult<()>
         where V: AsChangeset<Target=vaults>, <V as AsChangeset>::Changeset: QueryFragment<Sqlite>
     {
@@ -63,3 +70,60 @@ impl<'a> VaultDao<'a> {
         })
     }
 }
+
+pub struct ScheduleDao<'a>(&'a mut SqliteConnection);
+
+impl<'a> ScheduleDao<'a> {
+    pub fn new(conn: &'a mut SqliteConnection) -> Self {
+        ScheduleDao(conn)
+    }
+
+    /// Returns the inserted schedule.
+    pub fn insert(&mut self, e: &NewSchedule) -> QueryResult<Schedule> {
+        self.0.transaction(|conn| {
+            insert_into(schedules::table)
+                .values(e)
+                .execute(conn)?;
+            // ids are autoincrement so the latest is ours
+            schedules::table.order(schedules::id.desc())
+                .select(Schedule::as_select())
+                .first(conn)
+        })
+    }
+
+    pub fn delete(&mut self, id_v: i32) -> QueryResult<()> {
+        delete(schedules::table.find(id_v))
+            .execute(self.0)?;
+
+        Ok(())
+    }
+
+    pub fn get(&mut self, id_v: i32) -> QueryResult<Schedule> {
+        schedules::table.find(id_v)
+            .select(Schedule::as_select())
+            .first(self.0)
+    }
+
+    pub fn get_by_vault(&mut self, vault_id_v: i32) -> QueryResult<Vec<Schedule>> {
+        schedules::table.filter(schedules::vault_id.eq_all(vault_id_v))
+            .order((schedules::minute_of_day, schedules::id))
+            .select(Schedule::as_select())
+            .load(self.0)
+    }
+
+    pub fn get_enabled(&mut self) -> QueryResult<Vec<Schedule>> {
+        schedules::table.filter(schedules::enabled.eq(1))
+            .select(Schedule::as_select())
+            .load(self.0)
+    }
+
+    pub fn update<V>(&mut self, id_v: i32, value: V) -> QueryResult<()>
+        where V: AsChangeset<Target=schedules::table>, <V as AsChangeset>::Changeset: QueryFragment<Sqlite>
+    {
+        update(schedules::table.find(id_v))
+            .set(value)
+            .execute(self.0)?;
+
+        Ok(())
+    }
+}
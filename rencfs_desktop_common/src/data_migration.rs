@@ -0,0 +1,362 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Read};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, instrument, warn};
+
+use crate::progress::ProgressReporter;
+use crate::storage::get_data_dir;
+
+const PARTIAL_SUFFIX: &str = ".rencfs_desktop_partial";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Phase {
+    /// copying and verifying content, db still points to old data dir
+    Copying,
+    /// db points to the new data dir, old one needs to be removed
+    Switched,
+}
+
+/// Moves the encrypted content of a vault to a new data dir.
+///
+/// Progress is kept in a journal file, so if the daemon is stopped in the middle it can be resumed with [Migration::pending].
+/// Old data dir is removed only after the content was verified and db was switched to the new one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Migration {
+    pub vault_id: i32,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub phase: Phase,
+}
+
+impl Migration {
+    pub fn new(vault_id: i32, from: PathBuf, to: PathBuf) -> io::Result<Self> {
+        if to.starts_with(&from) || from.starts_with(&to) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "data dirs cannot be nested"));
+        }
+        let migration = Self { vault_id, from, to, phase: Phase::Copying };
+        migration.save()?;
+        Ok(migration)
+    }
+
+    /// Migrations interrupted by a daemon stop.
+    pub fn pending() -> Vec<Migration> {
+        let entries = match fs::read_dir(journal_dir()) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        entries.flatten()
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|e| {
+                let migration = fs::read_to_string(e.path()).ok()
+                    .and_then(|s| serde_json::from_str(&s).ok());
+                if migration.is_none() {
+                    warn!(path = %e.path().display(), "Invalid migration journal");
+                }
+                migration
+            })
+            .collect()
+    }
+
+    /// Copies everything to the new data dir and checks the content matches. Blocking.
+    ///
+    /// Files already copied by an interrupted run are not copied again.
+    #[instrument(skip(self, progress), fields(self.vault_id), err)]
+    pub fn copy_and_verify(&mut self, progress: &ProgressReporter) -> io::Result<()> {
+        if !self.from.exists() && self.to.exists() {
+            // moved with rename by an interrupted run
+            return Ok(());
+        }
+        if self.try_rename()? {
+            info!("Moved with rename");
+            return Ok(());
+        }
+        let total = dir_size(&self.from)?;
+        info!(total, "Copying content");
+        progress.set_phase("copying", 0.0);
+        progress.set_bytes_total(total);
+        fs::create_dir_all(&self.to)?;
+        copy_dir(&self.from, &self.to, progress)?;
+        info!("Verifying content");
+        progress.set_phase("verifying", 0.0);
+        progress.set_bytes_total(total);
+        verify_dir(&self.from, &self.to, progress)
+    }
+
+    pub fn mark_switched(&mut self) -> io::Result<()> {
+        self.phase = Phase::Switched;
+        self.save()
+    }
+
+    /// Removes the old data dir and the journal. Blocking.
+    #[instrument(skip(self), fields(self.vault_id), err)]
+    pub fn finish(self) -> io::Result<()> {
+        if self.phase != Phase::Switched {
+            return Err(io::Error::new(io::ErrorKind::Other, "migration was not switched"));
+        }
+        if self.from.exists() {
+            info!("Removing old data dir");
+            fs::remove_dir_all(&self.from)?;
+        }
+        fs::remove_file(journal_path(self.vault_id))
+    }
+
+    /// Leaves the content where it was, used when the migration fails.
+    pub fn abort(self) -> io::Result<()> {
+        fs::remove_file(journal_path(self.vault_id))
+    }
+
+    /// On the same filesystem a rename is atomic, and a lot faster.
+    fn try_rename(&self) -> io::Result<bool> {
+        let to_parent = match self.to.parent() {
+            Some(parent) => parent,
+            None => return Ok(false),
+        };
+        fs::create_dir_all(to_parent)?;
+        if fs::metadata(&self.from)?.dev() != fs::metadata(to_parent)?.dev() {
+            return Ok(false);
+        }
+        if self.to.exists() {
+            if fs::read_dir(&self.to)?.next().is_some() {
+                // not empty, maybe from an interrupted copy, continue with copy
+                return Ok(false);
+            }
+            fs::remove_dir(&self.to)?;
+        }
+        fs::rename(&self.from, &self.to)?;
+        File::open(to_parent)?.sync_all()?;
+        Ok(true)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        fs::create_dir_all(journal_dir())?;
+        let path = journal_path(self.vault_id);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(self)?)?;
+        File::open(&tmp)?.sync_all()?;
+        fs::rename(tmp, path)
+    }
+}
+
+fn journal_dir() -> PathBuf {
+    get_data_dir().join("migrations")
+}
+
+fn journal_path(vault_id: i32) -> PathBuf {
+    journal_dir().join(format!("vault_{}.json", vault_id))
+}
+
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+fn check_cancelled(progress: &ProgressReporter) -> io::Result<()> {
+    if progress.is_cancelled() {
+        return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+    }
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path, progress: &ProgressReporter) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        check_cancelled(progress)?;
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            fs::create_dir_all(&dest)?;
+            copy_dir(&entry.path(), &dest, progress)?;
+        } else if file_type.is_symlink() {
+            if dest.symlink_metadata().is_err() {
+                std::os::unix::fs::symlink(fs::read_link(entry.path())?, &dest)?;
+            }
+        } else {
+            // a file is renamed in place only after it's fully written, so if it's there with same size it's complete
+            let len = entry.metadata()?.len();
+            if dest.metadata().is_ok_and(|m| m.len() == len) {
+                debug!(path = %dest.display(), "Already copied");
+                progress.add_bytes(len);
+                continue;
+            }
+            copy_file(&entry.path(), &dest)?;
+            progress.add_bytes(len);
+        }
+    }
+    File::open(to)?.sync_all()
+}
+
+fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    let mut partial = to.as_os_str().to_owned();
+    partial.push(PARTIAL_SUFFIX);
+    let partial = PathBuf::from(partial);
+
+    let mut src = File::open(from)?;
+    let mut dest = File::create(&partial)?;
+    io::copy(&mut src, &mut dest)?;
+    dest.set_permissions(src.metadata()?.permissions())?;
+    dest.sync_all()?;
+    fs::rename(partial, to)
+}
+
+fn verify_dir(from: &Path, to: &Path, progress: &ProgressReporter) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        check_cancelled(progress)?;
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            verify_dir(&entry.path(), &dest, progress)?;
+        } else if file_type.is_file() {
+            if !files_equal(&entry.path(), &dest)? {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("content differs for {}", dest.display())));
+            }
+            progress.add_bytes(entry.metadata()?.len());
+        }
+    }
+    Ok(())
+}
+
+fn files_equal(a: &Path, b: &Path) -> io::Result<bool> {
+    let (a, b) = (File::open(a)?, File::open(b)?);
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    let (mut a, mut b) = (BufReader::new(a), BufReader::new(b));
+    let (mut buf_a, mut buf_b) = (vec![0u8; 64 * 1024], vec![0u8; 64 * 1024]);
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    // journals are in the shared data dir, each test uses its own vault id
+
+    fn write_content(dir: &Path) {
+        fs::create_dir_all(dir.join("inodes")).unwrap();
+        fs::write(dir.join("inodes").join("1"), vec![7u8; 100 * 1024]).unwrap();
+        fs::write(dir.join("inodes").join("2"), b"second").unwrap();
+        fs::write(dir.join("security"), b"key").unwrap();
+    }
+
+    fn assert_same_content(a: &Path, b: &Path) {
+        for path in ["inodes/1", "inodes/2", "security"] {
+            assert_eq!(fs::read(a.join(path)).unwrap(), fs::read(b.join(path)).unwrap(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn rename_on_same_filesystem() {
+        let tmp = TempDir::new().unwrap();
+        let (from, to) = (tmp.path().join("old"), tmp.path().join("new").join("data"));
+        write_content(&from);
+        let copy = tmp.path().join("copy");
+        write_content(&copy);
+
+        // parent of the new data dir doesn't exist yet
+        let mut migration = Migration::new(1_000_001, from.clone(), to.clone()).unwrap();
+        migration.copy_and_verify(&ProgressReporter::noop()).unwrap();
+        assert!(!from.exists());
+        assert_same_content(&copy, &to);
+
+        migration.mark_switched().unwrap();
+        migration.finish().unwrap();
+        assert!(!journal_path(1_000_001).exists());
+    }
+
+    #[test]
+    fn copy_and_verify() {
+        let tmp = TempDir::new().unwrap();
+        let (from, to) = (tmp.path().join("old"), tmp.path().join("new"));
+        write_content(&from);
+        // not empty so it's copied instead of renamed
+        fs::create_dir_all(&to).unwrap();
+        fs::write(to.join("unrelated"), b"x").unwrap();
+
+        let mut migration = Migration::new(1_000_002, from.clone(), to.clone()).unwrap();
+        migration.copy_and_verify(&ProgressReporter::noop()).unwrap();
+        assert_same_content(&from, &to);
+
+        migration.mark_switched().unwrap();
+        migration.finish().unwrap();
+        assert!(!from.exists());
+        assert!(!journal_path(1_000_002).exists());
+    }
+
+    #[test]
+    fn resume_after_interruption() {
+        let tmp = TempDir::new().unwrap();
+        let (from, to) = (tmp.path().join("old"), tmp.path().join("new"));
+        write_content(&from);
+        Migration::new(1_000_003, from.clone(), to.clone()).unwrap();
+        // stopped in the middle, one file copied and one partially written
+        fs::create_dir_all(to.join("inodes")).unwrap();
+        fs::copy(from.join("security"), to.join("security")).unwrap();
+        fs::write(to.join("inodes").join(format!("1{}", PARTIAL_SUFFIX)), vec![7u8; 10]).unwrap();
+
+        let mut migration = Migration::pending().into_iter().find(|m| m.vault_id == 1_000_003).unwrap();
+        assert_eq!(migration.phase, Phase::Copying);
+        migration.copy_and_verify(&ProgressReporter::noop()).unwrap();
+        assert_same_content(&from, &to);
+
+        migration.mark_switched().unwrap();
+        // stopped again after switching, only the cleanup is left
+        let migration = Migration::pending().into_iter().find(|m| m.vault_id == 1_000_003).unwrap();
+        assert_eq!(migration.phase, Phase::Switched);
+        migration.finish().unwrap();
+        assert!(!from.exists());
+    }
+
+    #[test]
+    fn mismatch_aborts() {
+        let tmp = TempDir::new().unwrap();
+        let (from, to) = (tmp.path().join("old"), tmp.path().join("new"));
+        write_content(&from);
+        // same size so it's taken as already copied, but the content differs
+        fs::create_dir_all(&to).unwrap();
+        fs::write(to.join("security"), b"bad").unwrap();
+
+        let mut migration = Migration::new(1_000_004, from.clone(), to.clone()).unwrap();
+        let err = migration.copy_and_verify(&ProgressReporter::noop()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        // not switched, so it can't be finished
+        assert!(migration.clone().finish().is_err());
+
+        migration.abort().unwrap();
+        assert!(!journal_path(1_000_004).exists());
+        assert_eq!(fs::read(from.join("security")).unwrap(), b"key");
+    }
+
+    #[test]
+    fn nested_dirs_are_rejected() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("data");
+        assert!(Migration::new(1_000_005, dir.clone(), dir.join("inner")).is_err());
+        assert!(Migration::new(1_000_005, dir.join("inner"), dir).is_err());
+    }
+}
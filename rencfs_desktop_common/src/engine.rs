@@ -0,0 +1,84 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::EngineMode;
+use crate::models::Vault;
+use crate::progress::ProgressReporter;
+use crate::vault_handler::VaultHandlerError;
+
+/// How vaults are mounted, used by [crate::vault_handler::VaultHandler] and [crate::supervisor::Supervisor].
+///
+/// [crate::process_engine::ProcessEngine] runs a rencfs process per vault, `InProcessEngine` mounts them in the daemon
+/// with the rencfs library. The daemon gets one from [create] and hands it down, tests pass their own, like the
+/// `FakeEngine` from the `fake-engine` feature that only pretends to mount, so the daemon can run without FUSE.
+#[tonic::async_trait]
+pub trait VaultEngine: Send + Sync {
+    /// Returns once the vault is mounted and served.
+    async fn mount(&self, vault: &Vault, password: &str, progress: &ProgressReporter) -> Result<Box<dyn Mount>, VaultHandlerError>;
+
+    /// Cleanly unmounts the vault while its [Mount] still serves it, which then exits on its own. Fails with
+    /// [VaultHandlerError::MountPointBusy] if files are open there.
+    ///
+    /// With `lazy` it's detached right away even if busy, for stale mounts and for force locking.
+    async fn unmount(&self, mount_point: &Path, lazy: bool) -> Result<(), VaultHandlerError>;
+
+    async fn health(&self, vault: &Vault) -> Health;
+
+    /// The vault must be locked.
+    async fn change_password(&self, vault: &Vault, old_password: &str, new_password: &str) -> Result<(), VaultHandlerError>;
+
+    /// Takes over the vault if a previous daemon run left it mounted and served, cleans up what's left of it otherwise.
+    async fn reconcile(&self, vault: &Vault) -> Reconciled;
+
+    /// What a previous daemon run left running that doesn't belong to any of `vaults`, it's left alone and only
+    /// reported.
+    fn orphans(&self, _vaults: &[Vault]) -> Vec<String> {
+        vec![]
+    }
+
+    /// `true` if mounts keep being served after the daemon exits, so they can be left mounted and adopted on next start.
+    fn can_detach(&self) -> bool;
+}
+
+/// What [VaultEngine::reconcile] found for a vault.
+pub enum Reconciled {
+    /// mounted and served, it's ours now
+    Adopted(Box<dyn Mount>),
+    /// served but not mounted, it was stopped
+    Stopped,
+    /// mounted but nothing served it, it was unmounted
+    StaleMount,
+    NotMounted,
+}
+
+/// A vault mounted by a [VaultEngine], served until killed or it exits on its own.
+#[tonic::async_trait]
+pub trait Mount: Send {
+    /// Waits until it exits and returns the exit code, if known. For processes killed by a signal it's the negated
+    /// signal number.
+    async fn wait(&mut self) -> std::io::Result<Option<i32>>;
+
+    /// Stops serving the vault, the mount itself stays until [VaultEngine::unmount].
+    async fn kill(&mut self) -> std::io::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    Mounted,
+    NotMounted,
+    /// mounted but nothing serves it anymore, accessing it fails with "transport endpoint is not connected"
+    Stale,
+}
+
+pub fn create(mode: EngineMode) -> Arc<dyn VaultEngine> {
+    match mode {
+        EngineMode::Process => Arc::new(crate::process_engine::ProcessEngine),
+        #[cfg(feature = "in-process")]
+        EngineMode::InProcess => Arc::new(crate::in_process_engine::InProcessEngine::default()),
+        #[cfg(not(feature = "in-process"))]
+        EngineMode::InProcess => {
+            tracing::warn!("Built without the in-process feature, using the process engine");
+            Arc::new(crate::process_engine::ProcessEngine)
+        }
+    }
+}
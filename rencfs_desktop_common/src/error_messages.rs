@@ -0,0 +1,73 @@
+use tonic::{Code, Status};
+
+use crate::storage::get_logs_dir;
+use crate::vault_handler::VaultHandlerError;
+use crate::vault_service_error::VaultServiceError;
+
+/// What to show the user in GUI and TUI, saying what they can do about it when we know.
+pub fn user_message(err: &VaultServiceError) -> String {
+    use VaultHandlerError::*;
+
+    match err {
+        VaultServiceError::VaultHandlerError(err) => match err {
+            InvalidPassword => "Wrong password, please try again.".to_string(),
+            PasswordRequired => "Enter the password to unlock the vault.".to_string(),
+            MountPointBusy { mount_point, processes } if processes.is_empty() =>
+                format!("{} is in use, close the files and terminals open there and try again, or force lock it.", mount_point),
+            MountPointBusy { mount_point, processes } =>
+                format!("{} is in use by {}, close them and try again, or force lock it.", mount_point, processes),
+            MountPointNotEmpty { mount_point } =>
+                format!("{} is not empty, choose an empty folder as mount point.", mount_point),
+            RencfsBinaryNotFound =>
+                "rencfs is not installed, install it or set its path as rencfs_bin in config.json.".to_string(),
+            IncompatibleRencfsVersion { found, required } =>
+                format!("rencfs {} is not supported, install a version {}.", found, required),
+            ProcessExited { cause } =>
+                format!("rencfs stopped while unlocking: {}. See the vault logs in {}.", cause, get_logs_dir().display()),
+            UnlockTimeout { secs } =>
+                format!("The vault was not mounted after {}s, try again or increase unlock_timeout_secs in config.json.", secs),
+            Database { cause } => format!("Database error: {}. Try restarting the daemon.", cause),
+            CannotAccessKeyring =>
+                "Cannot access the system keyring, unlock it or uncheck Remember password.".to_string(),
+            Cancelled => "Cancelled.".to_string(),
+            InvalidTransition { from, .. } => format!("The vault is {} right now, wait for it to finish and try again.", from),
+            CannotLockVault | CannotUnlockVault | CannotChangeMountPoint | CannotChangeDataDir | CannotChangePassword =>
+                format!("{}, see the daemon logs in {}.", capitalize(&err.to_string()), get_logs_dir().display()),
+        },
+        VaultServiceError::VaultNotFound => "The vault no longer exists, it might have been deleted from another window.".to_string(),
+        VaultServiceError::InvalidName => "Enter a name for the vault.".to_string(),
+        VaultServiceError::DuplicateName(name) => format!("A vault named {} already exists, choose another name.", name),
+        VaultServiceError::InvalidMountPoint(cause) => format!("Invalid mount point, {}. Choose another folder.", cause),
+        VaultServiceError::InvalidDataDir(cause) => format!("Invalid data dir, {}. Choose another folder.", cause),
+        VaultServiceError::InvalidRestartPolicy(value) => format!("Unknown restart policy {}.", value),
+        VaultServiceError::InvalidIdleTimeout(_) =>
+            format!("Auto-lock timeout can be at most {} minutes, use 0 to never lock.", VaultServiceError::MAX_IDLE_TIMEOUT_MINS),
+        VaultServiceError::NotTimeLimited => "The vault is not unlocked for a limited time, there is nothing to extend.".to_string(),
+        VaultServiceError::VaultBusy =>
+            "Another operation is running on this vault, wait for it to finish or cancel it and try again.".to_string(),
+        VaultServiceError::ScheduleNotFound => "The schedule no longer exists, it might have been deleted from another window.".to_string(),
+        VaultServiceError::InvalidSchedule(cause) => format!("Invalid schedule, {}.", cause),
+    }
+}
+
+/// For statuses that are not a [VaultServiceError], mostly transport and auth failures.
+pub fn status_message(status: &Status) -> String {
+    match status.code() {
+        Code::Unavailable => "Cannot reach the daemon, make sure it's running.".to_string(),
+        Code::Unauthenticated => "The daemon rejected our token, restart the app.".to_string(),
+        _ => format!("Error: {}", status.message()),
+    }
+}
+
+/// For any status from daemon.
+pub fn status_to_message(status: &Status) -> String {
+    match VaultServiceError::try_from(status.clone()) {
+        Ok(err) => user_message(&err),
+        Err(_) => status_message(status),
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    chars.next().map_or_else(String::new, |c| c.to_uppercase().chain(chars).collect())
+}
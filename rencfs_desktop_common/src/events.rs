@@ -0,0 +1,59 @@
+use tokio::sync::broadcast;
+use tracing::debug;
+
+/// Subscribers that can't keep up lose the oldest events.
+const CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VaultEventKind {
+    Created,
+    Renamed,
+    /// settings other than the name changed
+    Updated,
+    Deleted,
+    Unlocking,
+    Unlocked,
+    Locking,
+    Locked,
+    /// data dir is being changed
+    Migrating,
+    /// rencfs process exited on its own
+    Crashed,
+    Error,
+    /// vault is about to be locked for being idle, message has the seconds left
+    IdleWarning,
+}
+
+#[derive(Debug, Clone)]
+pub struct VaultEvent {
+    pub vault_id: u32,
+    pub kind: VaultEventKind,
+    pub message: Option<String>,
+}
+
+/// Fans out vault state changes to everyone interested, like the clients watching vaults.
+#[derive(Clone)]
+pub struct EventBus(broadcast::Sender<VaultEvent>);
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self(broadcast::channel(CAPACITY).0)
+    }
+
+    pub fn emit(&self, vault_id: u32, kind: VaultEventKind, message: Option<String>) {
+        let event = VaultEvent { vault_id, kind, message };
+        debug!(?event, "Vault event");
+        // it's fine if no one is listening
+        let _ = self.0.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<VaultEvent> {
+        self.0.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::oneshot;
+
+use crate::engine::{Health, Mount, Reconciled, VaultEngine};
+use crate::models::Vault;
+use crate::progress::ProgressReporter;
+use crate::vault_handler::VaultHandlerError;
+
+/// Mounts vaults only in memory, to run the daemon in tests without FUSE or rencfs.
+///
+/// The first mount of a data dir sets its password, like rencfs does for a new vault. Tests make mounts exit with
+/// [FakeEngine::crash] and unmounts fail with [FakeEngine::set_busy]. Clones share the mounts, so a daemon started
+/// again with a clone adopts what the previous one left mounted.
+#[derive(Clone, Default)]
+pub struct FakeEngine {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// by data dir
+    passwords: HashMap<String, String>,
+    /// by mount point
+    mounts: HashMap<String, FakeMountState>,
+    busy: HashSet<String>,
+}
+
+struct FakeMountState {
+    /// `None` after it was killed or crashed, the mount is stale until unmounted
+    exit_tx: Option<oneshot::Sender<i32>>,
+}
+
+impl FakeEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the mount at `mount_point` exit with `code`, returns `false` if nothing serves it.
+    pub fn crash(&self, mount_point: &str, code: i32) -> bool {
+        let tx = self.inner.lock().unwrap().mounts.get_mut(mount_point).and_then(|m| m.exit_tx.take());
+        tx.is_some_and(|tx| tx.send(code).is_ok())
+    }
+
+    /// `true` if `mount_point` is mounted and served.
+    pub fn is_mounted(&self, mount_point: &str) -> bool {
+        self.inner.lock().unwrap().mounts.get(mount_point).is_some_and(|m| m.exit_tx.is_some())
+    }
+
+    /// While busy, clean unmounts of `mount_point` fail with [VaultHandlerError::MountPointBusy].
+    pub fn set_busy(&self, mount_point: &str, busy: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        if busy {
+            inner.busy.insert(mount_point.to_string());
+        } else {
+            inner.busy.remove(mount_point);
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl VaultEngine for FakeEngine {
+    async fn mount(&self, vault: &Vault, password: &str, progress: &ProgressReporter) -> Result<Box<dyn Mount>, VaultHandlerError> {
+        if progress.is_cancelled() {
+            return Err(VaultHandlerError::Cancelled);
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if inner.mounts.contains_key(&vault.mount_point) {
+            return Err(VaultHandlerError::MountPointNotEmpty { mount_point: vault.mount_point.clone() });
+        }
+        if inner.passwords.entry(vault.data_dir.clone()).or_insert_with(|| password.to_string()) != password {
+            return Err(VaultHandlerError::InvalidPassword);
+        }
+        let (exit_tx, exit_rx) = oneshot::channel();
+        inner.mounts.insert(vault.mount_point.clone(), FakeMountState { exit_tx: Some(exit_tx) });
+        progress.set_phase("mounted", 100.0);
+
+        Ok(Box::new(FakeMount { engine: self.clone(), mount_point: vault.mount_point.clone(), exit_rx }))
+    }
+
+    async fn unmount(&self, mount_point: &Path, lazy: bool) -> Result<(), VaultHandlerError> {
+        let mount_point = mount_point.to_string_lossy().to_string();
+        let mut inner = self.inner.lock().unwrap();
+        if !lazy && inner.busy.contains(&mount_point) {
+            return Err(VaultHandlerError::MountPointBusy { mount_point, processes: String::new() });
+        }
+        // like rencfs, it exits once unmounted
+        if let Some(tx) = inner.mounts.remove(&mount_point).and_then(|m| m.exit_tx) {
+            let _ = tx.send(0);
+        }
+        Ok(())
+    }
+
+    async fn health(&self, vault: &Vault) -> Health {
+        match self.inner.lock().unwrap().mounts.get(&vault.mount_point) {
+            Some(FakeMountState { exit_tx: Some(_) }) => Health::Mounted,
+            Some(_) => Health::Stale,
+            None => Health::NotMounted,
+        }
+    }
+
+    async fn change_password(&self, vault: &Vault, old_password: &str, new_password: &str) -> Result<(), VaultHandlerError> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.passwords.get_mut(&vault.data_dir) {
+            Some(password) if password == old_password => {
+                *password = new_password.to_string();
+                Ok(())
+            }
+            _ => Err(VaultHandlerError::InvalidPassword),
+        }
+    }
+
+    async fn reconcile(&self, vault: &Vault) -> Reconciled {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(mount) = inner.mounts.get_mut(&vault.mount_point) else {
+            return Reconciled::NotMounted;
+        };
+        if mount.exit_tx.is_none() {
+            inner.mounts.remove(&vault.mount_point);
+            return Reconciled::StaleMount;
+        }
+        // the previous one was dropped with the handler that detached it
+        let (exit_tx, exit_rx) = oneshot::channel();
+        mount.exit_tx = Some(exit_tx);
+        Reconciled::Adopted(Box::new(FakeMount { engine: self.clone(), mount_point: vault.mount_point.clone(), exit_rx }))
+    }
+
+    fn can_detach(&self) -> bool {
+        true
+    }
+}
+
+struct FakeMount {
+    engine: FakeEngine,
+    mount_point: String,
+    exit_rx: oneshot::Receiver<i32>,
+}
+
+#[tonic::async_trait]
+impl Mount for FakeMount {
+    async fn wait(&mut self) -> io::Result<Option<i32>> {
+        match (&mut self.exit_rx).await {
+            Ok(code) => Ok(Some(code)),
+            // killed, nothing will exit anymore
+            Err(_) => Err(io::Error::new(io::ErrorKind::Other, "mount was stopped")),
+        }
+    }
+
+    async fn kill(&mut self) -> io::Result<()> {
+        if let Some(mount) = self.engine.inner.lock().unwrap().mounts.get_mut(&self.mount_point) {
+            mount.exit_tx.take();
+        }
+        Ok(())
+    }
+}
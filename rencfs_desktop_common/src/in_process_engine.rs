@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rencfs::crypto::Cipher;
+use rencfs::encryptedfs::{EncryptedFs, FsError, PasswordProvider};
+use rencfs::mount::{create_mount_point, MountHandle};
+use shush_rs::SecretString;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{error, info, instrument, warn};
+
+use crate::config::Config;
+use crate::engine::{Health, Mount, Reconciled, VaultEngine};
+use crate::models::Vault;
+use crate::mount_info::{cleanup_stale_mount, ensure_mount_point_empty, find_fuse_mount, mount_point_busy, umount};
+use crate::process_info::processes_using;
+use crate::progress::ProgressReporter;
+use crate::vault_handler::VaultHandlerError;
+
+/// rencfs default, vaults created with the binary use it too.
+const CIPHER: Cipher = Cipher::ChaCha20Poly1305;
+
+/// Sent to the task serving a mount to unmount it, it replies with the result.
+type UmountRequest = oneshot::Sender<io::Result<()>>;
+
+/// Mounts each vault in the daemon with the rencfs library, served on its own task.
+///
+/// There is no process to start, so mounting returns as soon as it's ready and locking unmounts through the mount
+/// handle. Mounts go away with the daemon.
+#[derive(Default, Clone)]
+pub struct InProcessEngine {
+    /// mount points we serve, to tell apart stale mounts and to unmount them through their handle
+    served: Arc<Mutex<HashMap<PathBuf, mpsc::Sender<UmountRequest>>>>,
+}
+
+#[tonic::async_trait]
+impl VaultEngine for InProcessEngine {
+    #[instrument(skip(self, vault, password, progress), fields(id = vault.id), err)]
+    async fn mount(&self, vault: &Vault, password: &str, progress: &ProgressReporter) -> Result<Box<dyn Mount>, VaultHandlerError> {
+        progress.set_phase("mounting", 0.0);
+        if progress.is_cancelled() {
+            return Err(VaultHandlerError::Cancelled);
+        }
+        ensure_mount_point_empty(&vault.mount_point)?;
+
+        let mount_point = create_mount_point(
+            Path::new(&vault.mount_point),
+            Path::new(&vault.data_dir),
+            Box::new(Password(password.to_string())),
+            CIPHER,
+            false,
+            false,
+            false,
+        );
+        let timeout = Duration::from_secs(Config::load().unlock_timeout_secs);
+        let handle = match tokio::time::timeout(timeout, mount_point.mount()).await {
+            Ok(Ok(handle)) => handle,
+            Ok(Err(FsError::InvalidPassword)) => return Err(VaultHandlerError::InvalidPassword),
+            Ok(Err(err)) => {
+                error!(err = %err, "Cannot mount");
+                return Err(VaultHandlerError::CannotUnlockVault);
+            }
+            Err(_) => {
+                error!(?timeout, "Mount didn't finish in time");
+                return Err(VaultHandlerError::UnlockTimeout { secs: timeout.as_secs() });
+            }
+        };
+        // mounting can't be interrupted, undo it instead
+        if progress.is_cancelled() {
+            if let Err(err) = handle.umount().await {
+                error!(err = %err, "Cannot unmount after cancel");
+            }
+            return Err(VaultHandlerError::Cancelled);
+        }
+        progress.set_phase("mounted", 100.0);
+
+        let path = PathBuf::from(&vault.mount_point);
+        let (umount_tx, umount_rx) = oneshot::channel();
+        let (requests_tx, requests_rx) = mpsc::channel(1);
+        self.served.lock().unwrap().insert(path.clone(), requests_tx);
+        let task = tokio::spawn(serve(handle, umount_rx, requests_rx, path, self.served.clone()));
+        Ok(Box::new(InProcessMount { umount_tx: Some(umount_tx), task }))
+    }
+
+    #[instrument(skip(self), err)]
+    async fn unmount(&self, mount_point: &Path, lazy: bool) -> Result<(), VaultHandlerError> {
+        if lazy {
+            cleanup_stale_mount(mount_point).await;
+            return Ok(());
+        }
+        // the handle can't be used anymore once unmounting failed, so don't try while files are open
+        let processes = processes_using(mount_point);
+        if !processes.is_empty() {
+            return Err(mount_point_busy(mount_point, &processes));
+        }
+        let requests_tx = self.served.lock().unwrap().get(mount_point).cloned();
+        let Some(requests_tx) = requests_tx else {
+            // not served by us
+            return umount(mount_point).await;
+        };
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if requests_tx.send(reply_tx).await.is_err() {
+            // stopped meanwhile
+            return Ok(());
+        }
+        match reply_rx.await {
+            Ok(Ok(())) | Err(_) => Ok(()),
+            Ok(Err(err)) if err.raw_os_error() == Some(libc::EBUSY) => {
+                Err(mount_point_busy(mount_point, &processes_using(mount_point)))
+            }
+            Ok(Err(err)) => {
+                error!(err = %err, "Cannot unmount");
+                Err(VaultHandlerError::CannotLockVault)
+            }
+        }
+    }
+
+    async fn health(&self, vault: &Vault) -> Health {
+        let mount_point = Path::new(&vault.mount_point);
+        match find_fuse_mount(mount_point) {
+            Ok(Some(_)) if self.served.lock().unwrap().contains_key(mount_point) => Health::Mounted,
+            Ok(Some(_)) => Health::Stale,
+            Ok(None) => Health::NotMounted,
+            Err(err) => {
+                warn!(err = %err, "Cannot read mounts");
+                Health::NotMounted
+            }
+        }
+    }
+
+    #[instrument(skip(self, vault, old_password, new_password), fields(id = vault.id), err)]
+    async fn change_password(&self, vault: &Vault, old_password: &str, new_password: &str) -> Result<(), VaultHandlerError> {
+        let old_password = SecretString::from_str(old_password).unwrap();
+        let new_password = SecretString::from_str(new_password).unwrap();
+        EncryptedFs::passwd(Path::new(&vault.data_dir), old_password, new_password, CIPHER).await.map_err(|err| match err {
+            FsError::InvalidPassword => VaultHandlerError::InvalidPassword,
+            err => {
+                error!(err = %err, "Cannot change password");
+                VaultHandlerError::CannotChangePassword
+            }
+        })
+    }
+
+    /// Our mounts went away with the previous daemon, at most a stale one is left.
+    async fn reconcile(&self, vault: &Vault) -> Reconciled {
+        match self.health(vault).await {
+            Health::Stale => {
+                warn!(id = vault.id, "Stale mount");
+                cleanup_stale_mount(Path::new(&vault.mount_point)).await;
+                Reconciled::StaleMount
+            }
+            _ => Reconciled::NotMounted,
+        }
+    }
+
+    fn can_detach(&self) -> bool {
+        false
+    }
+}
+
+/// Serves until the filesystem stops on its own or we're asked to unmount it, by [InProcessEngine::unmount] or by the
+/// [InProcessMount] being killed or dropped.
+async fn serve(mut handle: MountHandle, umount_rx: oneshot::Receiver<()>, mut requests: mpsc::Receiver<UmountRequest>,
+               mount_point: PathBuf, served: Arc<Mutex<HashMap<PathBuf, mpsc::Sender<UmountRequest>>>>) -> io::Result<()> {
+    let res = tokio::select! {
+        res = &mut handle => {
+            info!(mount_point = %mount_point.display(), "Filesystem stopped");
+            res
+        }
+        _ = umount_rx => {
+            info!(mount_point = %mount_point.display(), "Unmounting");
+            handle.umount().await
+        }
+        Some(reply) = requests.recv() => {
+            info!(mount_point = %mount_point.display(), "Unmounting");
+            // a failure goes to the one who asked, like a failed fusermount would
+            let _ = reply.send(handle.umount().await);
+            Ok(())
+        }
+    };
+    served.lock().unwrap().remove(&mount_point);
+    res
+}
+
+struct InProcessMount {
+    umount_tx: Option<oneshot::Sender<()>>,
+    task: JoinHandle<io::Result<()>>,
+}
+
+#[tonic::async_trait]
+impl Mount for InProcessMount {
+    /// The exit code is 0 if the filesystem stopped cleanly, 1 if it failed.
+    async fn wait(&mut self) -> io::Result<Option<i32>> {
+        match (&mut self.task).await {
+            Ok(Ok(_)) => Ok(Some(0)),
+            Ok(Err(err)) => {
+                error!(err = %err, "Filesystem failed");
+                Ok(Some(1))
+            }
+            Err(err) => Err(io::Error::new(io::ErrorKind::Other, err)),
+        }
+    }
+
+    async fn kill(&mut self) -> io::Result<()> {
+        if let Some(tx) = self.umount_tx.take() {
+            let _ = tx.send(());
+        }
+        match (&mut self.task).await {
+            Ok(res) => res,
+            Err(err) => Err(io::Error::new(io::ErrorKind::Other, err)),
+        }
+    }
+}
+
+struct Password(String);
+
+impl PasswordProvider for Password {
+    fn get_password(&self) -> Option<SecretString> {
+        SecretString::from_str(&self.0).ok()
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use secret_service::{EncryptionType, SecretService};
+use tracing::{error, info, instrument};
+
+use crate::vault_handler::VaultHandlerError;
+
+const APPLICATION_ATTR: &str = "application";
+const APPLICATION_VALUE: &str = "rencfs_desktop";
+const VAULT_ID_ATTR: &str = "vault_id";
+
+/// Vault passwords stored with the `org.freedesktop.secrets` D-Bus API on the session bus.
+///
+/// It connects to whatever `DBUS_SESSION_BUS_ADDRESS` points to, so it can be used with a stand-in secret service.
+pub struct Keyring<'a> {
+    ss: SecretService<'a>,
+}
+
+impl<'a> Keyring<'a> {
+    pub async fn connect() -> Result<Keyring<'a>, VaultHandlerError> {
+        Self::connect_with(EncryptionType::Dh).await
+    }
+
+    /// Use [EncryptionType::Plain] for secret services that don't support session encryption.
+    pub async fn connect_with(encryption: EncryptionType) -> Result<Keyring<'a>, VaultHandlerError> {
+        let ss = SecretService::connect(encryption).await.map_err(|err| {
+            error!(err = %err, "Cannot connect to secret service");
+            VaultHandlerError::CannotAccessKeyring
+        })?;
+        Ok(Self { ss })
+    }
+
+    #[instrument(skip(self, password), err)]
+    pub async fn set_password(&self, vault_id: u32, vault_name: &str, password: &str) -> Result<(), VaultHandlerError> {
+        let id = vault_id.to_string();
+        let collection = self.ss.get_default_collection().await.map_err(Self::map_err)?;
+        if collection.is_locked().await.map_err(Self::map_err)? {
+            collection.unlock().await.map_err(Self::map_err)?;
+        }
+        collection.create_item(
+            &format!("rencfs vault {}", vault_name),
+            Self::attributes(&id),
+            password.as_bytes(),
+            true,
+            "text/plain",
+        ).await.map_err(Self::map_err)?;
+        info!("Password saved in keyring");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn get_password(&self, vault_id: u32) -> Result<Option<String>, VaultHandlerError> {
+        let id = vault_id.to_string();
+        let items = self.ss.search_items(Self::attributes(&id)).await.map_err(Self::map_err)?;
+        let item = match items.unlocked.first() {
+            Some(item) => item,
+            None => match items.locked.first() {
+                Some(item) => {
+                    item.unlock().await.map_err(Self::map_err)?;
+                    item
+                }
+                None => return Ok(None),
+            }
+        };
+        let secret = item.get_secret().await.map_err(Self::map_err)?;
+
+        String::from_utf8(secret).map(Some).map_err(|_| {
+            error!("Password in keyring is not valid UTF-8");
+            VaultHandlerError::CannotAccessKeyring
+        })
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn delete_password(&self, vault_id: u32) -> Result<(), VaultHandlerError> {
+        let id = vault_id.to_string();
+        let items = self.ss.search_items(Self::attributes(&id)).await.map_err(Self::map_err)?;
+        for item in items.unlocked.iter().chain(items.locked.iter()) {
+            item.delete().await.map_err(Self::map_err)?;
+        }
+        info!("Password removed from keyring");
+
+        Ok(())
+    }
+
+    fn attributes(vault_id: &str) -> HashMap<&str, &str> {
+        HashMap::from([(APPLICATION_ATTR, APPLICATION_VALUE), (VAULT_ID_ATTR, vault_id)])
+    }
+
+    fn map_err(err: secret_service::Error) -> VaultHandlerError {
+        error!(err = %err, "Keyring error");
+        VaultHandlerError::CannotAccessKeyring
+    }
+}
@@ -13,6 +13,28 @@ pub mod persistence;
 pub mod vault_service_error;
 pub mod vault_handler;
 pub mod storage;
+pub mod config;
+pub mod rencfs_bin;
+pub mod keyring;
+pub mod mount_info;
+pub mod supervisor;
+pub mod process_info;
+pub mod data_migration;
+pub mod progress;
+pub mod events;
+pub mod transport;
+pub mod auth;
+pub mod error_messages;
+pub mod activity;
+pub mod schedule;
+pub mod vault_state;
+pub mod countdown;
+pub mod engine;
+pub mod process_engine;
+#[cfg(any(test, feature = "fake-engine"))]
+pub mod fake_engine;
+#[cfg(feature = "in-process")]
+pub mod in_process_engine;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
@@ -50,10 +72,6 @@ pub async fn execute_catch_unwind<F: FnOnce() -> R + UnwindSafe, R>(f: F) {
     }
 }
 
-#[allow(unreachable_code)]
 pub fn is_debug() -> bool {
-    #[cfg(debug_assertions)] {
-        return true;
-    }
-    return false;
+    cfg!(debug_assertions)
 }
\ No newline at end of file
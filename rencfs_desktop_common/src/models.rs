@@ -1,6 +1,6 @@
 use diesel::prelude::*;
 
-#[derive(Queryable, Selectable, Debug)]
+#[derive(Queryable, Selectable, Debug, Clone)]
 #[diesel(table_name = crate::schema::vaults)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
 pub struct Vault {
@@ -8,7 +8,17 @@ pub struct Vault {
     pub name: String,
     pub mount_point: String,
     pub data_dir: String,
-    pub locked: i32,
+    pub remember_password: i32,
+    pub restart_policy: String,
+    pub last_exit_status: Option<i32>,
+    /// lock after this many minutes without use, 0 to never lock
+    pub idle_timeout_mins: i32,
+    /// lock when the session is locked, the system sleeps or shuts down
+    pub lock_with_session: i32,
+    /// unix time in seconds when a time-limited unlock ends
+    pub lock_at: Option<i64>,
+    /// see [crate::vault_state::VaultState], use [Vault::state] to read it
+    pub state: String,
 }
 
 #[derive(Insertable, Debug)]
@@ -18,4 +28,61 @@ pub struct NewVault {
     pub name: String,
     pub mount_point: String,
     pub data_dir: String,
+    pub remember_password: i32,
+    pub restart_policy: String,
+    pub idle_timeout_mins: i32,
+    pub lock_with_session: i32,
+}
+
+/// Fields left `None` are not changed.
+#[derive(AsChangeset, Debug, Default)]
+#[diesel(table_name = crate::schema::vaults)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct UpdateVault {
+    pub name: Option<String>,
+    pub remember_password: Option<i32>,
+    pub restart_policy: Option<String>,
+    pub idle_timeout_mins: Option<i32>,
+    pub lock_with_session: Option<i32>,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::schedules)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Schedule {
+    pub id: i32,
+    pub vault_id: i32,
+    /// lock or unlock, see [crate::schedule::ScheduleAction]
+    pub action: String,
+    /// local time, minutes since midnight
+    pub minute_of_day: i32,
+    /// weekdays it runs on, bit 0 is Monday and bit 6 is Sunday
+    pub days: i32,
+    pub enabled: i32,
+    /// unix time in seconds of the last run, or when it was created or changed, so it doesn't run for times before that
+    pub last_run: Option<i64>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::schema::schedules)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct NewSchedule {
+    pub vault_id: i32,
+    pub action: String,
+    pub minute_of_day: i32,
+    pub days: i32,
+    pub enabled: i32,
+    pub last_run: Option<i64>,
+}
+
+/// Fields left `None` are not changed.
+#[derive(AsChangeset, Debug, Default)]
+#[diesel(table_name = crate::schema::schedules)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct UpdateSchedule {
+    pub action: Option<String>,
+    pub minute_of_day: Option<i32>,
+    pub days: Option<i32>,
+    pub enabled: Option<i32>,
+    pub last_run: Option<i64>,
 }
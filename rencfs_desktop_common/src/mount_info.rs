@@ -0,0 +1,285 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use tokio::process::{Child, Command};
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::process_info;
+use crate::progress::ProgressReporter;
+use crate::vault_handler::VaultHandlerError;
+
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// One line from `/proc/self/mountinfo`, only the fields we need.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MountEntry {
+    pub mount_id: u32,
+    /// `major:minor` of the device
+    pub dev: String,
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    pub source: String,
+}
+
+impl MountEntry {
+    pub fn is_fuse(&self) -> bool {
+        self.fs_type == "fuse" || self.fs_type.starts_with("fuse.")
+    }
+
+    /// Mount IDs are reused after unmount, so it's the same mount only if the device is the same too.
+    pub fn is_same_mount(&self, other: &MountEntry) -> bool {
+        self.mount_id == other.mount_id && self.dev == other.dev
+    }
+}
+
+pub fn read_mounts() -> std::io::Result<Vec<MountEntry>> {
+    Ok(fs::read_to_string(MOUNTINFO)?.lines().filter_map(parse_line).collect())
+}
+
+/// Returns the FUSE mount at `mount_point`, if any.
+pub fn find_fuse_mount(mount_point: &Path) -> std::io::Result<Option<MountEntry>> {
+    Ok(read_mounts()?.into_iter().find(|m| m.is_fuse() && m.mount_point == mount_point))
+}
+
+/// Returns the mount on top at `mount_point`, the one that's visible there, if anything is mounted there.
+pub fn mount_at(mount_point: &Path) -> std::io::Result<Option<MountEntry>> {
+    // mounts over the same point come later in the file
+    Ok(read_mounts()?.into_iter().filter(|m| m.mount_point == mount_point).last())
+}
+
+/// Checks if the process has `/dev/fuse` open, which is the case for the one serving a FUSE mount.
+pub fn holds_fuse_device(pid: u32) -> bool {
+    fs::read_dir(format!("/proc/{}/fd", pid))
+        .map(|entries| entries.flatten()
+            .any(|e| fs::read_link(e.path()).map(|p| p == Path::new("/dev/fuse")).unwrap_or(false)))
+        .unwrap_or(false)
+}
+
+/// Waits until a FUSE mount served by `child` shows up at `mount_point`.
+///
+/// `previous` is what was mounted there before spawning it, from [mount_at], so a mount that was already there isn't
+/// taken for the new one.
+///
+/// Returns [VaultHandlerError::ProcessExited] as soon as the child exits and [VaultHandlerError::UnlockTimeout] after `timeout`.
+#[instrument(skip(child, progress), err)]
+pub async fn wait_for_mount(child: &mut Child, mount_point: &Path, previous: Option<&MountEntry>, timeout: Duration,
+                            progress: &ProgressReporter) -> Result<(), VaultHandlerError> {
+    let start = Instant::now();
+    loop {
+        if progress.is_cancelled() {
+            return Err(VaultHandlerError::Cancelled);
+        }
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                error!(%status, "Child process exited before mounting");
+                return Err(VaultHandlerError::ProcessExited { cause: status.to_string() });
+            }
+            Ok(None) => {}
+            Err(err) => {
+                error!(err = %err, "Cannot check child process status");
+                return Err(VaultHandlerError::CannotUnlockVault);
+            }
+        }
+        let pid = child.id().ok_or_else(|| VaultHandlerError::ProcessExited { cause: "process exited".to_string() })?;
+        match mount_at(mount_point) {
+            Ok(Some(mount)) if mount.is_fuse() && !previous.is_some_and(|p| p.is_same_mount(&mount)) && holds_fuse_device(pid) => {
+                debug!(?mount, elapsed = ?start.elapsed(), "Mount is ready");
+                return Ok(());
+            }
+            Ok(_) => {}
+            Err(err) => {
+                error!(err = %err, "Cannot read {}", MOUNTINFO);
+                return Err(VaultHandlerError::CannotUnlockVault);
+            }
+        }
+        if start.elapsed() >= timeout {
+            error!(?timeout, "Mount didn't show up in time");
+            return Err(VaultHandlerError::UnlockTimeout { secs: timeout.as_secs() });
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Lazily unmounts a FUSE mount left behind by a process that died, otherwise the mount point is a dead endpoint.
+#[instrument]
+pub async fn cleanup_stale_mount(mount_point: &Path) {
+    match find_fuse_mount(mount_point) {
+        Ok(Some(_)) => {}
+        Ok(None) => return,
+        Err(err) => {
+            warn!(err = %err, "Cannot read {}", MOUNTINFO);
+            return;
+        }
+    }
+    info!("Unmounting stale mount");
+    match Command::new("fusermount").arg("-u").arg("-z").arg(mount_point).output().await {
+        Ok(out) if out.status.success() => {}
+        Ok(out) => error!(stderr = %String::from_utf8_lossy(&out.stderr), "Cannot unmount stale mount"),
+        Err(err) => error!(err = %err, "Cannot run fusermount"),
+    }
+}
+
+/// Cleanly unmounts a FUSE mount, the process serving it then exits on its own.
+///
+/// Fails with [VaultHandlerError::MountPointBusy] if files are open there, telling which processes have them open.
+#[instrument(err)]
+pub async fn umount(mount_point: &Path) -> Result<(), VaultHandlerError> {
+    // TODO: umount for windows
+    if !cfg!(any(linux, unix, macos, freebsd, openbsd, netbsd)) {
+        return Ok(());
+    }
+    // its messages are translated otherwise
+    let out = match Command::new("fusermount").env("LC_ALL", "C").arg("-u").arg(mount_point).output().await {
+        Ok(out) => out,
+        Err(err) => {
+            error!(err = %err, "Cannot run fusermount");
+            return Err(VaultHandlerError::CannotLockVault);
+        }
+    };
+    if out.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let processes = process_info::processes_using(mount_point);
+    if stderr.contains("busy") || !processes.is_empty() {
+        return Err(mount_point_busy(mount_point, &processes));
+    }
+    // it also fails if it was not mounted anymore, which is what we wanted
+    if let Ok(None) = find_fuse_mount(mount_point) {
+        return Ok(());
+    }
+    error!(%stderr, "Cannot unmount");
+    Err(VaultHandlerError::CannotLockVault)
+}
+
+/// [VaultHandlerError::MountPointBusy] telling which of `pids` have files open there.
+pub fn mount_point_busy(mount_point: &Path, pids: &[u32]) -> VaultHandlerError {
+    let processes = process_info::describe(pids);
+    error!(processes, "Cannot unmount, mount point busy");
+    VaultHandlerError::MountPointBusy { mount_point: mount_point.to_string_lossy().to_string(), processes }
+}
+
+/// rencfs would mount over the files and hide them.
+pub fn ensure_mount_point_empty(mount_point: &str) -> Result<(), VaultHandlerError> {
+    if fs::read_dir(mount_point).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(VaultHandlerError::MountPointNotEmpty { mount_point: mount_point.to_string() });
+    }
+    Ok(())
+}
+
+// format is described in `man 5 proc`, section /proc/pid/mountinfo
+// 36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+fn parse_line(line: &str) -> Option<MountEntry> {
+    let (before, after) = line.split_once(" - ")?;
+    let mut before = before.split(' ');
+    let mount_id = before.next()?.parse().ok()?;
+    let dev = before.nth(1)?;
+    let mount_point = before.nth(1)?;
+    let mut after = after.split(' ');
+    let fs_type = after.next()?;
+    let source = after.next().unwrap_or("");
+
+    Some(MountEntry {
+        mount_id,
+        dev: dev.to_string(),
+        mount_point: PathBuf::from(unescape(mount_point)),
+        fs_type: fs_type.to_string(),
+        source: unescape(source),
+    })
+}
+
+// space, tab, newline and backslash are escaped as octal, like `\040`
+fn unescape(s: &str) -> String {
+    let mut out = Vec::with_capacity(s.len());
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 4 <= bytes.len() && bytes[i + 1..i + 4].iter().all(|b| (b'0'..=b'7').contains(b)) {
+            let v = (bytes[i + 1] - b'0') as u32 * 64 + (bytes[i + 2] - b'0') as u32 * 8 + (bytes[i + 3] - b'0') as u32;
+            out.push(v as u8);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_line_reads_the_fields() {
+        let entry = parse_line("36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue").unwrap();
+        assert_eq!(entry, MountEntry {
+            mount_id: 36,
+            dev: "98:0".to_string(),
+            mount_point: PathBuf::from("/mnt2"),
+            fs_type: "ext3".to_string(),
+            source: "/dev/root".to_string(),
+        });
+        assert!(!entry.is_fuse());
+    }
+
+    #[test]
+    fn parse_line_without_optional_fields() {
+        let entry = parse_line("812 29 0:64 / /home/me/vault rw,nosuid,nodev - fuse.rencfs rencfs rw,user_id=1000").unwrap();
+        assert_eq!(entry.mount_id, 812);
+        assert_eq!(entry.dev, "0:64");
+        assert_eq!(entry.mount_point, PathBuf::from("/home/me/vault"));
+        assert!(entry.is_fuse());
+    }
+
+    #[test]
+    fn parse_line_with_several_optional_fields() {
+        let entry = parse_line("40 1 0:33 / /mnt rw shared:7 master:2 propagate_from:1 - fuse /dev/fuse rw").unwrap();
+        assert_eq!(entry.mount_point, PathBuf::from("/mnt"));
+        assert_eq!(entry.fs_type, "fuse");
+        assert!(entry.is_fuse());
+    }
+
+    #[test]
+    fn parse_line_unescapes_paths() {
+        let entry = parse_line(r"50 29 0:70 / /home/me/my\040vault rw - fuse.rencfs my\134source rw").unwrap();
+        assert_eq!(entry.mount_point, PathBuf::from("/home/me/my vault"));
+        assert_eq!(entry.source, r"my\source");
+    }
+
+    #[test]
+    fn parse_line_rejects_malformed_lines() {
+        assert!(parse_line("").is_none());
+        assert!(parse_line("36 35 98:0 /mnt1 /mnt2 rw").is_none());
+        assert!(parse_line("x 35 98:0 /mnt1 /mnt2 rw - ext3 /dev/root rw").is_none());
+        assert!(parse_line("36 35 98:0 - ext3 /dev/root rw").is_none());
+    }
+
+    #[test]
+    fn unescape_octal() {
+        assert_eq!(unescape(r"a\040b"), "a b");
+        assert_eq!(unescape(r"\011tab\012"), "\ttab\n");
+        assert_eq!(unescape(r"back\134slash"), r"back\slash");
+        assert_eq!(unescape(r"end\040"), "end ");
+    }
+
+    #[test]
+    fn unescape_leaves_other_backslashes() {
+        assert_eq!(unescape("plain"), "plain");
+        assert_eq!(unescape(r"a\b"), r"a\b");
+        assert_eq!(unescape(r"a\08"), r"a\08");
+        assert_eq!(unescape(r"short\04"), r"short\04");
+        assert_eq!(unescape("\\"), "\\");
+    }
+
+    #[test]
+    fn same_mount_needs_same_id_and_device() {
+        let entry = parse_line("812 29 0:64 / /mnt rw - fuse.rencfs rencfs rw").unwrap();
+        assert!(entry.is_same_mount(&entry.clone()));
+        // the id was reused by a new mount
+        assert!(!entry.is_same_mount(&parse_line("812 29 0:65 / /mnt rw - fuse.rencfs rencfs rw").unwrap()));
+        assert!(!entry.is_same_mount(&parse_line("813 29 0:64 / /mnt rw - fuse.rencfs rencfs rw").unwrap()));
+    }
+}
@@ -0,0 +1,294 @@
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use tokio::process::{Child, Command};
+use tracing::{error, info, instrument, warn};
+
+use crate::config::Config;
+use crate::engine::{Health, Mount, Reconciled, VaultEngine};
+use crate::models::Vault;
+use crate::mount_info::{cleanup_stale_mount, ensure_mount_point_empty, find_fuse_mount, holds_fuse_device, mount_at, umount, wait_for_mount};
+use crate::process_info;
+use crate::progress::ProgressReporter;
+use crate::rencfs_bin::resolve_rencfs_bin;
+use crate::storage::get_logs_dir;
+use crate::vault_handler::VaultHandlerError;
+
+const PASSWORD_ENV: &str = "ENCRYPTEDFS_PASSWORD";
+const NEW_PASSWORD_ENV: &str = "ENCRYPTEDFS_NEW_PASSWORD";
+const ADOPTED_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const ADOPTED_KILL_GRACE: Duration = Duration::from_secs(5);
+
+/// Mounts each vault with its own `rencfs` process.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ProcessEngine;
+
+#[tonic::async_trait]
+impl VaultEngine for ProcessEngine {
+    async fn mount(&self, vault: &Vault, password: &str, progress: &ProgressReporter) -> Result<Box<dyn Mount>, VaultHandlerError> {
+        let child = start_rencfs(vault, password, progress).await?;
+        Ok(Box::new(ProcessMount { child }))
+    }
+
+    #[instrument(skip(self), err)]
+    async fn unmount(&self, mount_point: &Path, lazy: bool) -> Result<(), VaultHandlerError> {
+        if lazy {
+            cleanup_stale_mount(mount_point).await;
+            return Ok(());
+        }
+        umount(mount_point).await
+    }
+
+    async fn health(&self, vault: &Vault) -> Health {
+        let mount_point = Path::new(&vault.mount_point);
+        match find_fuse_mount(mount_point) {
+            Ok(Some(_)) => {}
+            Ok(None) => return Health::NotMounted,
+            Err(err) => {
+                warn!(err = %err, "Cannot read mounts");
+                return Health::NotMounted;
+            }
+        }
+        let served = process_info::find_rencfs_processes().iter()
+            .any(|p| p.mount_point == mount_point && holds_fuse_device(p.pid));
+        if served { Health::Mounted } else { Health::Stale }
+    }
+
+    #[instrument(skip(self, vault, old_password, new_password), fields(id = vault.id), err)]
+    async fn change_password(&self, vault: &Vault, old_password: &str, new_password: &str) -> Result<(), VaultHandlerError> {
+        let rencfs_bin = resolve_rencfs_bin().await?;
+        let mut command = Command::new(rencfs_bin);
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .arg("--change-password")
+            .arg("--data-dir")
+            .arg(&vault.data_dir);
+        let child = spawn_with_passwords(command, &[(PASSWORD_ENV, old_password), (NEW_PASSWORD_ENV, new_password)]);
+        let child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                error!(err = %err, "Cannot start process");
+                return Err(VaultHandlerError::CannotChangePassword);
+            }
+        };
+        let out = child.wait_with_output().await.map_err(|err| {
+            error!(err = %err, "Cannot wait for child process");
+            VaultHandlerError::CannotChangePassword
+        })?;
+        if out.status.success() {
+            return Ok(());
+        }
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        error!(status = %out.status, %stderr, "Cannot change password");
+        if is_invalid_password(&stderr) {
+            Err(VaultHandlerError::InvalidPassword)
+        } else {
+            Err(VaultHandlerError::CannotChangePassword)
+        }
+    }
+
+    async fn reconcile(&self, vault: &Vault) -> Reconciled {
+        let mount_point = Path::new(&vault.mount_point);
+        let mounted = match find_fuse_mount(mount_point) {
+            Ok(mount) => mount.is_some(),
+            Err(err) => {
+                error!(err = %err, "Cannot read mounts");
+                false
+            }
+        };
+        let process = process_info::find_rencfs_processes().into_iter()
+            .find(|p| p.mount_point == mount_point && p.data_dir == Path::new(&vault.data_dir));
+
+        match (process, mounted) {
+            (Some(process), true) => {
+                info!(id = vault.id, pid = process.pid, "Adopting rencfs process");
+                Reconciled::Adopted(Box::new(AdoptedProcess { pid: process.pid }))
+            }
+            (Some(process), false) => {
+                warn!(id = vault.id, pid = process.pid, "rencfs running but vault not mounted, killing it");
+                if let Err(err) = process_info::kill(process.pid, libc::SIGKILL) {
+                    error!(err = %err, "Cannot kill process");
+                }
+                Reconciled::Stopped
+            }
+            (None, true) => {
+                warn!(id = vault.id, "Stale mount");
+                cleanup_stale_mount(mount_point).await;
+                Reconciled::StaleMount
+            }
+            (None, false) => Reconciled::NotMounted,
+        }
+    }
+
+    /// rencfs processes of the current user not matching any vault.
+    fn orphans(&self, vaults: &[Vault]) -> Vec<String> {
+        process_info::find_rencfs_processes().into_iter()
+            .filter(|p| !vaults.iter().any(|v| p.mount_point == Path::new(&v.mount_point) && p.data_dir == Path::new(&v.data_dir)))
+            .map(|p| format!("pid {} at {}", p.pid, p.mount_point.display()))
+            .collect()
+    }
+
+    fn can_detach(&self) -> bool {
+        true
+    }
+}
+
+/// A rencfs process we started.
+struct ProcessMount {
+    child: Child,
+}
+
+#[tonic::async_trait]
+impl Mount for ProcessMount {
+    async fn wait(&mut self) -> std::io::Result<Option<i32>> {
+        self.child.wait().await.map(|status| Some(exit_code(&status)))
+    }
+
+    async fn kill(&mut self) -> std::io::Result<()> {
+        self.child.kill().await
+    }
+}
+
+/// A rencfs process started by a previous daemon run, it's not our child so we can't wait on it, we poll instead.
+struct AdoptedProcess {
+    pid: u32,
+}
+
+#[tonic::async_trait]
+impl Mount for AdoptedProcess {
+    async fn wait(&mut self) -> std::io::Result<Option<i32>> {
+        while process_info::is_alive(self.pid) {
+            tokio::time::sleep(ADOPTED_POLL_INTERVAL).await;
+        }
+        // exit status is only available to the parent
+        Ok(None)
+    }
+
+    async fn kill(&mut self) -> std::io::Result<()> {
+        process_info::kill(self.pid, libc::SIGTERM)?;
+        let start = Instant::now();
+        while process_info::is_alive(self.pid) {
+            if start.elapsed() >= ADOPTED_KILL_GRACE {
+                warn!(pid = self.pid, "Adopted process didn't exit, sending SIGKILL");
+                return process_info::kill(self.pid, libc::SIGKILL);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Ok(())
+    }
+}
+
+/// For processes killed by a signal we use the negated signal number.
+fn exit_code(status: &ExitStatus) -> i32 {
+    status.code().or_else(|| status.signal().map(|s| -s)).unwrap_or(-1)
+}
+
+/// Spawns rencfs for the vault and waits until it's mounted.
+#[instrument(skip(vault, password, progress), fields(id = vault.id), err)]
+async fn start_rencfs(vault: &Vault, password: &str, progress: &ProgressReporter) -> Result<Child, VaultHandlerError> {
+    progress.set_phase("starting", 0.0);
+
+    ensure_mount_point_empty(&vault.mount_point)?;
+
+    // create logs files
+    let logs_dir = get_logs_dir();
+    let stdout = OpenOptions::new().append(true).create(true).open(logs_dir.join(format!("vault_{}.out", vault.id))).expect("Cannot create stdout file");
+    let stderr_path = logs_dir.join(format!("vault_{}.err", vault.id));
+    let stderr = OpenOptions::new().append(true).create(true).open(&stderr_path).expect("Cannot create stderr file");
+    // remember where this run's output starts so we can look for errors only from it
+    let stderr_offset = stderr.metadata().map(|m| m.len()).unwrap_or(0);
+
+    // what's mounted there now, so we wait for the new mount and not take this one for it
+    let previous = match mount_at(Path::new(&vault.mount_point)) {
+        Ok(previous) => previous,
+        Err(err) => {
+            error!(err = %err, "Cannot read mounts");
+            return Err(VaultHandlerError::CannotUnlockVault);
+        }
+    };
+
+    // spawn new process
+    let rencfs_bin = resolve_rencfs_bin().await?;
+    let mut command = Command::new(rencfs_bin);
+    command
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr)
+        .arg("--mount-point")
+        .arg(&vault.mount_point)
+        .arg("--data-dir")
+        .arg(&vault.data_dir)
+        .arg("--umount-on-start");
+    let mut child = match spawn_with_passwords(command, &[(PASSWORD_ENV, password)]) {
+        Ok(child) => child,
+        Err(err) => {
+            error!(err = %err, "Cannot start process");
+            return Err(VaultHandlerError::CannotUnlockVault);
+        }
+    };
+
+    // wait for the mount to show up, or the child to fail
+    progress.set_phase("mounting", 50.0);
+    let timeout = Duration::from_secs(Config::load().unlock_timeout_secs);
+    if let Err(err) = wait_for_mount(&mut child, Path::new(&vault.mount_point), previous.as_ref(), timeout, progress).await {
+        let _ = child.kill().await;
+        return Err(match err {
+            VaultHandlerError::ProcessExited { cause } => unlock_error(&stderr_path, stderr_offset, cause),
+            err => err,
+        });
+    }
+    progress.set_phase("mounted", 100.0);
+
+    Ok(child)
+}
+
+/// rencfs reads passwords from the tty, which the daemon doesn't have, or from the environment, so they are passed there.
+///
+/// They are set only for the child and removed from `command` right after spawning, our own environment is never touched.
+/// The child's environment can still be read from `/proc/<pid>/environ`, but only by the same user, who can read its memory anyway.
+fn spawn_with_passwords(mut command: Command, passwords: &[(&str, &str)]) -> std::io::Result<Child> {
+    for (key, password) in passwords {
+        command.env(key, password);
+    }
+    let child = command.spawn();
+    for (key, _) in passwords {
+        command.env_remove(key);
+    }
+    child
+}
+
+/// rencfs exits with 1 for every error, the wrong password can only be told apart by its message.
+fn is_invalid_password(stderr: &str) -> bool {
+    stderr.to_lowercase().contains("invalid password")
+}
+
+/// Tells apart a wrong password from other failures by looking at what the child wrote to stderr.
+///
+/// For other failures the last line from stderr is the cause, if any, otherwise `cause`.
+fn unlock_error(stderr_path: &Path, offset: u64, cause: String) -> VaultHandlerError {
+    let mut out = String::new();
+    let read = fs::File::open(stderr_path)
+        .and_then(|mut f| {
+            f.seek(SeekFrom::Start(offset))?;
+            f.read_to_string(&mut out)
+        });
+    if let Err(err) = read {
+        warn!(err = %err, "Cannot read child process stderr");
+    }
+    if is_invalid_password(&out) {
+        VaultHandlerError::InvalidPassword
+    } else {
+        let cause = out.lines().rev()
+            .map(str::trim)
+            .find(|l| !l.is_empty())
+            .map_or(cause, str::to_string);
+        VaultHandlerError::ProcessExited { cause }
+    }
+}
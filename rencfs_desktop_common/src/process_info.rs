@@ -0,0 +1,107 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+const RENCFS_BIN_NAME: &str = "rencfs";
+
+/// A running rencfs process found in `/proc`, not necessarily started by us.
+#[derive(Debug, Clone)]
+pub struct RencfsProcess {
+    pub pid: u32,
+    pub mount_point: PathBuf,
+    pub data_dir: PathBuf,
+}
+
+/// Scans `/proc` for rencfs processes of the current user and reads their mount point and data dir from the args.
+pub fn find_rencfs_processes() -> Vec<RencfsProcess> {
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!(err = %err, "Cannot read /proc");
+            return vec![];
+        }
+    };
+    entries.flatten()
+        .filter_map(|e| e.file_name().to_str().and_then(|s| s.parse::<u32>().ok()))
+        .filter(|pid| is_owned_by_current_user(*pid) && is_alive(*pid))
+        .filter_map(|pid| {
+            let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+            let args: Vec<String> = cmdline.split(|b| *b == 0)
+                .map(|a| String::from_utf8_lossy(a).to_string())
+                .collect();
+            if Path::new(args.first()?).file_name()? != RENCFS_BIN_NAME {
+                return None;
+            }
+            Some(RencfsProcess {
+                pid,
+                mount_point: PathBuf::from(arg_value(&args, "--mount-point")?),
+                data_dir: PathBuf::from(arg_value(&args, "--data-dir")?),
+            })
+        })
+        .collect()
+}
+
+/// `false` if the process doesn't exist or is a zombie.
+pub fn is_alive(pid: u32) -> bool {
+    // third field in stat is the state, the second one, the command name, is in parentheses and could contain spaces
+    match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => stat.rsplit_once(')')
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .is_some_and(|state| state != "Z" && state != "X"),
+        Err(_) => false,
+    }
+}
+
+/// Processes of the current user with their cwd or an open file under `path`.
+pub fn processes_using(path: &Path) -> Vec<u32> {
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!(err = %err, "Cannot read /proc");
+            return vec![];
+        }
+    };
+    let own_pid = std::process::id();
+    entries.flatten()
+        .filter_map(|e| e.file_name().to_str().and_then(|s| s.parse::<u32>().ok()))
+        .filter(|pid| *pid != own_pid && is_owned_by_current_user(*pid))
+        .filter(|pid| {
+            let cwd = fs::read_link(format!("/proc/{}/cwd", pid)).is_ok_and(|p| p.starts_with(path));
+            cwd || fs::read_dir(format!("/proc/{}/fd", pid))
+                .map(|fds| fds.flatten().any(|fd| fs::read_link(fd.path()).is_ok_and(|p| p.starts_with(path))))
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+/// Like `vim (1234), bash (1240)`, for telling the user what to close.
+pub fn describe(pids: &[u32]) -> String {
+    pids.iter()
+        .map(|pid| {
+            let name = fs::read_to_string(format!("/proc/{}/comm", pid)).map_or_else(|_| "?".to_string(), |s| s.trim().to_string());
+            format!("{} ({})", name, pid)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn is_owned_by_current_user(pid: u32) -> bool {
+    let uid = unsafe { libc::getuid() };
+    fs::metadata(format!("/proc/{}", pid)).is_ok_and(|m| m.uid() == uid)
+}
+
+pub fn kill(pid: u32, signal: libc::c_int) -> std::io::Result<()> {
+    if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter().position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str())
+}
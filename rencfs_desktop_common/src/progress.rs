@@ -0,0 +1,91 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    pub phase: String,
+    /// 0..=100
+    pub percent: f32,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    /// only known for phases that report bytes
+    pub eta: Option<Duration>,
+}
+
+/// Passed to long-running [VaultHandler](crate::vault_handler::VaultHandler) operations to report progress and to check
+/// if they were cancelled.
+///
+/// Can be used from blocking code too.
+#[derive(Clone)]
+pub struct ProgressReporter {
+    tx: Option<Arc<watch::Sender<Progress>>>,
+    cancelled: Arc<AtomicBool>,
+    phase_started: Arc<std::sync::Mutex<Instant>>,
+}
+
+impl ProgressReporter {
+    pub fn new() -> (Self, watch::Receiver<Progress>) {
+        let (tx, rx) = watch::channel(Progress::default());
+        (Self {
+            tx: Some(Arc::new(tx)),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            phase_started: Arc::new(std::sync::Mutex::new(Instant::now())),
+        }, rx)
+    }
+
+    /// For callers not interested in progress.
+    pub fn noop() -> Self {
+        Self {
+            tx: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            phase_started: Arc::new(std::sync::Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn set_phase(&self, phase: &str, percent: f32) {
+        *self.phase_started.lock().unwrap() = Instant::now();
+        self.modify(|p| {
+            p.phase = phase.to_string();
+            p.percent = percent;
+            p.bytes_done = 0;
+            p.bytes_total = 0;
+            p.eta = None;
+        });
+    }
+
+    /// After this the percent is computed from bytes.
+    pub fn set_bytes_total(&self, total: u64) {
+        self.modify(|p| p.bytes_total = total);
+    }
+
+    pub fn add_bytes(&self, n: u64) {
+        let elapsed = self.phase_started.lock().unwrap().elapsed();
+        self.modify(|p| {
+            p.bytes_done = (p.bytes_done + n).min(p.bytes_total);
+            if p.bytes_total > 0 {
+                p.percent = p.bytes_done as f32 * 100.0 / p.bytes_total as f32;
+            }
+            if p.bytes_done > 0 {
+                let remaining = (p.bytes_total - p.bytes_done) as f64 / p.bytes_done as f64;
+                p.eta = Some(elapsed.mul_f64(remaining));
+            }
+        });
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn modify(&self, f: impl FnOnce(&mut Progress)) {
+        if let Some(tx) = &self.tx {
+            tx.send_modify(f);
+        }
+    }
+}
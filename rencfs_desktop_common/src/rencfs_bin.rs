@@ -0,0 +1,215 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use tracing::{debug, error, info, instrument};
+
+use crate::config::Config;
+use crate::vault_handler::VaultHandlerError;
+
+pub const RENCFS_BIN_ENV: &str = "RENCFS_BIN";
+const RENCFS_BIN_NAME: &str = "rencfs";
+
+/// Lowest `rencfs` version accepted, inclusive.
+pub const MIN_RENCFS_VERSION: (u32, u32, u32) = (0, 5, 0);
+/// First `rencfs` version not accepted anymore, exclusive.
+pub const MAX_RENCFS_VERSION: (u32, u32, u32) = (1, 0, 0);
+
+/// Finds the `rencfs` binary and checks it's a compatible version.
+///
+/// Lookup order is: `rencfs_bin` from [Config], `RENCFS_BIN` env var, `$PATH`, next to the current executable.
+#[instrument(err)]
+pub async fn resolve_rencfs_bin() -> Result<PathBuf, VaultHandlerError> {
+    let path = find_rencfs_bin(&Config::load()).ok_or_else(|| {
+        error!("Cannot find rencfs binary");
+        VaultHandlerError::RencfsBinaryNotFound
+    })?;
+    info!(path = %path.display(), "Found rencfs binary");
+    check_version(&path).await?;
+
+    Ok(path)
+}
+
+fn find_rencfs_bin(config: &Config) -> Option<PathBuf> {
+    let exe_dir = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+    find_rencfs_bin_in(config.rencfs_bin.as_deref(), env::var_os(RENCFS_BIN_ENV), env::var_os("PATH"), exe_dir)
+}
+
+/// [find_rencfs_bin] with the environment passed in.
+fn find_rencfs_bin_in(config_bin: Option<&Path>, env_bin: Option<OsString>, paths: Option<OsString>,
+                      exe_dir: Option<PathBuf>) -> Option<PathBuf> {
+    if let Some(path) = config_bin {
+        // when set explicitly we don't fallback to other locations, it would hide a wrong config
+        return Some(path.to_path_buf());
+    }
+    if let Some(path) = env_bin {
+        return Some(PathBuf::from(path));
+    }
+    if let Some(paths) = paths {
+        if let Some(path) = env::split_paths(&paths)
+            .map(|dir| dir.join(RENCFS_BIN_NAME))
+            .find(|path| is_executable(path)) {
+            return Some(path);
+        }
+    }
+    exe_dir
+        .map(|dir| dir.join(RENCFS_BIN_NAME))
+        .filter(|path| is_executable(path))
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)] {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata().map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0).unwrap_or(false)
+    }
+    #[cfg(not(unix))] {
+        path.is_file()
+    }
+}
+
+#[instrument(err)]
+async fn check_version(path: &Path) -> Result<(), VaultHandlerError> {
+    let out = Command::new(path)
+        .arg("--version")
+        .output().await
+        .map_err(|err| {
+            error!(err = %err, "Cannot run rencfs binary");
+            VaultHandlerError::RencfsBinaryNotFound
+        })?;
+    let out = String::from_utf8_lossy(&out.stdout);
+    debug!(out = %out.trim(), "rencfs --version");
+
+    let required = format_version(MIN_RENCFS_VERSION, MAX_RENCFS_VERSION);
+    // output looks like `rencfs 0.5.0`
+    let version = out.split_whitespace().last().and_then(parse_version);
+    match version {
+        Some(version) if version >= MIN_RENCFS_VERSION && version < MAX_RENCFS_VERSION => Ok(()),
+        _ => {
+            error!(found = %out.trim(), required, "Incompatible rencfs version");
+            Err(VaultHandlerError::IncompatibleRencfsVersion { found: out.trim().to_string(), required })
+        }
+    }
+}
+
+fn parse_version(s: &str) -> Option<(u32, u32, u32)> {
+    let s = s.trim_start_matches('v');
+    // ignore pre-release and build metadata
+    let s = s.split(|c| c == '-' || c == '+').next()?;
+    let mut parts = s.split('.').map(|p| p.parse::<u32>());
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some((major, minor, patch))
+}
+
+fn format_version(min: (u32, u32, u32), max: (u32, u32, u32)) -> String {
+    format!(">={}.{}.{}, <{}.{}.{}", min.0, min.1, min.2, max.0, max.1, max.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// A `rencfs` in `dir` printing `version` for `--version`.
+    fn stub(dir: &Path, version: &str) -> PathBuf {
+        let path = dir.join(RENCFS_BIN_NAME);
+        fs::write(&path, format!("#!/bin/sh\necho '{}'\n", version)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn dirs(n: usize) -> Vec<TempDir> {
+        (0..n).map(|_| TempDir::new().unwrap()).collect()
+    }
+
+    #[test]
+    fn parse_versions() {
+        assert_eq!(parse_version("0.5.0"), Some((0, 5, 0)));
+        assert_eq!(parse_version("v1.2.3"), Some((1, 2, 3)));
+        assert_eq!(parse_version("0.13"), Some((0, 13, 0)));
+        assert_eq!(parse_version("2"), Some((2, 0, 0)));
+        assert_eq!(parse_version("0.6.0-beta.1"), Some((0, 6, 0)));
+        assert_eq!(parse_version("0.6.0+build.5"), Some((0, 6, 0)));
+        assert_eq!(parse_version("rencfs"), None);
+        assert_eq!(parse_version("0.x.1"), None);
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn format_bounds() {
+        assert_eq!(format_version(MIN_RENCFS_VERSION, MAX_RENCFS_VERSION), ">=0.5.0, <1.0.0");
+    }
+
+    // all in one test, writing a script while another test spawns a process can make running it fail with ETXTBSY
+    #[tokio::test]
+    async fn check_version_bounds() {
+        let cases = [
+            ("rencfs 0.5.0", true),
+            ("rencfs 0.13.2", true),
+            ("rencfs 0.99.99", true),
+            ("rencfs 0.4.9", false),
+            ("rencfs 1.0.0", false),
+            ("rencfs 1.0.0-rc.1", false),
+            ("rencfs", false),
+            ("", false),
+        ];
+        let dirs = dirs(cases.len());
+        let bins: Vec<_> = cases.iter().zip(&dirs).map(|((out, _), dir)| stub(dir.path(), out)).collect();
+        for ((out, ok), bin) in cases.iter().zip(&bins) {
+            let res = check_version(bin).await;
+            assert_eq!(res.is_ok(), *ok, "{:?}: {:?}", out, res);
+            if !ok {
+                assert_eq!(res, Err(VaultHandlerError::IncompatibleRencfsVersion {
+                    found: out.to_string(),
+                    required: ">=0.5.0, <1.0.0".to_string(),
+                }));
+            }
+        }
+
+        let missing = dirs[0].path().join("missing");
+        assert_eq!(check_version(&missing).await, Err(VaultHandlerError::RencfsBinaryNotFound));
+    }
+
+    #[test]
+    fn resolution_order() {
+        let dirs = dirs(4);
+        let [config, env_dir, path_dir, exe_dir] = [0, 1, 2, 3].map(|i| dirs[i].path());
+        let in_config = config.join(RENCFS_BIN_NAME);
+        let in_env = env_dir.join(RENCFS_BIN_NAME);
+        let in_path = stub(path_dir, "rencfs 0.5.0");
+        let next_to_exe = stub(exe_dir, "rencfs 0.5.0");
+        let paths = || Some(env::join_paths([config, path_dir]).unwrap());
+
+        let find = |config_bin: Option<&Path>, env_bin: Option<&Path>, paths: Option<OsString>| {
+            find_rencfs_bin_in(config_bin, env_bin.map(|p| p.as_os_str().to_owned()), paths, Some(exe_dir.to_path_buf()))
+        };
+        // explicit settings are taken even if they don't exist, so a wrong one shows up
+        assert_eq!(find(Some(in_config.as_path()), Some(in_env.as_path()), paths()), Some(in_config));
+        assert_eq!(find(None, Some(in_env.as_path()), paths()), Some(in_env));
+        // the first dir in PATH has no rencfs
+        assert_eq!(find(None, None, paths()), Some(in_path));
+        assert_eq!(find(None, None, Some(env::join_paths([config]).unwrap())), Some(next_to_exe.clone()));
+        assert_eq!(find(None, None, None), Some(next_to_exe));
+
+        assert_eq!(find_rencfs_bin_in(None, None, None, Some(config.to_path_buf())), None);
+        assert_eq!(find_rencfs_bin_in(None, None, None, None), None);
+    }
+
+    #[test]
+    fn not_executable_is_skipped() {
+        let dir = TempDir::new().unwrap();
+        let bin = stub(dir.path(), "rencfs 0.5.0");
+        fs::set_permissions(&bin, fs::Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(find_rencfs_bin_in(None, None, Some(dir.path().as_os_str().to_owned()), None), None);
+        // a dir named like it neither
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(RENCFS_BIN_NAME)).unwrap();
+        assert_eq!(find_rencfs_bin_in(None, None, None, Some(dir.path().to_path_buf())), None);
+    }
+}
@@ -0,0 +1,166 @@
+use std::str::FromStr;
+
+/// What a schedule does to its vault, stored in `schedules.action`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScheduleAction {
+    #[default]
+    Lock,
+    Unlock,
+}
+
+impl ScheduleAction {
+    pub const ALL: [ScheduleAction; 2] = [ScheduleAction::Lock, ScheduleAction::Unlock];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleAction::Lock => "lock",
+            ScheduleAction::Unlock => "unlock",
+        }
+    }
+}
+
+impl FromStr for ScheduleAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ScheduleAction::ALL.into_iter().find(|a| a.as_str() == s).ok_or(())
+    }
+}
+
+/// In the order of the bits in `schedules.days`.
+pub const DAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+pub const WEEKDAYS: i32 = 0b0011111;
+pub const ALL_DAYS: i32 = 0b1111111;
+pub const MINUTES_PER_DAY: i32 = 24 * 60;
+
+/// Like `19:05`.
+pub fn format_time(minute_of_day: i32) -> String {
+    format!("{:02}:{:02}", minute_of_day / 60, minute_of_day % 60)
+}
+
+/// Like `Mon Tue Fri`, or `every day` and `weekdays`.
+pub fn format_days(days: i32) -> String {
+    match days & ALL_DAYS {
+        ALL_DAYS => "every day".to_string(),
+        WEEKDAYS => "weekdays".to_string(),
+        days => DAY_NAMES.iter().enumerate()
+            .filter(|(i, _)| days & (1 << i) != 0)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// The latest time at or before `now` the schedule was due, as unix time in seconds, `None` if it has no days.
+///
+/// `minute_of_day` is in local time, we let `mktime` resolve it so DST changes are taken into account.
+pub fn latest_occurrence(minute_of_day: i32, days: i32, now: i64) -> Option<i64> {
+    let mut today: libc::tm = unsafe { std::mem::zeroed() };
+    let now_t = now as libc::time_t;
+    if unsafe { libc::localtime_r(&now_t, &mut today) }.is_null() {
+        return None;
+    }
+    // a week back is enough to find any day, the first one is for later today
+    for back in 0..=7 {
+        let mut tm = today;
+        tm.tm_mday -= back;
+        tm.tm_hour = minute_of_day / 60;
+        tm.tm_min = minute_of_day % 60;
+        tm.tm_sec = 0;
+        tm.tm_isdst = -1;
+        let at = unsafe { libc::mktime(&mut tm) } as i64;
+        if at == -1 || at > now {
+            continue;
+        }
+        // mktime normalized tm_wday, it starts on Sunday
+        let day = (tm.tm_wday + 6) % 7;
+        if days & (1 << day) != 0 {
+            return Some(at);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Once;
+
+    use super::*;
+
+    const WEDNESDAY: i32 = 1 << 2;
+    const WEEKEND: i32 = 0b1100000;
+
+    /// Central European time with its DST rules, so it doesn't depend on the zone of the machine or tzdata.
+    fn set_zone() {
+        static ZONE: Once = Once::new();
+        ZONE.call_once(|| {
+            std::env::set_var("TZ", "CET-1CEST,M3.5.0,M10.5.0/3");
+            unsafe { libc::tzset() };
+        });
+    }
+
+    fn local(year: i32, month: i32, day: i32, hour: i32, min: i32, sec: i32) -> i64 {
+        set_zone();
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        tm.tm_year = year - 1900;
+        tm.tm_mon = month - 1;
+        tm.tm_mday = day;
+        tm.tm_hour = hour;
+        tm.tm_min = min;
+        tm.tm_sec = sec;
+        tm.tm_isdst = -1;
+        unsafe { libc::mktime(&mut tm) as i64 }
+    }
+
+    fn at(hour: i32, min: i32) -> i32 {
+        hour * 60 + min
+    }
+
+    #[test]
+    fn weekday_masks() {
+        // a Wednesday
+        let now = local(2024, 6, 12, 12, 0, 0);
+        assert_eq!(latest_occurrence(at(9, 0), WEEKDAYS, now), Some(local(2024, 6, 12, 9, 0, 0)));
+        assert_eq!(latest_occurrence(at(13, 0), WEEKDAYS, now), Some(local(2024, 6, 11, 13, 0, 0)));
+        assert_eq!(latest_occurrence(at(9, 0), WEEKEND, now), Some(local(2024, 6, 9, 9, 0, 0)));
+        assert_eq!(latest_occurrence(at(13, 0), 1, now), Some(local(2024, 6, 10, 13, 0, 0)));
+        assert_eq!(latest_occurrence(at(23, 59), ALL_DAYS, now), Some(local(2024, 6, 11, 23, 59, 0)));
+        assert_eq!(latest_occurrence(at(0, 0), ALL_DAYS, now), Some(local(2024, 6, 12, 0, 0, 0)));
+    }
+
+    #[test]
+    fn no_days() {
+        assert_eq!(latest_occurrence(at(9, 0), 0, local(2024, 6, 12, 12, 0, 0)), None);
+    }
+
+    #[test]
+    fn same_minute_boundary() {
+        let due = local(2024, 6, 12, 12, 0, 0);
+        assert_eq!(latest_occurrence(at(12, 0), ALL_DAYS, due), Some(due));
+        assert_eq!(latest_occurrence(at(12, 0), ALL_DAYS, due + 30), Some(due));
+        assert_eq!(latest_occurrence(at(12, 0), ALL_DAYS, due - 1), Some(local(2024, 6, 11, 12, 0, 0)));
+    }
+
+    #[test]
+    fn spring_forward_nonexistent_time() {
+        // clocks go from 02:00 to 03:00 on 2024-03-31, 02:30 doesn't exist that day but it's still due that day
+        let now = local(2024, 3, 31, 12, 0, 0);
+        let due = latest_occurrence(at(2, 30), ALL_DAYS, now).unwrap();
+        assert!(due > local(2024, 3, 31, 1, 59, 59) && due < local(2024, 3, 31, 4, 0, 0), "{}", due);
+        // the day before and after are unaffected
+        assert_eq!(latest_occurrence(at(2, 30), ALL_DAYS, local(2024, 3, 30, 12, 0, 0)), Some(local(2024, 3, 30, 2, 30, 0)));
+        assert_eq!(latest_occurrence(at(2, 30), ALL_DAYS, local(2024, 4, 1, 12, 0, 0)), Some(local(2024, 4, 1, 2, 30, 0)));
+        // over the change a day is 23 hours
+        assert_eq!(local(2024, 3, 31, 12, 0, 0) - local(2024, 3, 30, 12, 0, 0), 23 * 3600);
+    }
+
+    #[test]
+    fn looks_back_a_week() {
+        // a Wednesday, before the schedule's time, so it's due since the Wednesday before
+        let now = local(2024, 6, 12, 12, 0, 0);
+        assert_eq!(latest_occurrence(at(13, 0), WEDNESDAY, now), Some(local(2024, 6, 5, 13, 0, 0)));
+        assert_eq!(latest_occurrence(at(12, 0), WEDNESDAY, now), Some(now));
+        // a Thursday
+        assert_eq!(latest_occurrence(at(13, 0), 1 << 3, now), Some(local(2024, 6, 6, 13, 0, 0)));
+    }
+}
@@ -6,6 +6,31 @@ diesel::table! {
         name -> Text,
         mount_point -> Text,
         data_dir -> Text,
-        locked -> Integer,
+        remember_password -> Integer,
+        restart_policy -> Text,
+        last_exit_status -> Nullable<Integer>,
+        idle_timeout_mins -> Integer,
+        lock_with_session -> Integer,
+        lock_at -> Nullable<BigInt>,
+        state -> Text,
     }
 }
+
+diesel::table! {
+    schedules (id) {
+        id -> Integer,
+        vault_id -> Integer,
+        action -> Text,
+        minute_of_day -> Integer,
+        days -> Integer,
+        enabled -> Integer,
+        last_run -> Nullable<BigInt>,
+    }
+}
+
+diesel::joinable!(schedules -> vaults (vault_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    schedules,
+    vaults,
+);
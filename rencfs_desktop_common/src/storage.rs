@@ -1,6 +1,7 @@
 use directories::ProjectDirs;
 use tracing::{error, instrument};
-use std::{fs, panic};
+use std::{fs, io, panic};
+use std::os::unix::fs::PermissionsExt;
 use std::path::PathBuf;
 use std::str::FromStr;
 use crate::app_details::{APPLICATION, ORGANIZATION, QUALIFIER};
@@ -45,6 +46,27 @@ pub fn get_logs_dir() -> PathBuf {
     }
 }
 
+/// Per-user dir for the daemon socket, `$XDG_RUNTIME_DIR/rencfs_desktop`, only the owner can access it.
+///
+/// Clients use it too, so it fails instead of panicking, they then report the daemon as unavailable.
+pub fn get_runtime_dir() -> io::Result<PathBuf> {
+    let path = if is_debug() {
+        get_dev_data_dir()
+    } else {
+        let proj_dirs = ProjectDirs::from(QUALIFIER, ORGANIZATION, APPLICATION)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "cannot get project directories"))?;
+        // no runtime dir when XDG_RUNTIME_DIR is not set, like in some minimal sessions
+        proj_dirs.runtime_dir().unwrap_or(proj_dirs.data_local_dir()).to_path_buf()
+    };
+    fs::create_dir_all(&path)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o700))?;
+    Ok(path)
+}
+
+pub fn get_socket_path() -> io::Result<PathBuf> {
+    Ok(get_runtime_dir()?.join("daemon.sock"))
+}
+
 fn get_dev_data_dir() -> PathBuf {
     let path = PathBuf::from_str(&format!("/tmp/{}", APPLICATION.replace(" ", "-").to_lowercase())).unwrap();
     fs::create_dir_all(&path).expect("Cannot create data directory");
@@ -0,0 +1,226 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use diesel::SqliteConnection;
+use tokio::sync::{Mutex, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{error, info, instrument, warn};
+
+use crate::dao::VaultDao;
+use crate::engine::{Health, Mount, VaultEngine};
+use crate::events::{EventBus, VaultEventKind};
+use crate::models::Vault;
+use crate::progress::ProgressReporter;
+use crate::vault_state::{transition, VaultState};
+
+const MAX_RESTARTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// If the vault ran at least this long we consider it was healthy and reset the restarts counter.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+/// How long the mount has to exit after it was unmounted before we kill it.
+const EXIT_AFTER_UNMOUNT_GRACE: Duration = Duration::from_secs(5);
+
+/// What to do when the rencfs process of an unlocked vault exits on its own, stored in `vaults.restart_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
+}
+
+impl RestartPolicy {
+    pub const ALL: [RestartPolicy; 3] = [RestartPolicy::Never, RestartPolicy::OnFailure, RestartPolicy::Always];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RestartPolicy::Never => "never",
+            RestartPolicy::OnFailure => "on-failure",
+            RestartPolicy::Always => "always",
+        }
+    }
+
+    fn should_restart(&self, code: i32) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => code != 0,
+            RestartPolicy::Always => true,
+        }
+    }
+}
+
+impl FromStr for RestartPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RestartPolicy::ALL.into_iter().find(|p| p.as_str() == s).ok_or(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StopMode {
+    /// kill the mount, which unmounts the vault
+    Kill,
+    /// stop watching but leave the mount running, it will be adopted on next daemon start
+    Detach,
+    /// it was unmounted, give it time to exit on its own and kill it only after that
+    Unmounted,
+}
+
+/// Owns the [Mount] of an unlocked vault and watches it until it's stopped.
+///
+/// If it exits on its own it records the exit status, cleans up the stale mount, marks the vault crashed
+/// and restarts it according to the vault's [RestartPolicy].
+pub struct Supervisor {
+    stop_tx: Option<oneshot::Sender<StopMode>>,
+    handle: JoinHandle<()>,
+    unmounting: Arc<AtomicBool>,
+}
+
+impl Supervisor {
+    /// Without `password` the vault is never restarted, like for processes adopted from a previous daemon run.
+    pub fn spawn(vault: Vault, mount: Box<dyn Mount>, password: Option<String>, engine: Arc<dyn VaultEngine>,
+                 db_conn: Arc<Mutex<SqliteConnection>>, events: EventBus) -> Self {
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let unmounting = Arc::new(AtomicBool::new(false));
+        let handle = tokio::spawn(supervise(vault, mount, password, engine, db_conn, events, stop_rx, unmounting.clone()));
+        Self { stop_tx: Some(stop_tx), handle, unmounting }
+    }
+
+    /// `false` after the vault exited and was not restarted.
+    pub fn is_running(&self) -> bool {
+        !self.handle.is_finished()
+    }
+
+    /// Kills the mount and waits for the supervisor to finish.
+    pub async fn stop(self) {
+        self.send_stop(StopMode::Kill).await
+    }
+
+    /// Stops watching the mount but leaves it running, so the vault stays mounted.
+    pub async fn detach(self) {
+        self.send_stop(StopMode::Detach).await
+    }
+
+    /// Set while we unmount the vault, so the mount exiting because of it is not taken for a crash.
+    pub fn set_unmounting(&self, value: bool) {
+        self.unmounting.store(value, Ordering::SeqCst);
+    }
+
+    /// After the vault was unmounted, waits for the mount to exit and kills it if it doesn't.
+    pub async fn stop_unmounted(self) {
+        self.send_stop(StopMode::Unmounted).await
+    }
+
+    async fn send_stop(mut self, mode: StopMode) {
+        if let Some(tx) = self.stop_tx.take() {
+            let _ = tx.send(mode);
+        }
+        if let Err(err) = self.handle.await {
+            error!(err = %err, "Supervisor task failed");
+        }
+    }
+}
+
+#[instrument(skip_all, fields(id = vault.id))]
+#[allow(clippy::too_many_arguments)]
+async fn supervise(vault: Vault, mut mount: Box<dyn Mount>, password: Option<String>, engine: Arc<dyn VaultEngine>,
+                   db_conn: Arc<Mutex<SqliteConnection>>, events: EventBus, mut stop_rx: oneshot::Receiver<StopMode>,
+                   unmounting: Arc<AtomicBool>) {
+    let policy = RestartPolicy::from_str(&vault.restart_policy).unwrap_or_default();
+    let mut restarts = 0;
+    loop {
+        let started = Instant::now();
+        let code = tokio::select! {
+            code = mount.wait() => code,
+            mode = &mut stop_rx => {
+                // if handler was dropped without stopping us we kill it, like it would be on lock
+                let mode = mode.unwrap_or(StopMode::Kill);
+                if mode == StopMode::Unmounted {
+                    match tokio::time::timeout(EXIT_AFTER_UNMOUNT_GRACE, mount.wait()).await {
+                        Ok(_) => return,
+                        Err(_) => warn!("Mount didn't exit after unmount, killing it"),
+                    }
+                }
+                if mode != StopMode::Detach {
+                    if let Err(err) = mount.kill().await {
+                        error!(err = %err, "Error killing mount");
+                    }
+                }
+                return;
+            }
+        };
+        if unmounting.load(Ordering::SeqCst) {
+            info!(?code, "Vault exited after unmount");
+            return;
+        }
+        let code = match code {
+            Ok(code) => code,
+            Err(err) => {
+                error!(err = %err, "Cannot wait for mount");
+                return;
+            }
+        };
+
+        // exited on its own
+        warn!(code, "Vault exited");
+        db_update_exited(vault.id, code, &db_conn).await;
+        if engine.health(&vault).await == Health::Stale {
+            let _ = engine.unmount(Path::new(&vault.mount_point), true).await;
+        }
+        events.emit(vault.id as u32, VaultEventKind::Crashed, code.map(|code| format!("exit status {}", code)));
+
+        // we don't know the password of adopted vaults, and their exit status
+        let Some(password) = &password else { return; };
+        if !code.is_some_and(|code| policy.should_restart(code)) {
+            return;
+        }
+        if started.elapsed() >= STABLE_AFTER {
+            restarts = 0;
+        }
+        loop {
+            if restarts >= MAX_RESTARTS {
+                error!(restarts, "Giving up restarting");
+                return;
+            }
+            let backoff = INITIAL_BACKOFF.saturating_mul(2u32.saturating_pow(restarts)).min(MAX_BACKOFF);
+            restarts += 1;
+            info!(?backoff, restarts, "Restarting");
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {}
+                _ = &mut stop_rx => return,
+            }
+            match engine.mount(&vault, password, &ProgressReporter::noop()).await {
+                Ok(new_mount) => {
+                    mount = new_mount;
+                    db_update_restarted(vault.id, &db_conn).await;
+                    events.emit(vault.id as u32, VaultEventKind::Unlocked, None);
+                    break;
+                }
+                Err(err) => error!(err = %err, "Cannot restart"),
+            }
+        }
+    }
+}
+
+async fn db_update_exited(id: i32, code: Option<i32>, db_conn: &Arc<Mutex<SqliteConnection>>) {
+    use crate::schema::vaults::dsl::last_exit_status;
+    use diesel::ExpressionMethods;
+
+    let mut guard = db_conn.lock().await;
+    let mut dao = VaultDao::new(&mut *guard);
+    if let Err(err) = dao.update(id, last_exit_status.eq(code)) {
+        error!(err = %err, "Cannot update vault exit status");
+    }
+    // transition logs why it failed
+    let _ = transition(&mut dao, id, VaultState::Crashed);
+}
+
+async fn db_update_restarted(id: i32, db_conn: &Arc<Mutex<SqliteConnection>>) {
+    let mut guard = db_conn.lock().await;
+    let _ = transition(&mut VaultDao::new(&mut *guard), id, VaultState::Unlocked);
+}
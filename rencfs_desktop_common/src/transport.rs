@@ -0,0 +1,19 @@
+use tokio::net::UnixStream;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::{Channel, Endpoint, Error, Uri};
+use tower::service_fn;
+
+use crate::auth::AuthInterceptor;
+use crate::storage::get_socket_path;
+
+/// What generated clients are created with, it adds the auth token to each request.
+pub type AuthChannel = InterceptedService<Channel, AuthInterceptor>;
+
+/// Connects to daemon over its unix socket.
+pub async fn connect() -> Result<AuthChannel, Error> {
+    // the uri is ignored, we always connect to the socket, not finding it fails like the daemon not running
+    let channel = Endpoint::from_static("http://[::]:50051")
+        .connect_with_connector(service_fn(|_: Uri| async { UnixStream::connect(get_socket_path()?).await }))
+        .await?;
+    Ok(InterceptedService::new(channel, AuthInterceptor))
+}
@@ -1,19 +1,22 @@
-use std::process;
-use std::fs::OpenOptions;
+use std::path::Path;
 use std::sync::Arc;
 
-use diesel::{QueryResult, SqliteConnection};
-use serde::{Deserialize, Serialize};
-use sysinfo::{Pid, ProcessStatus, System};
+use diesel::SqliteConnection;
 use thiserror::Error;
-use tokio::process::{Child, Command};
 use tokio::sync::Mutex;
 use tracing::{error, info, instrument, warn};
 
 use crate::dao::VaultDao;
-use crate::storage::get_logs_dir;
+use crate::engine::{Health, Mount, VaultEngine};
+use crate::events::{EventBus, VaultEventKind};
+use crate::data_migration::{Migration, Phase};
+use crate::keyring::Keyring;
+use crate::models::Vault;
+use crate::progress::ProgressReporter;
+use crate::supervisor::Supervisor;
+use crate::vault_state::{transition, VaultState};
 
-#[derive(Debug, Error, Serialize, Deserialize, Clone)]
+#[derive(Debug, Error, Clone, PartialEq)]
 pub enum VaultHandlerError {
     #[error("cannot lock vault")]
     CannotLockVault,
@@ -23,86 +26,272 @@ pub enum VaultHandlerError {
     CannotChangeMountPoint,
     #[error("cannot change data dir")]
     CannotChangeDataDir,
+    #[error("cannot change password")]
+    CannotChangePassword,
+    #[error("invalid password")]
+    InvalidPassword,
+    #[error("password required")]
+    PasswordRequired,
+    #[error("cannot access keyring")]
+    CannotAccessKeyring,
+    #[error("rencfs process exited before the vault was mounted: {cause}")]
+    ProcessExited { cause: String },
+    #[error("timeout waiting for the vault to be mounted after {secs}s")]
+    UnlockTimeout { secs: u64 },
+    #[error("operation cancelled")]
+    Cancelled,
+    #[error("rencfs binary not found, set it in config or with RENCFS_BIN env var")]
+    RencfsBinaryNotFound,
+    #[error("incompatible rencfs version {found}, required {required}")]
+    IncompatibleRencfsVersion { found: String, required: String },
+    /// `processes` has the ones with files open there, empty if we can't tell
+    #[error("mount point {mount_point} is busy")]
+    MountPointBusy { mount_point: String, processes: String },
+    #[error("mount point {mount_point} is not empty")]
+    MountPointNotEmpty { mount_point: String },
+    #[error("database error: {cause}")]
+    Database { cause: String },
+    #[error("vault is {from}, it cannot become {to}")]
+    InvalidTransition { from: String, to: String },
 }
 
 pub struct VaultHandler {
     id: u32,
-    child: Option<Child>,
+    supervisor: Option<Supervisor>,
+    /// kept while unlocked so we can remount when mount point or data dir changes
+    password: Option<String>,
+    engine: Arc<dyn VaultEngine>,
     db_conn: Arc<Mutex<SqliteConnection>>,
+    events: EventBus,
 }
 
 impl VaultHandler {
-    pub fn new(id: u32, db_conn: Arc<Mutex<SqliteConnection>>) -> Self {
-        Self { id, child: None, db_conn }
+    pub fn new(id: u32, engine: Arc<dyn VaultEngine>, db_conn: Arc<Mutex<SqliteConnection>>, events: EventBus) -> Self {
+        Self { id, supervisor: None, password: None, engine, db_conn, events }
     }
 
-    #[instrument(skip(self), fields(self.id), err)]
+    /// Takes over a vault left unlocked by a previous daemon run, we don't know its password so it's never restarted.
+    pub fn adopt(vault: Vault, mount: Box<dyn Mount>, engine: Arc<dyn VaultEngine>, db_conn: Arc<Mutex<SqliteConnection>>,
+                 events: EventBus) -> Self {
+        let id = vault.id as u32;
+        let supervisor = Supervisor::spawn(vault, mount, None, engine.clone(), db_conn.clone(), events.clone());
+        Self { id, supervisor: Some(supervisor), password: None, engine, db_conn, events }
+    }
+
+    /// The supervisor stops when the mount exits and is not restarted, then the vault is crashed.
+    fn is_unlocked(&self) -> bool {
+        self.supervisor.as_ref().is_some_and(|s| s.is_running())
+    }
+
+    /// Unmounts the vault cleanly, if files are open there it fails with [VaultHandlerError::MountPointBusy] and the
+    /// vault stays unlocked.
     pub async fn lock(&mut self, mount_point: Option<String>) -> Result<(), VaultHandlerError> {
+        let res = self.do_lock(mount_point, false).await;
+        self.emit_lock_result(&res);
+        res
+    }
+
+    /// Locks even if files are open there, the mount is lazily detached and killed. Programs using it get errors from
+    /// then on and lose what they didn't save. Only done when the user asks for it, automatic locks retry instead.
+    pub async fn force_lock(&mut self) -> Result<(), VaultHandlerError> {
+        let res = self.do_lock(None, true).await;
+        self.emit_lock_result(&res);
+        res
+    }
+
+    fn emit_lock_result(&self, res: &Result<(), VaultHandlerError>) {
+        match res {
+            Ok(_) => self.events.emit(self.id, VaultEventKind::Locked, None),
+            // tell who keeps it busy, they need to close their files before it can be locked
+            Err(err @ VaultHandlerError::MountPointBusy { processes, .. }) if !processes.is_empty() =>
+                self.events.emit(self.id, VaultEventKind::Error, Some(format!("{}, in use by {}", err, processes))),
+            Err(err) => self.events.emit(self.id, VaultEventKind::Error, Some(err.to_string())),
+        }
+    }
+
+    #[instrument(skip(self), fields(self.id), err)]
+    async fn do_lock(&mut self, mount_point: Option<String>, force: bool) -> Result<(), VaultHandlerError> {
         info!("");
 
-        {
-            let mut guard = self.db_conn.lock().await;
-            let mut dao = VaultDao::new(&mut *guard);
-            match self.db_update_locked(true, &mut dao).await {
-                Ok(_) => {}
-                Err(err) => {
-                    error!(err = %err, "Cannot update vault state");
-                    return Err(VaultHandlerError::CannotLockVault.into());
+        if !self.is_unlocked() {
+            info!("VaultHandler already locked");
+            self.supervisor.take();
+            // also clears Error and Crashed, db can still say unlocked if the mount is gone without us noticing
+            let vault = self.vault().await?;
+            if self.engine.health(&vault).await == Health::Stale {
+                let _ = self.engine.unmount(Path::new(&vault.mount_point), true).await;
+            }
+            if vault.state() == VaultState::Unlocked {
+                self.set_state(VaultState::Locking).await?;
+            }
+            return self.set_locked().await;
+        }
+        self.set_state(VaultState::Locking).await?;
+        self.events.emit(self.id, VaultEventKind::Locking, None);
+
+        let mount_point = match mount_point {
+            Some(mount_point) => mount_point,
+            None => self.vault().await?.mount_point,
+        };
+        let supervisor = self.supervisor.take().unwrap();
+        // the mount exits once unmounted, that's not a crash
+        supervisor.set_unmounting(true);
+        if force {
+            warn!("Force locking, detaching the mount and killing it");
+            if let Err(err) = self.engine.unmount(Path::new(&mount_point), true).await {
+                supervisor.set_unmounting(false);
+                self.supervisor = Some(supervisor);
+                let _ = self.set_state(VaultState::Unlocked).await;
+                return Err(err);
+            }
+            supervisor.stop().await;
+        } else {
+            // killing it first could lose writes not flushed yet, unmounting lets it finish them and exit
+            if let Err(err) = self.engine.unmount(Path::new(&mount_point), false).await {
+                supervisor.set_unmounting(false);
+                if supervisor.is_running() {
+                    // still mounted and served, nothing changed
+                    self.supervisor = Some(supervisor);
+                    let _ = self.set_state(VaultState::Unlocked).await;
+                } else {
+                    let _ = self.set_state(VaultState::Error).await;
                 }
+                return Err(err);
             }
+            supervisor.stop_unmounted().await;
         }
+        self.password.take();
 
-        if self.child.is_none() {
-            info!("VaultHandler already locked");
+        self.set_locked().await
+    }
+
+    /// Leaves the vault mounted but stops watching its process, used when daemon exits.
+    #[instrument(skip(self), fields(self.id))]
+    pub async fn detach(&mut self) {
+        info!("");
+
+        self.password.take();
+        if let Some(supervisor) = self.supervisor.take() {
+            supervisor.detach().await;
+        }
+    }
+
+    /// If `password` is `None` it's read from keyring, when the vault has `remember_password` set.
+    pub async fn unlock(&mut self, password: Option<String>, progress: &ProgressReporter) -> Result<(), VaultHandlerError> {
+        if self.is_unlocked() {
+            info!(id = self.id, "VaultHandler already unlocked");
             return Ok(());
         }
-        info!("VaultHandler killing child process to lock the vault");
-        if let Err(err) = self.child.take().unwrap().kill().await {
-            error!(err = %err, "Error killing child process");
-            return Err(VaultHandlerError::CannotLockVault.into());
+        let res = match self.set_state(VaultState::Unlocking).await {
+            Ok(_) => {
+                self.events.emit(self.id, VaultEventKind::Unlocking, None);
+                let res = self.do_unlock(password, progress).await;
+                let state = match &res {
+                    Ok(_) => VaultState::Unlocked,
+                    Err(err) => unlock_failed_state(err),
+                };
+                // keep the unlock error if both fail
+                let set = self.set_state(state).await;
+                res.and(set.map(|_| ()))
+            }
+            Err(err) => Err(err),
+        };
+        match &res {
+            Ok(_) => self.events.emit(self.id, VaultEventKind::Unlocked, None),
+            Err(VaultHandlerError::Cancelled) => self.events.emit(self.id, VaultEventKind::Locked, None),
+            Err(err) => self.events.emit(self.id, VaultEventKind::Error, Some(err.to_string())),
         }
+        res
+    }
 
-        // for some reason of we use 'kill' method the child process doesn't receive the SIGKILL signal
-        // for that case we use `umount` command
-        // TODO: umount for windows
-        if cfg!(any(linux, unix, macos, freebsd, openbsd, netbsd)) {
-            let mount_point = if let Some(mount_point) = mount_point {
-                mount_point
-            } else {
-                let mut guard = self.db_conn.lock().await;
-                let mut dao = VaultDao::new(&mut *guard);
-                match dao.get(self.id as i32) {
-                    Ok(vault) => vault.mount_point,
-                    Err(err) => {
-                        error!(%err, "Cannot get vault");
-                        return Err(VaultHandlerError::CannotLockVault.into());
-                    }
+    #[instrument(skip(self, password, progress), fields(self.id), err)]
+    async fn do_unlock(&mut self, password: Option<String>, progress: &ProgressReporter) -> Result<(), VaultHandlerError> {
+        info!("");
+
+        let vault = self.vault().await?;
+
+        let remember_password = vault.remember_password == 1;
+        let from_keyring = password.is_none();
+        let password = match password {
+            Some(password) => password,
+            None if remember_password => self.password_from_keyring().await.ok_or(VaultHandlerError::PasswordRequired)?,
+            None => return Err(VaultHandlerError::PasswordRequired),
+        };
+
+        let mount = self.engine.mount(&vault, &password, progress).await?;
+
+        if remember_password && !from_keyring {
+            // not being able to save it shouldn't fail the unlock
+            match Keyring::connect().await {
+                Ok(keyring) => if let Err(err) = keyring.set_password(self.id, &vault.name, &password).await {
+                    warn!(err = %err, "Cannot save password in keyring");
                 }
-            };
-            if let Err(_) = process::Command::new("umount")
-                .arg(&mount_point)
-                .output() {
-                error!(mount_point, "Cannot umount");
-                return Err(VaultHandlerError::CannotLockVault.into());
+                Err(err) => warn!(err = %err, "Cannot save password in keyring"),
             }
         }
 
+        self.supervisor = Some(Supervisor::spawn(vault, mount, Some(password.clone()), self.engine.clone(),
+                                                 self.db_conn.clone(), self.events.clone()));
+        self.password = Some(password);
+
         Ok(())
     }
 
+    /// `None` if the keyring can't be reached or doesn't have it, the user is asked for it then.
+    async fn password_from_keyring(&self) -> Option<String> {
+        let keyring = match Keyring::connect().await {
+            Ok(keyring) => keyring,
+            Err(err) => {
+                warn!(err = %err, "Cannot get password from keyring");
+                return None;
+            }
+        };
+        match keyring.get_password(self.id).await {
+            Ok(Some(password)) => Some(password),
+            Ok(None) => {
+                warn!("Password not found in keyring");
+                None
+            }
+            Err(err) => {
+                warn!(err = %err, "Cannot get password from keyring");
+                None
+            }
+        }
+    }
+
     #[instrument(skip(self), fields(self.id), err)]
-    pub async fn unlock(&mut self) -> Result<(), VaultHandlerError> {
+    pub async fn change_mount_point(&mut self, new_mount_point: String) -> Result<(), VaultHandlerError> {
         info!("");
 
-        if self.child.is_some() {
-            info!("VaultHandler already unlocked");
-            return Ok(());
+        use crate::schema::vaults::dsl::mount_point;
+        use diesel::ExpressionMethods;
+
+        let unlocked = self.is_unlocked();
+        let password = self.password.clone();
+        if unlocked {
+            self.lock(None).await?;
+        }
+        {
+            let mut guard = self.db_conn.lock().await;
+            if let Err(err) = VaultDao::new(&mut *guard).update(self.id as i32, mount_point.eq(new_mount_point)) {
+                error!(err = %err, "Cannot update mount point");
+                return Err(VaultHandlerError::Database { cause: err.to_string() });
+            }
+        }
+        if unlocked {
+            match password {
+                Some(password) => self.unlock(Some(password), &ProgressReporter::noop()).await?,
+                None => self.emit_left_locked(),
+            }
         }
 
-        // create logs files
-        let logs_dir = get_logs_dir();
-        let stdout = OpenOptions::new().append(true).create(true).open(logs_dir.join(format!("vault_{}.out", self.id))).expect("Cannot create stdout file");
-        let stderr = OpenOptions::new().append(true).create(true).open(logs_dir.join(format!("vault_{}.err", self.id))).expect("Cannot create stderr file");
+        Ok(())
+    }
+
+    /// Moves the content to `new_data_dir` and then points the vault to it.
+    #[instrument(skip(self, progress), fields(self.id), err)]
+    pub async fn change_data_dir(&mut self, new_data_dir: String, progress: &ProgressReporter) -> Result<(), VaultHandlerError> {
+        info!("");
 
         let vault = {
             let mut guard = self.db_conn.lock().await;
@@ -111,136 +300,216 @@ impl VaultHandler {
                 Ok(vault) => vault,
                 Err(err) => {
                     error!(err = %err, "Cannot get vault");
-                    return Err(VaultHandlerError::CannotLockVault.into());
+                    return Err(VaultHandlerError::CannotChangeDataDir);
                 }
             }
         };
+        if vault.data_dir == new_data_dir {
+            return Ok(());
+        }
 
-        // spawn new process
-        let child = Command::new("/home/gnome/dev/RustroverProjects/rencfs/rencfs/target/debug/rencfs")
-            // TODO get pass from keystore
-            .env("ENCRYPTEDFS_PASSWORD", "pass-42")
-            .stdout(stdout)
-            .stderr(stderr)
-            .arg("--mount-point")
-            .arg(&vault.mount_point)
-            .arg("--data-dir")
-            .arg(&vault.data_dir)
-            .arg("--umount-on-start")
-            .spawn();
-        let child = match child {
-            Ok(child) => child,
-            Err(err) => {
-                error!(err = %err, "Cannot start process");
-                return Err(VaultHandlerError::CannotUnlockVault.into());
+        let unlocked = self.is_unlocked();
+        let password = self.password.clone();
+        if unlocked {
+            self.lock(Some(vault.mount_point.clone())).await?;
+        }
+
+        let migration = Migration::new(self.id as i32, vault.data_dir.into(), new_data_dir.into()).map_err(|err| {
+            error!(err = %err, "Cannot start data dir migration");
+            VaultHandlerError::CannotChangeDataDir
+        })?;
+        let res = self.migrate_data_dir(migration, progress).await;
+
+        // unlock back even if it failed, when not switched the content is still in the old data dir
+        if unlocked {
+            match password {
+                Some(password) => {
+                    // keep the migration error if both fail
+                    let unlocked_back = self.unlock(Some(password), &ProgressReporter::noop()).await;
+                    return res.and(unlocked_back);
+                }
+                None => self.emit_left_locked(),
             }
+        }
+        res
+    }
+
+    /// Adopted vaults don't have their password, after a change that needs to remount them they are left locked.
+    fn emit_left_locked(&self) {
+        warn!("Password not known, leaving vault locked");
+        self.events.emit(self.id, VaultEventKind::Error, Some("locked to apply the change, unlock it again".to_string()));
+    }
+
+    /// Continues a data dir change interrupted by a daemon stop, the vault is left locked.
+    #[instrument(skip(self, migration), fields(self.id), err)]
+    pub async fn resume_data_dir_change(&mut self, migration: Migration) -> Result<(), VaultHandlerError> {
+        info!(from = %migration.from.display(), to = %migration.to.display(), phase = ?migration.phase, "");
+
+        if self.is_unlocked() {
+            self.lock(None).await?;
+        }
+        self.migrate_data_dir(migration, &ProgressReporter::noop()).await
+    }
+
+    async fn migrate_data_dir(&mut self, migration: Migration, progress: &ProgressReporter) -> Result<(), VaultHandlerError> {
+        self.set_state(VaultState::Migrating).await?;
+        self.events.emit(self.id, VaultEventKind::Migrating, None);
+        let res = self.do_migrate_data_dir(migration, progress).await;
+        // when it fails before switching the content is still in the old data dir, after it's in the new one
+        let state = match &res {
+            Ok(_) | Err(VaultHandlerError::Cancelled) => VaultState::Locked,
+            Err(_) => VaultState::Error,
         };
+        // keep the migration error if both fail
+        let set = self.set_state(state).await;
+        let res = res.and(set.map(|_| ()));
+        match &res {
+            Ok(_) | Err(VaultHandlerError::Cancelled) => self.events.emit(self.id, VaultEventKind::Locked, None),
+            Err(err) => self.events.emit(self.id, VaultEventKind::Error, Some(err.to_string())),
+        }
+        res
+    }
 
-        // wait few second and check if it started correctly
-        tokio::time::sleep(tokio::time::Duration::from_secs(8)).await;
-        if child.id().is_none() {
-            return Err(VaultHandlerError::CannotUnlockVault.into());
-        }
-        let mut sys = System::new();
-        sys.refresh_processes();
-        let mut is_defunct = false;
-        match sys.process(Pid::from_u32(child.id().unwrap())) {
-            Some(process) => {
-                println!("{:?}", process.status());
-                if process.status() == ProcessStatus::Dead ||
-                    process.status() == ProcessStatus::Zombie ||
-                    process.status() == ProcessStatus::Stop {
-                    warn!("Process is dead or zombie, killing it");
-                    is_defunct = true;
-                } else {
-                    // try to check if it's defunct with ps command
-                    // TODO: ps for windows
-                    if cfg!(any(linux, unix, macos, freebsd, openbsd, netbsd)) {
-                        let out = Command::new("ps")
-                            .arg("-f")
-                            .arg(child.id().unwrap().to_string())
-                            .output().await
-                            .expect("Cannot run ps command");
-                        String::from_utf8(out.stdout).unwrap().lines().for_each(|line| {
-                            if line.contains("defunct") {
-                                warn!("Process is defunct, killing it");
-                                is_defunct = true;
-                            }
-                        });
-                    }
+    async fn do_migrate_data_dir(&mut self, mut migration: Migration, progress: &ProgressReporter) -> Result<(), VaultHandlerError> {
+        use crate::schema::vaults::dsl::data_dir;
+        use diesel::ExpressionMethods;
+
+        if migration.phase == Phase::Copying {
+            let progress2 = progress.clone();
+            let res = tokio::task::spawn_blocking(move || {
+                let res = migration.copy_and_verify(&progress2);
+                (migration, res)
+            }).await;
+            migration = match res {
+                Ok((migration, Ok(_))) => migration,
+                Ok((migration, Err(err))) => {
+                    error!(err = %err, "Cannot copy content to new data dir");
+                    // old data dir is untouched, keep using it
+                    let _ = migration.abort();
+                    return Err(if progress.is_cancelled() { VaultHandlerError::Cancelled } else { VaultHandlerError::CannotChangeDataDir });
+                }
+                Err(err) => {
+                    error!(err = %err, "Data dir migration task failed");
+                    return Err(VaultHandlerError::CannotChangeDataDir);
+                }
+            };
+
+            {
+                let mut guard = self.db_conn.lock().await;
+                let mut dao = VaultDao::new(&mut *guard);
+                if let Err(err) = dao.update(self.id as i32, data_dir.eq(migration.to.to_string_lossy().to_string())) {
+                    error!(err = %err, "Cannot update vault data dir");
+                    return Err(VaultHandlerError::CannotChangeDataDir);
                 }
             }
-            None => return Err(VaultHandlerError::CannotUnlockVault.into())
-        }
-        if is_defunct {
-            // TODO: kill for windows
-            if cfg!(any(linux, unix, macos, freebsd, openbsd, netbsd)) {
-                process::Command::new("kill")
-                    .arg(child.id().unwrap().to_string())
-                    .output()
-                    .expect("Cannot kill process");
+            self.events.emit(self.id, VaultEventKind::Updated, None);
+            if let Err(err) = migration.mark_switched() {
+                error!(err = %err, "Cannot save migration journal");
+                return Err(VaultHandlerError::CannotChangeDataDir);
             }
-            return Err(VaultHandlerError::CannotUnlockVault.into());
         }
 
-        self.child = Some(child);
-
-        let mut guard = self.db_conn.lock().await;
-        let mut dao = VaultDao::new(&mut *guard);
-        match self.db_update_locked(false, &mut dao).await {
-            Ok(_) => {}
+        match tokio::task::spawn_blocking(move || migration.finish()).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(err)) => {
+                // vault already uses the new data dir, we'll retry removing the old one on next start
+                warn!(err = %err, "Cannot remove old data dir");
+                Ok(())
+            }
             Err(err) => {
-                error!(err = %err, "Cannot update vault state");
-                return Err(VaultHandlerError::CannotUnlockVault.into());
+                error!(err = %err, "Data dir migration task failed");
+                Err(VaultHandlerError::CannotChangeDataDir)
             }
         }
-
-        Ok(())
     }
 
+    /// Sets when a time-limited unlock ends, as unix time in seconds, `None` to stay unlocked until locked.
     #[instrument(skip(self), fields(self.id), err)]
-    pub async fn change_mount_point(&mut self, old_mount_point: String) -> Result<(), VaultHandlerError> {
+    pub async fn set_lock_at(&mut self, value: Option<i64>) -> Result<(), VaultHandlerError> {
+        use crate::schema::vaults::dsl::lock_at;
+        use diesel::ExpressionMethods;
+
+        let mut guard = self.db_conn.lock().await;
+        VaultDao::new(&mut *guard).update(self.id as i32, lock_at.eq(value)).map_err(|err| {
+            error!(err = %err, "Cannot update lock time");
+            VaultHandlerError::Database { cause: err.to_string() }
+        })
+    }
+
+    /// An unlocked vault is locked for it and unlocked back after, with the new password if it was changed.
+    #[instrument(skip(self, old_password, new_password), fields(self.id), err)]
+    pub async fn change_password(&mut self, old_password: String, new_password: String) -> Result<(), VaultHandlerError> {
         info!("");
 
-        let unlocked = self.child.is_some();
+        let unlocked = self.is_unlocked();
         if unlocked {
-            self.lock(Some(old_mount_point)).await?;
-            self.unlock().await?;
+            // no need to lock it to find out, adopted vaults we don't know
+            if self.password.as_ref().is_some_and(|p| *p != old_password) {
+                return Err(VaultHandlerError::InvalidPassword);
+            }
+            self.lock(None).await?;
         }
+        let vault = self.vault().await?;
+        let res = self.engine.change_password(&vault, &old_password, &new_password).await;
 
-        Ok(())
+        if res.is_ok() && vault.remember_password == 1 {
+            match Keyring::connect().await {
+                Ok(keyring) => if let Err(err) = keyring.set_password(self.id, &vault.name, &new_password).await {
+                    warn!(err = %err, "Cannot save password in keyring");
+                }
+                Err(err) => warn!(err = %err, "Cannot save password in keyring"),
+            }
+        }
+        if unlocked {
+            let password = if res.is_ok() { new_password } else { old_password };
+            self.unlock(Some(password), &ProgressReporter::noop()).await?;
+        }
+        res
     }
 
     #[instrument(skip(self), fields(self.id), err)]
-    pub async fn change_data_dir(&mut self, old_data_dir: String) -> Result<(), VaultHandlerError> {
+    pub async fn forget_password(&mut self) -> Result<(), VaultHandlerError> {
         info!("");
 
-        let unlocked = self.child.is_some();
-        if unlocked {
-            let mount_point = {
-                let mut guard = self.db_conn.lock().await;
-                let mut dao = VaultDao::new(&mut *guard);
-                match dao.get(self.id as i32) {
-                    Ok(vault) => vault.mount_point,
-                    Err(err) => {
-                        error!(err = %err, "Cannot get vault");
-                        return Err(VaultHandlerError::CannotChangeDataDir.into());
-                    }
-                }
-            };
-            self.lock(Some(mount_point)).await?;
-            // TODO: move content to new data dir
-            self.unlock().await?;
-        }
+        Keyring::connect().await?.delete_password(self.id).await
+    }
 
-        Ok(())
+    async fn vault(&self) -> Result<Vault, VaultHandlerError> {
+        let mut guard = self.db_conn.lock().await;
+        VaultDao::new(&mut *guard).get(self.id as i32).map_err(|err| {
+            error!(err = %err, "Cannot get vault");
+            VaultHandlerError::Database { cause: err.to_string() }
+        })
     }
 
-    #[instrument(skip(self, dao), fields(self.id), err)]
-    async fn db_update_locked(&self, state: bool, dao: &mut VaultDao<'_>) -> QueryResult<()> {
-        use crate::schema::vaults::dsl::locked;
+    /// Returns the previous state, fails if the vault can't go from it to `to`.
+    async fn set_state(&self, to: VaultState) -> Result<VaultState, VaultHandlerError> {
+        let mut guard = self.db_conn.lock().await;
+        transition(&mut VaultDao::new(&mut *guard), self.id as i32, to)
+    }
+
+    async fn set_locked(&self) -> Result<(), VaultHandlerError> {
+        use crate::schema::vaults::dsl::lock_at;
         use diesel::ExpressionMethods;
 
-        dao.update(self.id as i32, locked.eq(if state { 1 } else { 0 }))
+        let mut guard = self.db_conn.lock().await;
+        let mut dao = VaultDao::new(&mut *guard);
+        transition(&mut dao, self.id as i32, VaultState::Locked)?;
+        // a time-limited unlock ends with it
+        dao.update(self.id as i32, lock_at.eq(None::<i64>)).map_err(|err| {
+            error!(err = %err, "Cannot update lock time");
+            VaultHandlerError::Database { cause: err.to_string() }
+        })
+    }
+}
+
+/// Failing because of something to fix before trying again leaves the vault locked, as nothing was mounted.
+fn unlock_failed_state(err: &VaultHandlerError) -> VaultState {
+    use VaultHandlerError::*;
+
+    match err {
+        InvalidPassword | PasswordRequired | CannotAccessKeyring | Cancelled | RencfsBinaryNotFound |
+        IncompatibleRencfsVersion { .. } | MountPointNotEmpty { .. } => VaultState::Locked,
+        _ => VaultState::Error,
     }
 }
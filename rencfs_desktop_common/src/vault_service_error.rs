@@ -1,43 +1,283 @@
-use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
 use thiserror::Error;
-use tonic::Status;
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
 
 use crate::vault_handler::VaultHandlerError;
 
-#[derive(Debug, Error, Serialize, Deserialize, Clone)]
+#[derive(Debug, Error, Clone, PartialEq)]
 pub enum VaultServiceError {
     #[error("{0}")]
     VaultHandlerError(#[from] VaultHandlerError),
+    #[error("vault not found")]
+    VaultNotFound,
+    #[error("invalid name")]
+    InvalidName,
+    #[error("another vault named {0} exists")]
+    DuplicateName(String),
+    #[error("invalid mount point: {0}")]
+    InvalidMountPoint(String),
+    #[error("invalid data dir: {0}")]
+    InvalidDataDir(String),
+    #[error("invalid restart policy {0}")]
+    InvalidRestartPolicy(String),
+    #[error("idle timeout of {0} minutes is too long")]
+    InvalidIdleTimeout(u32),
+    #[error("vault is not unlocked for a limited time")]
+    NotTimeLimited,
+    #[error("another operation is running on the vault")]
+    VaultBusy,
+    #[error("schedule not found")]
+    ScheduleNotFound,
+    #[error("invalid schedule: {0}")]
+    InvalidSchedule(String),
 }
 
-static CUSTOM_ERROR: &str = "x-custom-tonic-error-vault_service_error";
+/// `ErrorInfo.domain` of our errors, statuses with other domains, or without details, are not ours.
+const DOMAIN: &str = "rencfs_desktop";
+
+impl VaultServiceError {
+    /// A week, longer than that is the same as never locking.
+    pub const MAX_IDLE_TIMEOUT_MINS: u32 = 7 * 24 * 60;
+
+    pub fn code(&self) -> Code {
+        use VaultHandlerError::*;
+
+        match self {
+            Self::VaultHandlerError(err) => match err {
+                InvalidPassword => Code::PermissionDenied,
+                PasswordRequired | MountPointBusy { .. } | MountPointNotEmpty { .. } | RencfsBinaryNotFound |
+                IncompatibleRencfsVersion { .. } | InvalidTransition { .. } => Code::FailedPrecondition,
+                CannotAccessKeyring => Code::Unavailable,
+                ProcessExited { .. } => Code::Aborted,
+                UnlockTimeout { .. } => Code::DeadlineExceeded,
+                Cancelled => Code::Cancelled,
+                CannotLockVault | CannotUnlockVault | CannotChangeMountPoint | CannotChangeDataDir | CannotChangePassword |
+                Database { .. } => Code::Internal,
+            },
+            Self::VaultNotFound | Self::ScheduleNotFound => Code::NotFound,
+            Self::NotTimeLimited => Code::FailedPrecondition,
+            Self::VaultBusy => Code::Aborted,
+            Self::DuplicateName(_) => Code::AlreadyExists,
+            Self::InvalidName | Self::InvalidMountPoint(_) | Self::InvalidDataDir(_) | Self::InvalidRestartPolicy(_) |
+            Self::InvalidIdleTimeout(_) | Self::InvalidSchedule(_) => Code::InvalidArgument,
+        }
+    }
+
+    /// `ErrorInfo.reason`, identifies the variant.
+    pub fn reason(&self) -> &'static str {
+        use VaultHandlerError::*;
+
+        match self {
+            Self::VaultHandlerError(err) => match err {
+                CannotLockVault => "CANNOT_LOCK_VAULT",
+                CannotUnlockVault => "CANNOT_UNLOCK_VAULT",
+                CannotChangeMountPoint => "CANNOT_CHANGE_MOUNT_POINT",
+                CannotChangeDataDir => "CANNOT_CHANGE_DATA_DIR",
+                CannotChangePassword => "CANNOT_CHANGE_PASSWORD",
+                InvalidPassword => "INVALID_PASSWORD",
+                PasswordRequired => "PASSWORD_REQUIRED",
+                CannotAccessKeyring => "CANNOT_ACCESS_KEYRING",
+                ProcessExited { .. } => "PROCESS_EXITED",
+                UnlockTimeout { .. } => "UNLOCK_TIMEOUT",
+                Cancelled => "CANCELLED",
+                RencfsBinaryNotFound => "RENCFS_BINARY_NOT_FOUND",
+                IncompatibleRencfsVersion { .. } => "INCOMPATIBLE_RENCFS_VERSION",
+                MountPointBusy { .. } => "MOUNT_POINT_BUSY",
+                MountPointNotEmpty { .. } => "MOUNT_POINT_NOT_EMPTY",
+                Database { .. } => "DATABASE",
+                InvalidTransition { .. } => "INVALID_TRANSITION",
+            },
+            Self::VaultNotFound => "VAULT_NOT_FOUND",
+            Self::InvalidName => "INVALID_NAME",
+            Self::DuplicateName(_) => "DUPLICATE_NAME",
+            Self::InvalidMountPoint(_) => "INVALID_MOUNT_POINT",
+            Self::InvalidDataDir(_) => "INVALID_DATA_DIR",
+            Self::InvalidRestartPolicy(_) => "INVALID_RESTART_POLICY",
+            Self::InvalidIdleTimeout(_) => "INVALID_IDLE_TIMEOUT",
+            Self::NotTimeLimited => "NOT_TIME_LIMITED",
+            Self::VaultBusy => "VAULT_BUSY",
+            Self::ScheduleNotFound => "SCHEDULE_NOT_FOUND",
+            Self::InvalidSchedule(_) => "INVALID_SCHEDULE",
+        }
+    }
+
+    /// `ErrorInfo.metadata`, the variant fields.
+    fn metadata(&self) -> HashMap<String, String> {
+        use VaultHandlerError::*;
+
+        let fields: Vec<(&str, String)> = match self {
+            Self::VaultHandlerError(err) => match err {
+                ProcessExited { cause } | Database { cause } => vec![("cause", cause.clone())],
+                UnlockTimeout { secs } => vec![("secs", secs.to_string())],
+                IncompatibleRencfsVersion { found, required } => vec![("found", found.clone()), ("required", required.clone())],
+                MountPointBusy { mount_point, processes } =>
+                    vec![("mount_point", mount_point.clone()), ("processes", processes.clone())],
+                MountPointNotEmpty { mount_point } => vec![("mount_point", mount_point.clone())],
+                InvalidTransition { from, to } => vec![("from", from.clone()), ("to", to.clone())],
+                _ => vec![],
+            },
+            Self::DuplicateName(name) => vec![("name", name.clone())],
+            Self::InvalidMountPoint(cause) | Self::InvalidDataDir(cause) | Self::InvalidSchedule(cause) => vec![("cause", cause.clone())],
+            Self::InvalidRestartPolicy(value) => vec![("value", value.clone())],
+            Self::InvalidIdleTimeout(value) => vec![("value", value.to_string())],
+            _ => vec![],
+        };
+        fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+    }
+
+    fn from_reason(reason: &str, metadata: &HashMap<String, String>) -> Option<Self> {
+        use VaultHandlerError::*;
+
+        let get = |key: &str| metadata.get(key).cloned().unwrap_or_default();
+        let err: VaultHandlerError = match reason {
+            "CANNOT_LOCK_VAULT" => CannotLockVault,
+            "CANNOT_UNLOCK_VAULT" => CannotUnlockVault,
+            "CANNOT_CHANGE_MOUNT_POINT" => CannotChangeMountPoint,
+            "CANNOT_CHANGE_DATA_DIR" => CannotChangeDataDir,
+            "CANNOT_CHANGE_PASSWORD" => CannotChangePassword,
+            "INVALID_PASSWORD" => InvalidPassword,
+            "PASSWORD_REQUIRED" => PasswordRequired,
+            "CANNOT_ACCESS_KEYRING" => CannotAccessKeyring,
+            "PROCESS_EXITED" => ProcessExited { cause: get("cause") },
+            "UNLOCK_TIMEOUT" => UnlockTimeout { secs: get("secs").parse().unwrap_or_default() },
+            "CANCELLED" => Cancelled,
+            "RENCFS_BINARY_NOT_FOUND" => RencfsBinaryNotFound,
+            "INCOMPATIBLE_RENCFS_VERSION" => IncompatibleRencfsVersion { found: get("found"), required: get("required") },
+            "MOUNT_POINT_BUSY" => MountPointBusy { mount_point: get("mount_point"), processes: get("processes") },
+            "MOUNT_POINT_NOT_EMPTY" => MountPointNotEmpty { mount_point: get("mount_point") },
+            "DATABASE" => Database { cause: get("cause") },
+            "INVALID_TRANSITION" => InvalidTransition { from: get("from"), to: get("to") },
+            "VAULT_NOT_FOUND" => return Some(Self::VaultNotFound),
+            "INVALID_NAME" => return Some(Self::InvalidName),
+            "DUPLICATE_NAME" => return Some(Self::DuplicateName(get("name"))),
+            "INVALID_MOUNT_POINT" => return Some(Self::InvalidMountPoint(get("cause"))),
+            "INVALID_DATA_DIR" => return Some(Self::InvalidDataDir(get("cause"))),
+            "INVALID_RESTART_POLICY" => return Some(Self::InvalidRestartPolicy(get("value"))),
+            "INVALID_IDLE_TIMEOUT" => return Some(Self::InvalidIdleTimeout(get("value").parse().unwrap_or_default())),
+            "NOT_TIME_LIMITED" => return Some(Self::NotTimeLimited),
+            "VAULT_BUSY" => return Some(Self::VaultBusy),
+            "SCHEDULE_NOT_FOUND" => return Some(Self::ScheduleNotFound),
+            "INVALID_SCHEDULE" => return Some(Self::InvalidSchedule(get("cause"))),
+            _ => return None,
+        };
+        Some(err.into())
+    }
+
+    /// The request field that was invalid, for validation errors.
+    fn field(&self) -> Option<&'static str> {
+        match self {
+            Self::InvalidName | Self::DuplicateName(_) => Some("name"),
+            Self::InvalidMountPoint(_) | Self::VaultHandlerError(VaultHandlerError::MountPointNotEmpty { .. }) => Some("mount_point"),
+            Self::InvalidDataDir(_) => Some("data_dir"),
+            Self::InvalidRestartPolicy(_) => Some("restart_policy"),
+            Self::InvalidIdleTimeout(_) => Some("idle_timeout_mins"),
+            _ => None,
+        }
+    }
+}
 
 impl TryFrom<Status> for VaultServiceError {
     type Error = ();
 
     fn try_from(status: Status) -> Result<Self, Self::Error> {
-        match status.code() {
-            tonic::Code::Internal => {
-                if let Some(err) = status.metadata().get(CUSTOM_ERROR) {
-                    Ok(serde_json::from_str(err.to_str().unwrap()).unwrap())
-                } else {
-                    Err(())
-                }
-            }
-            _ => Err(()),
+        let details = status.get_error_details();
+        let info = details.error_info().ok_or(())?;
+        if info.domain != DOMAIN {
+            return Err(());
         }
+        Self::from_reason(&info.reason, &info.metadata).ok_or(())
     }
 }
 
 impl From<VaultServiceError> for Status {
     fn from(e: VaultServiceError) -> Self {
-        let mut status = Status::internal(format!("internal error: {}", e));
-
-        status.metadata_mut().insert(CUSTOM_ERROR,
-                                     serde_json::to_string(&e)
-                                         .unwrap_or("could not serialize: {e}".to_string())
-                                         .parse()
-                                         .unwrap_or(tonic::metadata::MetadataValue::from_static("unable to create metadata value")));
-        status
+        let mut details = ErrorDetails::with_error_info(e.reason(), DOMAIN, e.metadata());
+        if let Some(field) = e.field() {
+            details.add_bad_request_violation(field, e.to_string());
+        }
+        Status::with_error_details(e.code(), e.to_string(), details)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// Every variant, with fields set so we notice if one is lost on the way, add new ones here.
+    fn all_errors() -> Vec<VaultServiceError> {
+        use VaultHandlerError::*;
+
+        let handler_errors = vec![
+            CannotLockVault,
+            CannotUnlockVault,
+            CannotChangeMountPoint,
+            CannotChangeDataDir,
+            CannotChangePassword,
+            InvalidPassword,
+            PasswordRequired,
+            CannotAccessKeyring,
+            ProcessExited { cause: "exit status: 1".to_string() },
+            UnlockTimeout { secs: 30 },
+            Cancelled,
+            RencfsBinaryNotFound,
+            IncompatibleRencfsVersion { found: "0.12.0".to_string(), required: ">=0.13.0, <0.14.0".to_string() },
+            MountPointBusy { mount_point: "/home/me/vault".to_string(), processes: "bash (1234)".to_string() },
+            MountPointNotEmpty { mount_point: "/home/me/vault".to_string() },
+            Database { cause: "database is locked".to_string() },
+            InvalidTransition { from: "migrating".to_string(), to: "unlocking".to_string() },
+        ];
+        handler_errors.into_iter().map(VaultServiceError::from).chain([
+            VaultServiceError::VaultNotFound,
+            VaultServiceError::InvalidName,
+            VaultServiceError::DuplicateName("work".to_string()),
+            VaultServiceError::InvalidMountPoint("not absolute".to_string()),
+            VaultServiceError::InvalidDataDir("not a dir".to_string()),
+            VaultServiceError::InvalidRestartPolicy("sometimes".to_string()),
+            VaultServiceError::InvalidIdleTimeout(VaultServiceError::MAX_IDLE_TIMEOUT_MINS + 1),
+            VaultServiceError::NotTimeLimited,
+            VaultServiceError::VaultBusy,
+            VaultServiceError::ScheduleNotFound,
+            VaultServiceError::InvalidSchedule("no days".to_string()),
+        ]).collect()
+    }
+
+    #[test]
+    fn round_trips_through_status() {
+        for err in all_errors() {
+            let status = Status::from(err.clone());
+            assert_eq!(status.code(), err.code(), "{:?}", err);
+            assert_eq!(status.message(), err.to_string());
+            assert_eq!(VaultServiceError::try_from(status), Ok(err));
+        }
+    }
+
+    #[test]
+    fn reasons_are_unique() {
+        let errors = all_errors();
+        let reasons: HashSet<_> = errors.iter().map(|err| err.reason()).collect();
+        assert_eq!(reasons.len(), errors.len());
+    }
+
+    #[test]
+    fn validation_errors_name_the_field() {
+        let status = Status::from(VaultServiceError::DuplicateName("work".to_string()));
+        let violations = status.get_error_details().bad_request().unwrap().field_violations.clone();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].field, "name");
+    }
+
+    #[test]
+    fn other_statuses_are_not_ours() {
+        assert_eq!(VaultServiceError::try_from(Status::internal("boom")), Err(()));
+
+        let details = ErrorDetails::with_error_info("VAULT_NOT_FOUND", "elsewhere", HashMap::new());
+        assert_eq!(VaultServiceError::try_from(Status::with_error_details(Code::NotFound, "gone", details)), Err(()));
+
+        let details = ErrorDetails::with_error_info("NOT_A_REASON", DOMAIN, HashMap::new());
+        assert_eq!(VaultServiceError::try_from(Status::with_error_details(Code::Internal, "what", details)), Err(()));
     }
 }
@@ -0,0 +1,140 @@
+use std::fmt;
+use std::str::FromStr;
+
+use tracing::{error, warn};
+
+use crate::dao::VaultDao;
+use crate::models::Vault;
+use crate::vault_handler::VaultHandlerError;
+
+/// Where a vault is in its lifecycle, stored in `vaults.state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VaultState {
+    #[default]
+    Locked,
+    Unlocking,
+    Unlocked,
+    Locking,
+    /// its data dir is being changed
+    Migrating,
+    /// the last lock, unlock or data dir change failed, the vault is not mounted but might need attention
+    Error,
+    /// the rencfs process exited on its own, it might still be restarted
+    Crashed,
+}
+
+impl VaultState {
+    pub const ALL: [VaultState; 7] = [VaultState::Locked, VaultState::Unlocking, VaultState::Unlocked, VaultState::Locking,
+        VaultState::Migrating, VaultState::Error, VaultState::Crashed];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VaultState::Locked => "locked",
+            VaultState::Unlocking => "unlocking",
+            VaultState::Unlocked => "unlocked",
+            VaultState::Locking => "locking",
+            VaultState::Migrating => "migrating",
+            VaultState::Error => "error",
+            VaultState::Crashed => "crashed",
+        }
+    }
+
+    /// Staying in the same state is always allowed.
+    pub fn can_transition(&self, to: VaultState) -> bool {
+        use VaultState::*;
+
+        *self == to || matches!((self, to),
+            (Locked | Error | Crashed, Unlocking | Migrating) |
+            (Unlocking, Unlocked | Locked | Error) |
+            (Unlocked | Error | Crashed, Locking) |
+            // the process can exit while we're stopping it
+            (Unlocked | Locking, Crashed) |
+            // unmounting failed because files are open there, it's still mounted
+            (Locking, Locked | Unlocked | Error) |
+            (Migrating, Locked | Error) |
+            // restarted by the supervisor
+            (Crashed, Unlocked) |
+            // nothing was mounted, there's nothing to do to lock it
+            (Error | Crashed, Locked)
+        )
+    }
+}
+
+impl FromStr for VaultState {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        VaultState::ALL.into_iter().find(|state| state.as_str() == s).ok_or(())
+    }
+}
+
+impl fmt::Display for VaultState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Vault {
+    pub fn state(&self) -> VaultState {
+        VaultState::from_str(&self.state).unwrap_or_else(|_| {
+            warn!(id = self.id, state = self.state, "Unknown vault state");
+            VaultState::Error
+        })
+    }
+}
+
+/// Moves the vault to `to` if it's allowed from the state in db, returns the previous state.
+pub fn transition(dao: &mut VaultDao, id: i32, to: VaultState) -> Result<VaultState, VaultHandlerError> {
+    use crate::schema::vaults::dsl::state;
+    use diesel::ExpressionMethods;
+
+    let from = dao.get(id).map_err(|err| {
+        error!(err = %err, id, "Cannot get vault");
+        VaultHandlerError::Database { cause: err.to_string() }
+    })?.state();
+    if !from.can_transition(to) {
+        warn!(id, %from, %to, "Invalid vault state transition");
+        return Err(VaultHandlerError::InvalidTransition { from: from.to_string(), to: to.to_string() });
+    }
+    if from != to {
+        dao.update(id, state.eq(to.as_str())).map_err(|err| {
+            error!(err = %err, id, "Cannot update vault state");
+            VaultHandlerError::Database { cause: err.to_string() }
+        })?;
+    }
+    Ok(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use VaultState::*;
+
+    #[test]
+    fn can_transition() {
+        // every state with where it can go, anything else is refused
+        let allowed = [
+            (Locked, vec![Locked, Unlocking, Migrating]),
+            (Unlocking, vec![Unlocking, Unlocked, Locked, Error]),
+            (Unlocked, vec![Unlocked, Locking, Crashed]),
+            (Locking, vec![Locking, Locked, Unlocked, Error, Crashed]),
+            (Migrating, vec![Migrating, Locked, Error]),
+            (Error, vec![Error, Unlocking, Migrating, Locking, Locked]),
+            (Crashed, vec![Crashed, Unlocking, Migrating, Locking, Unlocked, Locked]),
+        ];
+        assert_eq!(allowed.len(), VaultState::ALL.len());
+        for (from, to) in allowed {
+            for state in VaultState::ALL {
+                assert_eq!(from.can_transition(state), to.contains(&state), "{} -> {}", from, state);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_what_it_stores() {
+        for state in VaultState::ALL {
+            assert_eq!(state.as_str().parse(), Ok(state));
+        }
+        assert_eq!("mounted".parse::<VaultState>(), Err(()));
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use secret_service::EncryptionType;
+use tempfile::TempDir;
+use zbus::{interface, ObjectServer};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+use rencfs_desktop_common::keyring::Keyring;
+use rencfs_desktop_test_support::Bus;
+
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const COLLECTION_PATH: &str = "/org/freedesktop/secrets/collection/login";
+const SESSION_PATH: &str = "/org/freedesktop/secrets/session/1";
+const NO_PROMPT: &str = "/";
+
+/// (session, parameters, value, content type)
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+struct StoredItem {
+    path: OwnedObjectPath,
+    attributes: HashMap<String, String>,
+    secret: Vec<u8>,
+}
+
+type Store = Arc<Mutex<Vec<StoredItem>>>;
+
+/// The parts of `org.freedesktop.secrets` the keyring uses, plain sessions only and nothing ever locked.
+struct FakeService {
+    items: Store,
+}
+
+#[interface(name = "org.freedesktop.Secret.Service")]
+impl FakeService {
+    fn open_session(&self, algorithm: &str, _input: Value<'_>) -> zbus::fdo::Result<(OwnedValue, OwnedObjectPath)> {
+        if algorithm != "plain" {
+            return Err(zbus::fdo::Error::NotSupported(algorithm.to_string()));
+        }
+        Ok((OwnedValue::try_from(Value::from("")).unwrap(), path(SESSION_PATH)))
+    }
+
+    fn search_items(&self, attributes: HashMap<String, String>) -> (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) {
+        let items = self.items.lock().unwrap();
+        let unlocked = items.iter()
+            .filter(|item| attributes.iter().all(|(k, v)| item.attributes.get(k) == Some(v)))
+            .map(|item| item.path.clone())
+            .collect();
+        (unlocked, vec![])
+    }
+
+    fn unlock(&self, objects: Vec<OwnedObjectPath>) -> (Vec<OwnedObjectPath>, OwnedObjectPath) {
+        (objects, path(NO_PROMPT))
+    }
+
+    fn read_alias(&self, name: &str) -> OwnedObjectPath {
+        path(if name == "default" { COLLECTION_PATH } else { NO_PROMPT })
+    }
+}
+
+struct FakeCollection {
+    items: Store,
+    next_id: u32,
+}
+
+#[interface(name = "org.freedesktop.Secret.Collection")]
+impl FakeCollection {
+    async fn create_item(
+        &mut self,
+        properties: HashMap<String, OwnedValue>,
+        secret: Secret,
+        replace: bool,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> zbus::fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+        let attributes = properties.get("org.freedesktop.Secret.Item.Attributes")
+            .and_then(|value| HashMap::<String, String>::try_from(Value::from(value.try_clone().ok()?)).ok())
+            .ok_or_else(|| zbus::fdo::Error::InvalidArgs("missing attributes".to_string()))?;
+        let existing = if replace {
+            self.items.lock().unwrap().iter().find(|item| item.attributes == attributes).map(|item| item.path.clone())
+        } else {
+            None
+        };
+        let item_path = match existing {
+            Some(item_path) => {
+                let mut items = self.items.lock().unwrap();
+                items.iter_mut().find(|item| item.path == item_path).unwrap().secret = secret.2;
+                item_path
+            }
+            None => {
+                self.next_id += 1;
+                let item_path = path(&format!("{}/{}", COLLECTION_PATH, self.next_id));
+                self.items.lock().unwrap().push(StoredItem { path: item_path.clone(), attributes, secret: secret.2 });
+                server.at(&item_path, FakeItem { items: self.items.clone(), path: item_path.clone() }).await?;
+                item_path
+            }
+        };
+        Ok((item_path, path(NO_PROMPT)))
+    }
+
+    #[zbus(property)]
+    fn locked(&self) -> bool {
+        false
+    }
+}
+
+struct FakeItem {
+    items: Store,
+    path: OwnedObjectPath,
+}
+
+#[interface(name = "org.freedesktop.Secret.Item")]
+impl FakeItem {
+    fn get_secret(&self, session: OwnedObjectPath) -> zbus::fdo::Result<Secret> {
+        let items = self.items.lock().unwrap();
+        let item = items.iter().find(|item| item.path == self.path)
+            .ok_or_else(|| zbus::fdo::Error::UnknownObject(self.path.to_string()))?;
+        Ok((session, vec![], item.secret.clone(), "text/plain".to_string()))
+    }
+
+    async fn delete(&self, #[zbus(object_server)] server: &ObjectServer) -> zbus::fdo::Result<OwnedObjectPath> {
+        self.items.lock().unwrap().retain(|item| item.path != self.path);
+        server.remove::<Self, _>(&self.path).await?;
+        Ok(path(NO_PROMPT))
+    }
+
+    #[zbus(property)]
+    fn locked(&self) -> bool {
+        false
+    }
+}
+
+fn path(path: &str) -> OwnedObjectPath {
+    ObjectPath::try_from(path).unwrap().into()
+}
+
+async fn start_secret_service(bus: &Bus) -> zbus::Connection {
+    let items = Store::default();
+    zbus::connection::Builder::address(bus.address.as_str()).unwrap()
+        .name("org.freedesktop.secrets").unwrap()
+        .serve_at(SERVICE_PATH, FakeService { items: items.clone() }).unwrap()
+        .serve_at(COLLECTION_PATH, FakeCollection { items, next_id: 0 }).unwrap()
+        .build().await.unwrap()
+}
+
+// one test, the keyring finds the bus through the environment of the whole process
+#[tokio::test]
+async fn set_get_and_delete_password() {
+    let dir = TempDir::new().unwrap();
+    let bus = Bus::start(&dir.path().join("bus"));
+    let _service = start_secret_service(&bus).await;
+    std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &bus.address);
+
+    let keyring = Keyring::connect_with(EncryptionType::Plain).await.unwrap();
+    assert_eq!(keyring.get_password(1).await.unwrap(), None);
+
+    keyring.set_password(1, "first", "secret").await.unwrap();
+    keyring.set_password(2, "second", "other").await.unwrap();
+    assert_eq!(keyring.get_password(1).await.unwrap(), Some("secret".to_string()));
+    assert_eq!(keyring.get_password(2).await.unwrap(), Some("other".to_string()));
+
+    // saving it again replaces it
+    keyring.set_password(1, "first", "changed").await.unwrap();
+    assert_eq!(keyring.get_password(1).await.unwrap(), Some("changed".to_string()));
+
+    keyring.delete_password(1).await.unwrap();
+    assert_eq!(keyring.get_password(1).await.unwrap(), None);
+    assert_eq!(keyring.get_password(2).await.unwrap(), Some("other".to_string()));
+}
@@ -0,0 +1,51 @@
+use std::io;
+use std::sync::{Arc, RwLock};
+
+use tonic::{Request, Status};
+use tracing::{info, instrument, warn};
+
+use rencfs_desktop_common::auth::{generate_token, token_matches, write_token, TOKEN_METADATA};
+
+/// The token clients must send, a new one is generated on each daemon start.
+#[derive(Clone)]
+pub struct Auth {
+    token: Arc<RwLock<String>>,
+}
+
+impl Auth {
+    /// Generates a token and writes it to the token file.
+    pub fn init() -> io::Result<Self> {
+        let auth = Self::with_token(String::new());
+        auth.rotate()?;
+        Ok(auth)
+    }
+
+    /// Accepts `token` without writing it to the token file, for when clients get it some other way.
+    pub fn with_token(token: String) -> Self {
+        Self { token: Arc::new(RwLock::new(token)) }
+    }
+
+    /// Old token is rejected from now on, clients read the new one from the token file.
+    #[instrument(skip(self), err)]
+    pub fn rotate(&self) -> io::Result<()> {
+        let token = generate_token();
+        // write it first so if it fails we keep the old one, which clients still have
+        write_token(&token)?;
+        *self.token.write().unwrap() = token;
+        info!("Auth token rotated");
+        Ok(())
+    }
+
+    pub fn check(&self, request: Request<()>) -> Result<Request<()>, Status> {
+        let actual = request.metadata().get(TOKEN_METADATA)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        match actual {
+            Some(actual) if token_matches(&self.token.read().unwrap(), actual) => Ok(request),
+            _ => {
+                warn!("Rejected request with missing or invalid token");
+                Err(Status::unauthenticated("invalid token"))
+            }
+        }
+    }
+}
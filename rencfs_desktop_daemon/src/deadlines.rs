@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use diesel::SqliteConnection;
+use tokio::sync::{Mutex, Notify};
+use tracing::{error, info, instrument};
+
+use rencfs_desktop_common::dao::VaultDao;
+use rencfs_desktop_common::vault_state::VaultState;
+
+use crate::handlers::Handlers;
+
+/// Timers don't advance while suspended, so we check at least this often to catch deadlines passed meanwhile.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Locks vaults unlocked for a limited time when their `lock_at` passes.
+#[derive(Clone)]
+pub struct Deadlines(Arc<Notify>);
+
+impl Deadlines {
+    pub fn spawn(handlers: Handlers, db_conn: Arc<Mutex<SqliteConnection>>) -> Self {
+        let notify = Arc::new(Notify::new());
+        let changed = notify.clone();
+        tokio::spawn(async move {
+            loop {
+                let wait = match lock_expired(&handlers, &db_conn).await {
+                    Some(next) => Duration::from_secs(next.saturating_sub(now()) as u64).min(MAX_WAIT),
+                    None => MAX_WAIT,
+                };
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = changed.notified() => {}
+                }
+            }
+        });
+        Self(notify)
+    }
+
+    /// Call after changing a `lock_at`, so we wake up in time for it.
+    pub fn changed(&self) {
+        self.0.notify_one();
+    }
+}
+
+/// Unix time in seconds.
+pub fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Returns the next deadline of the vaults still unlocked.
+#[instrument(skip_all)]
+async fn lock_expired(handlers: &Handlers, db_conn: &Arc<Mutex<SqliteConnection>>) -> Option<i64> {
+    let vaults = {
+        let mut guard = db_conn.lock().await;
+        match VaultDao::new(&mut *guard).get_all(None) {
+            Ok(vaults) => vaults,
+            Err(err) => {
+                error!(err = %err, "Cannot get vaults");
+                return None;
+            }
+        }
+    };
+    let now = now();
+    let mut next = None;
+    for vault in vaults.iter().filter(|v| v.state() == VaultState::Unlocked) {
+        let Some(lock_at) = vault.lock_at else { continue; };
+        if lock_at > now {
+            next = Some(next.map_or(lock_at, |n: i64| n.min(lock_at)));
+            continue;
+        }
+        let id = vault.id as u32;
+        let Ok(mut handler) = handlers.try_get(id).await else {
+            // we don't wait for the operation running on it to not delay the others, we retry after MAX_WAIT
+            continue;
+        };
+        info!(id, "Time-limited unlock ended, locking");
+        // if files are open there it stays unlocked, the handler sent an event telling who uses it and we retry after
+        // MAX_WAIT, until they're closed
+        if let Err(err) = handler.lock(None).await {
+            error!(id, err = %err, "Cannot lock vault");
+        }
+    }
+
+    next
+}
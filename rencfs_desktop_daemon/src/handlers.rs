@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use diesel::SqliteConnection;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+use rencfs_desktop_common::engine::VaultEngine;
+use rencfs_desktop_common::events::EventBus;
+use rencfs_desktop_common::vault_handler::VaultHandler;
+use rencfs_desktop_common::vault_service_error::VaultServiceError;
+
+use crate::vault_crud;
+
+/// Vault handlers, each one behind its own lock so operations on different vaults run in parallel.
+///
+/// Operations on the same vault never overlap. Client requests use [Handlers::try_get] and are rejected with
+/// [VaultServiceError::VaultBusy] while another operation runs on the vault, instead of hanging behind a long unlock
+/// or data dir change. Background tasks use [Handlers::get] and wait for their turn, in the order they asked for it.
+#[derive(Clone)]
+pub struct Handlers {
+    // only held to look up or insert, never across an await
+    handlers: Arc<Mutex<HashMap<u32, Arc<AsyncMutex<VaultHandler>>>>>,
+    engine: Arc<dyn VaultEngine>,
+    db_conn: Arc<AsyncMutex<SqliteConnection>>,
+    events: EventBus,
+}
+
+impl Handlers {
+    pub fn new(handlers: HashMap<u32, VaultHandler>, engine: Arc<dyn VaultEngine>, db_conn: Arc<AsyncMutex<SqliteConnection>>,
+               events: EventBus) -> Self {
+        let handlers = handlers.into_iter()
+            .map(|(id, handler)| (id, Arc::new(AsyncMutex::new(handler))))
+            .collect();
+        Self {
+            handlers: Arc::new(Mutex::new(handlers)),
+            engine,
+            db_conn,
+            events,
+        }
+    }
+
+    /// Waits for the operation running on the vault, if any.
+    pub async fn get(&self, id: u32) -> Result<OwnedMutexGuard<VaultHandler>, VaultServiceError> {
+        Ok(self.handler(id).await?.lock_owned().await)
+    }
+
+    /// Fails with [VaultServiceError::VaultBusy] if an operation is running on the vault.
+    pub async fn try_get(&self, id: u32) -> Result<OwnedMutexGuard<VaultHandler>, VaultServiceError> {
+        self.handler(id).await?.try_lock_owned().map_err(|_| VaultServiceError::VaultBusy)
+    }
+
+    /// Forgets the handler of a deleted vault, callers still holding it can finish with it.
+    pub fn remove(&self, id: u32) {
+        self.handlers.lock().unwrap().remove(&id);
+    }
+
+    pub fn engine(&self) -> &Arc<dyn VaultEngine> {
+        &self.engine
+    }
+
+    /// Ids of the vaults we have a handler for.
+    pub fn ids(&self) -> Vec<u32> {
+        self.handlers.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Fails with [VaultServiceError::VaultNotFound] for ids of vaults that don't exist, so we don't keep handlers for
+    /// them.
+    async fn handler(&self, id: u32) -> Result<Arc<AsyncMutex<VaultHandler>>, VaultServiceError> {
+        if let Some(handler) = self.handlers.lock().unwrap().get(&id) {
+            return Ok(handler.clone());
+        }
+        vault_crud::get(&self.db_conn, id).await?;
+        Ok(self.handlers.lock().unwrap()
+            .entry(id)
+            .or_insert_with(|| {
+                let handler = VaultHandler::new(id, self.engine.clone(), self.db_conn.clone(), self.events.clone());
+                Arc::new(AsyncMutex::new(handler))
+            })
+            .clone())
+    }
+}
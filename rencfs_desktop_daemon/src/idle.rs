@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use diesel::SqliteConnection;
+use tokio::sync::Mutex;
+use tracing::{error, info, instrument, warn};
+
+use rencfs_desktop_common::activity::last_activity;
+use rencfs_desktop_common::dao::VaultDao;
+use rencfs_desktop_common::events::{EventBus, VaultEventKind};
+use rencfs_desktop_common::vault_handler::VaultHandlerError;
+use rencfs_desktop_common::vault_state::VaultState;
+
+use crate::handlers::Handlers;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// How long before locking we send [VaultEventKind::IdleWarning].
+const WARNING_BEFORE: Duration = Duration::from_secs(60);
+
+/// What we know about an unlocked vault with an idle timeout.
+struct Tracked {
+    last_active: SystemTime,
+    warned: bool,
+}
+
+/// Locks vaults that were not used for their `idle_timeout_mins`, sending a warning event shortly before.
+pub fn spawn(handlers: Handlers, db_conn: Arc<Mutex<SqliteConnection>>, events: EventBus) {
+    tokio::spawn(async move {
+        let mut tracked = HashMap::new();
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            check(&mut tracked, &handlers, &db_conn, &events).await;
+        }
+    });
+}
+
+#[instrument(skip_all)]
+async fn check(tracked: &mut HashMap<u32, Tracked>, handlers: &Handlers,
+               db_conn: &Arc<Mutex<SqliteConnection>>, events: &EventBus) {
+    let vaults = {
+        let mut guard = db_conn.lock().await;
+        match VaultDao::new(&mut *guard).get_all(None) {
+            Ok(vaults) => vaults,
+            Err(err) => {
+                error!(err = %err, "Cannot get vaults");
+                return;
+            }
+        }
+    };
+    let vaults: Vec<_> = vaults.into_iter().filter(|v| v.state() == VaultState::Unlocked && v.idle_timeout_mins > 0).collect();
+    // forget the ones locked meanwhile or with the timeout turned off, they start over if it's back on
+    tracked.retain(|id, _| vaults.iter().any(|v| v.id as u32 == *id));
+
+    let now = SystemTime::now();
+    for vault in vaults {
+        let id = vault.id as u32;
+        let entry = tracked.entry(id).or_insert_with(|| Tracked {
+            // first time we see it unlocked, count from now
+            last_active: now,
+            warned: false,
+        });
+        // taken each time, they can be changed while it's unlocked
+        let (mount_point, data_dir) = (PathBuf::from(&vault.mount_point), PathBuf::from(&vault.data_dir));
+        let activity = tokio::task::spawn_blocking(move || last_activity(&mount_point, &data_dir)).await.ok().flatten();
+        if let Some(activity) = activity.filter(|a| *a > entry.last_active) {
+            entry.last_active = activity.min(now);
+            entry.warned = false;
+        }
+
+        let timeout = Duration::from_secs(vault.idle_timeout_mins as u64 * 60);
+        let idle = now.duration_since(entry.last_active).unwrap_or_default();
+        if idle >= timeout {
+            let Ok(mut handler) = handlers.try_get(id).await else {
+                // an operation on it counts as using it
+                entry.last_active = now;
+                entry.warned = false;
+                continue;
+            };
+            info!(id, ?idle, "Locking idle vault");
+            match handler.lock(None).await {
+                Ok(_) => { tracked.remove(&id); }
+                // files open there count as using it, one was opened since we checked, so it's not idle
+                Err(VaultHandlerError::MountPointBusy { .. }) => {
+                    info!(id, "Vault is in use, not idle anymore");
+                    entry.last_active = now;
+                    entry.warned = false;
+                }
+                Err(err) => {
+                    // handler sent an error event, try again after another timeout
+                    warn!(id, err = %err, "Cannot lock idle vault");
+                    entry.last_active = now;
+                    entry.warned = false;
+                }
+            }
+        } else if !entry.warned && idle + WARNING_BEFORE >= timeout {
+            let left = timeout - idle;
+            events.emit(id, VaultEventKind::IdleWarning, Some(left.as_secs().to_string()));
+            entry.warned = true;
+        }
+    }
+}
@@ -0,0 +1,15 @@
+// the daemon's parts, main puts them together and integration tests build them with a fake engine
+
+pub mod vault_service;
+pub mod reconcile;
+pub mod shutdown;
+pub mod operations;
+pub mod vault_crud;
+pub mod socket;
+pub mod auth;
+pub mod idle;
+pub mod logind;
+pub mod deadlines;
+pub mod scheduler;
+pub mod handlers;
+pub mod schedule_crud;
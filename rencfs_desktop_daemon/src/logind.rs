@@ -0,0 +1,264 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use diesel::SqliteConnection;
+use tokio::sync::Mutex;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_stream::StreamExt;
+use tracing::{error, info, instrument, warn};
+use zbus::{Connection, MatchRule, MessageStream, proxy};
+use zbus::zvariant::{ObjectPath, OwnedFd, OwnedObjectPath};
+
+use rencfs_desktop_common::config::Config;
+use rencfs_desktop_common::dao::VaultDao;
+use rencfs_desktop_common::vault_handler::VaultHandlerError;
+use rencfs_desktop_common::vault_service_error::VaultServiceError;
+use rencfs_desktop_common::vault_state::VaultState;
+
+use crate::handlers::Handlers;
+
+const LOGIND_SERVICE: &str = "org.freedesktop.login1";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// logind's default `InhibitDelayMaxSec`, it doesn't wait longer for us before sleeping.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often we try again to lock vaults that were in use when the session was locked.
+const BUSY_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    /// Sleep and shutdown wait until the returned fd is closed, or logind's `InhibitDelayMaxSec` passes.
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn prepare_for_shutdown(&self, start: bool) -> zbus::Result<()>;
+}
+
+#[proxy(interface = "org.freedesktop.login1.Session", default_service = "org.freedesktop.login1")]
+trait Session {
+    #[zbus(property)]
+    fn user(&self) -> zbus::Result<(u32, OwnedObjectPath)>;
+}
+
+/// Locks the vaults with `lock_with_session` set when our session is locked, or the system sleeps or shuts down.
+///
+/// If logind is not reachable we only log it and retry, the vaults are then locked only by the user or when idle.
+pub fn spawn(handlers: Handlers, db_conn: Arc<Mutex<SqliteConnection>>) {
+    tokio::spawn(run(Config::load().logind_address, handlers, db_conn));
+}
+
+/// Watches logind at `address`, or on the system bus if not set, reconnecting with a backoff whenever the connection
+/// is lost.
+pub async fn run(address: Option<String>, handlers: Handlers, db_conn: Arc<Mutex<SqliteConnection>>) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let started = Instant::now();
+        match watch(address.as_deref(), &handlers, &db_conn).await {
+            Ok(_) => warn!("logind connection closed"),
+            Err(err) => warn!(err = %err, "Cannot watch logind, vaults won't be locked on sleep or session lock"),
+        }
+        if started.elapsed() >= MAX_BACKOFF {
+            // it was working, try again soon
+            backoff = INITIAL_BACKOFF;
+        }
+        info!(?backoff, "Reconnecting to logind");
+        tokio::time::sleep(backoff).await;
+        backoff = backoff.saturating_mul(2).min(MAX_BACKOFF);
+    }
+}
+
+/// Returns when the connection is closed, bad messages are only logged.
+async fn watch(address: Option<&str>, handlers: &Handlers, db_conn: &Arc<Mutex<SqliteConnection>>) -> zbus::Result<()> {
+    let conn = match address {
+        Some(address) => zbus::connection::Builder::address(address)?.build().await?,
+        None => Connection::system().await?,
+    };
+    let manager = ManagerProxy::new(&conn).await?;
+    let mut sleep = manager.receive_prepare_for_sleep().await?;
+    let mut shutdown = manager.receive_prepare_for_shutdown().await?;
+    // Lock and Unlock are sent on each session's own object, we check if it's ours when they come
+    let rule = MatchRule::builder()
+        .msg_type(zbus::message::Type::Signal)
+        .sender(LOGIND_SERVICE)?
+        .interface(SESSION_INTERFACE)?
+        .build();
+    let mut session_lock = MessageStream::for_match_rule(rule, &conn, None).await?;
+
+    let mut inhibitor = inhibit(&manager).await;
+    // locks vaults that were in use when the session was locked, until it's unlocked
+    let mut retry_busy: Option<JoinHandle<()>> = None;
+    info!("Watching logind");
+    loop {
+        tokio::select! {
+            Some(signal) = sleep.next() => {
+                match signal.args() {
+                    Ok(args) if args.start => {
+                        lock_vaults("sleep", handlers, db_conn).await;
+                        // let it sleep
+                        inhibitor.take();
+                    }
+                    // woke up, be ready for next time
+                    Ok(_) => inhibitor = inhibit(&manager).await,
+                    Err(err) => warn!(err = %err, "Invalid PrepareForSleep signal"),
+                }
+            }
+            Some(signal) = shutdown.next() => {
+                match signal.args() {
+                    Ok(args) if args.start => {
+                        lock_vaults("shutdown", handlers, db_conn).await;
+                        inhibitor.take();
+                    }
+                    Ok(_) => {}
+                    Err(err) => warn!(err = %err, "Invalid PrepareForShutdown signal"),
+                }
+            }
+            Some(msg) = session_lock.next() => {
+                let msg = match msg {
+                    Ok(msg) => msg,
+                    Err(err) => {
+                        warn!(err = %err, "Invalid session signal");
+                        continue;
+                    }
+                };
+                let header = msg.header();
+                let (Some(path), Some(member)) = (header.path(), header.member()) else { continue; };
+                if !matches!(member.as_str(), "Lock" | "Unlock") || !is_own_session(&conn, path).await {
+                    continue;
+                }
+                // on Unlock the user is back, vaults still in use stay unlocked
+                if let Some(task) = retry_busy.take() {
+                    task.abort();
+                }
+                if member.as_str() == "Lock" {
+                    let busy = lock_vaults("session lock", handlers, db_conn).await;
+                    if !busy.is_empty() {
+                        retry_busy = Some(tokio::spawn(lock_when_not_busy(busy, handlers.clone())));
+                    }
+                }
+            }
+            }
+            else => return Ok(()),
+        }
+    }
+}
+
+/// Delays sleep and shutdown until we locked the vaults.
+async fn inhibit(manager: &ManagerProxy<'_>) -> Option<OwnedFd> {
+    match manager.inhibit("sleep:shutdown", "rencfs_desktop", "Lock vaults", "delay").await {
+        Ok(fd) => Some(fd),
+        Err(err) => {
+            // we still get the signals, but the system might sleep before we're done
+            warn!(err = %err, "Cannot take logind inhibitor lock");
+            None
+        }
+    }
+}
+
+async fn is_own_session(conn: &Connection, path: &ObjectPath<'_>) -> bool {
+    let session = match SessionProxy::builder(conn).path(path.to_owned()) {
+        Ok(builder) => builder.build().await,
+        Err(err) => Err(err),
+    };
+    match session {
+        Ok(session) => match session.user().await {
+            Ok((uid, _)) => uid == unsafe { libc::getuid() },
+            Err(err) => {
+                warn!(err = %err, %path, "Cannot get session user");
+                false
+            }
+        },
+        Err(err) => {
+            warn!(err = %err, %path, "Cannot get session");
+            false
+        }
+    }
+}
+
+/// Returns the vaults that could not be locked because files are open there, they stay unlocked.
+///
+/// Stops waiting after [LOCK_TIMEOUT] so sleep isn't held up, vaults still locking then finish meanwhile.
+#[instrument(skip(handlers, db_conn))]
+async fn lock_vaults(reason: &str, handlers: &Handlers, db_conn: &Arc<Mutex<SqliteConnection>>) -> Vec<u32> {
+    let vaults = {
+        let mut guard = db_conn.lock().await;
+        match VaultDao::new(&mut *guard).get_all(None) {
+            Ok(vaults) => vaults,
+            Err(err) => {
+                error!(err = %err, "Cannot get vaults");
+                return vec![];
+            }
+        }
+    };
+    let mut tasks = JoinSet::new();
+    // also the ones being unlocked, restarted after a crash or moved, they end up unlocked
+    let to_lock = vaults.iter().filter(|v| v.lock_with_session == 1 && matches!(v.state(),
+        VaultState::Unlocking | VaultState::Unlocked | VaultState::Crashed | VaultState::Migrating));
+    for vault in to_lock {
+        let id = vault.id as u32;
+        let handlers = handlers.clone();
+        tasks.spawn(async move {
+            let handler = match handlers.try_get(id).await {
+                Err(VaultServiceError::VaultBusy) => {
+                    info!(id, "Waiting for the operation running on vault");
+                    handlers.get(id).await
+                }
+                res => res,
+            };
+            let Ok(mut handler) = handler else {
+                // deleted meanwhile
+                return (id, Ok(()));
+            };
+            info!(id, "Locking vault");
+            (id, handler.lock(None).await)
+        });
+    }
+    let mut busy = vec![];
+    let all_done = tokio::time::timeout(LOCK_TIMEOUT, async {
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((_, Ok(_))) => {}
+                // the handler sent an event telling who uses it, killing the mount would lose what they didn't save
+                Ok((id, Err(VaultHandlerError::MountPointBusy { .. }))) => {
+                    warn!(id, "Vault is in use, leaving it unlocked");
+                    busy.push(id);
+                }
+                Ok((id, Err(err))) => error!(id, err = %err, "Cannot lock vault"),
+                Err(err) => error!(err = %err, "Lock task failed"),
+            }
+        }
+    }).await;
+    if all_done.is_err() {
+        warn!("Timeout locking vaults, letting them finish in background");
+        tasks.detach_all();
+    }
+    busy
+}
+
+/// Tries again to lock `ids` until the files open there are closed.
+#[instrument(skip(handlers))]
+async fn lock_when_not_busy(mut ids: Vec<u32>, handlers: Handlers) {
+    while !ids.is_empty() {
+        tokio::time::sleep(BUSY_RETRY_INTERVAL).await;
+        let mut still_busy = vec![];
+        for id in ids {
+            let Ok(mut handler) = handlers.try_get(id).await else {
+                // an operation is running on it, maybe the user unlocked or locked it meanwhile
+                continue;
+            };
+            match handler.lock(None).await {
+                Ok(_) => info!(id, "Locked vault no longer in use"),
+                Err(VaultHandlerError::MountPointBusy { .. }) => still_busy.push(id),
+                Err(err) => error!(id, err = %err, "Cannot lock vault"),
+            }
+        }
+        ids = still_busy;
+    }
+}
@@ -10,19 +10,23 @@ use std::thread;
 
 use daemonize::Daemonize;
 use dotenvy::dotenv;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, watch};
 use tokio::task;
 use tonic::transport::Server;
-use tracing::{error, info, instrument, Level};
+use tracing::{error, info, instrument, Level, warn};
+use rencfs_desktop_common::config::Config;
+use rencfs_desktop_common::events::EventBus;
 use rencfs_desktop_common::is_debug;
 
 use rencfs_desktop_common::persistence::run_migrations;
-use rencfs_desktop_common::storage::{get_data_dir, get_logs_dir};
+use rencfs_desktop_common::storage::{get_data_dir, get_logs_dir, get_socket_path};
 
-use crate::vault_service::MyVaultService;
-use crate::vault_service::vault_service_server::VaultServiceServer;
-
-mod vault_service;
+use rencfs_desktop_daemon::{idle, logind, reconcile, scheduler, shutdown, socket};
+use rencfs_desktop_daemon::auth::Auth;
+use rencfs_desktop_daemon::deadlines::Deadlines;
+use rencfs_desktop_daemon::handlers::Handlers;
+use rencfs_desktop_daemon::vault_service::MyVaultService;
+use rencfs_desktop_daemon::vault_service::vault_service_server::VaultServiceServer;
 
 #[tokio::main]
 async fn main() {
@@ -128,16 +132,53 @@ async fn daemon_run_async() -> Result<(), Box<dyn std::error::Error>> {
         panic!("Cannot run migrations")
     });
     let db_conn = Arc::new(Mutex::new(conn));
+    let events = EventBus::new();
+    let engine = rencfs_desktop_common::engine::create(Config::load().engine);
+
+    info!("Reconciling vaults state");
+    let mut handlers = reconcile::reconcile(&engine, db_conn.clone(), &events).await;
+    reconcile::resume_data_dir_migrations(&mut handlers, &engine, db_conn.clone(), &events).await;
+    let handlers = Handlers::new(handlers, engine, db_conn.clone(), events.clone());
+    idle::spawn(handlers.clone(), db_conn.clone(), events.clone());
+    logind::spawn(handlers.clone(), db_conn.clone());
+    scheduler::spawn(handlers.clone(), db_conn.clone());
+    let deadlines = Deadlines::spawn(handlers.clone(), db_conn.clone());
 
     info!("Starting server");
-    let addr = "[::1]:50051".parse()?;
-    let service = MyVaultService::new(db_conn);
-
-    info!("Listening on {}", addr);
-    Server::builder()
-        .add_service(VaultServiceServer::new(service))
-        .serve(addr)
-        .await?;
+    let auth = Auth::init()?;
+    let service = MyVaultService::new(db_conn.clone(), handlers.clone(), events, auth.clone(), deadlines);
+    let service = VaultServiceServer::with_interceptor(service, move |request| auth.check(request));
+
+    // both servers stop on the same signal
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    tokio::spawn(async move {
+        shutdown::wait_for_signal().await;
+        let _ = shutdown_tx.send(());
+    });
+    let stopped = |mut rx: watch::Receiver<()>| async move {
+        let _ = rx.changed().await;
+    };
+
+    let socket_path = get_socket_path()?;
+    let listener = socket::bind(&socket_path)?;
+    info!("Listening on {}", socket_path.display());
+    let uds_server = Server::builder()
+        .add_service(service.clone())
+        .serve_with_incoming_shutdown(socket::incoming(listener), stopped(shutdown_rx.clone()));
+
+    if let Some(addr) = Config::load().tcp_address {
+        let addr = addr.parse()?;
+        warn!("Listening on {}, any local user can connect to it", addr);
+        let tcp_server = Server::builder()
+            .add_service(service)
+            .serve_with_shutdown(addr, stopped(shutdown_rx));
+        tokio::try_join!(uds_server, tcp_server)?;
+    } else {
+        uds_server.await?;
+    }
+    socket::remove(&socket_path);
+
+    shutdown::shutdown(handlers, db_conn).await;
 
     Ok(())
 }
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+use tracing::{info, instrument};
+
+use rencfs_desktop_common::progress::{Progress, ProgressReporter};
+use rencfs_desktop_common::vault_handler::VaultHandlerError;
+use rencfs_desktop_common::vault_service_error::VaultServiceError;
+
+use crate::vault_service::operation_progress::State;
+use crate::vault_service::OperationProgress;
+
+/// Finished operations are kept this long so clients that start watching late still get the result.
+const KEEP_FINISHED: Duration = Duration::from_secs(60);
+
+type OperationResult = Option<Result<(), VaultHandlerError>>;
+
+#[derive(Clone)]
+struct Operation {
+    reporter: ProgressReporter,
+    progress_rx: watch::Receiver<Progress>,
+    result_rx: watch::Receiver<OperationResult>,
+}
+
+/// Long-running vault operations, each one runs on its own task and can be watched and cancelled by id.
+pub struct Operations {
+    next_id: AtomicU64,
+    operations: Mutex<HashMap<u64, Operation>>,
+}
+
+impl Operations {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            next_id: AtomicU64::new(1),
+            operations: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Spawns the future returned by `f` and returns the operation id.
+    #[instrument(skip_all)]
+    pub fn start<F, Fut>(self: &Arc<Self>, f: F) -> u64
+        where F: FnOnce(ProgressReporter) -> Fut, Fut: Future<Output=Result<(), VaultHandlerError>> + Send + 'static {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (reporter, progress_rx) = ProgressReporter::new();
+        let (result_tx, result_rx) = watch::channel(None);
+        self.operations.lock().unwrap().insert(id, Operation { reporter: reporter.clone(), progress_rx, result_rx });
+        info!(id, "Operation started");
+
+        let fut = f(reporter);
+        let this = self.clone();
+        tokio::spawn(async move {
+            let res = fut.await;
+            info!(id, ?res, "Operation finished");
+            let _ = result_tx.send(Some(res));
+            tokio::time::sleep(KEEP_FINISHED).await;
+            this.operations.lock().unwrap().remove(&id);
+        });
+
+        id
+    }
+
+    /// `false` if there is no such operation.
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.operations.lock().unwrap().get(&id) {
+            Some(op) => {
+                op.reporter.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Streams progress until the operation finishes, `None` if there is no such operation.
+    pub fn watch(&self, id: u64) -> Option<ReceiverStream<Result<OperationProgress, Status>>> {
+        let mut op = self.operations.lock().unwrap().get(&id)?.clone();
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            loop {
+                let result = op.result_rx.borrow_and_update().clone();
+                let progress = op.progress_rx.borrow_and_update().clone();
+                let finished = result.is_some();
+                let msg = match result {
+                    None => Ok(to_message(id, State::Running, &progress)),
+                    Some(Ok(_)) => Ok(to_message(id, State::Succeeded, &progress)),
+                    Some(Err(VaultHandlerError::Cancelled)) => Ok(to_message(id, State::Cancelled, &progress)),
+                    Some(Err(err)) => Err(VaultServiceError::from(err).into()),
+                };
+                if tx.send(msg).await.is_err() || finished {
+                    // client went away or we're done
+                    return;
+                }
+                tokio::select! {
+                    _ = op.progress_rx.changed() => {}
+                    _ = op.result_rx.changed() => {}
+                }
+            }
+        });
+
+        Some(ReceiverStream::new(rx))
+    }
+}
+
+fn to_message(id: u64, state: State, progress: &Progress) -> OperationProgress {
+    OperationProgress {
+        operation_id: id,
+        state: state.into(),
+        phase: progress.phase.clone(),
+        percent: progress.percent,
+        bytes_done: progress.bytes_done,
+        bytes_total: progress.bytes_total,
+        eta_secs: progress.eta.map(|eta| eta.as_secs()),
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use diesel::{ExpressionMethods, SqliteConnection};
+use tokio::sync::Mutex;
+use tracing::{error, info, instrument};
+
+use rencfs_desktop_common::dao::VaultDao;
+use rencfs_desktop_common::data_migration::Migration;
+use rencfs_desktop_common::engine::{Reconciled, VaultEngine};
+use rencfs_desktop_common::events::EventBus;
+use rencfs_desktop_common::vault_handler::VaultHandler;
+use rencfs_desktop_common::vault_state::VaultState;
+
+#[derive(Debug, Default)]
+struct Report {
+    adopted: Vec<i32>,
+    /// served but the vault wasn't mounted
+    stopped: Vec<i32>,
+    /// mounted but nothing serving it
+    stale_mounts: Vec<i32>,
+    /// db said unlocked, or in the middle of an operation, but nothing was running
+    marked_locked: Vec<i32>,
+    /// left running but not matching any vault, left alone
+    orphans: Vec<String>,
+}
+
+/// Matches what the engine finds mounted and running with `vaults` rows, left from a previous daemon run.
+///
+/// Vaults still mounted and served are adopted and returned as handlers, everything else is cleaned up by the engine
+/// and marked locked. Vaults in Error or Crashed state with nothing left running keep it, so the user still sees it.
+///
+/// States are set directly, not through [VaultState::can_transition], as this is what's really there.
+#[instrument(skip_all)]
+pub async fn reconcile(engine: &Arc<dyn VaultEngine>, db_conn: Arc<Mutex<SqliteConnection>>, events: &EventBus) -> HashMap<u32, VaultHandler> {
+    let mut handlers = HashMap::new();
+    let mut report = Report::default();
+
+    let vaults = {
+        let mut guard = db_conn.lock().await;
+        match VaultDao::new(&mut *guard).get_all(None) {
+            Ok(vaults) => vaults,
+            Err(err) => {
+                error!(err = %err, "Cannot get vaults");
+                return handlers;
+            }
+        }
+    };
+    report.orphans = engine.orphans(&vaults);
+
+    for vault in vaults {
+        let state = vault.state();
+        let new_state = match engine.reconcile(&vault).await {
+            Reconciled::Adopted(mount) => {
+                info!(id = vault.id, "Adopting unlocked vault");
+                report.adopted.push(vault.id);
+                handlers.insert(vault.id as u32, VaultHandler::adopt(vault.clone(), mount, engine.clone(), db_conn.clone(), events.clone()));
+                VaultState::Unlocked
+            }
+            Reconciled::Stopped => {
+                report.stopped.push(vault.id);
+                VaultState::Locked
+            }
+            Reconciled::StaleMount => {
+                report.stale_mounts.push(vault.id);
+                VaultState::Locked
+            }
+            Reconciled::NotMounted => match state {
+                VaultState::Locked | VaultState::Error | VaultState::Crashed => state,
+                _ => {
+                    report.marked_locked.push(vault.id);
+                    VaultState::Locked
+                }
+            },
+        };
+        if new_state != state {
+            use rencfs_desktop_common::schema::vaults::dsl::state as state_col;
+
+            let mut guard = db_conn.lock().await;
+            if let Err(err) = VaultDao::new(&mut *guard).update(vault.id, state_col.eq(new_state.as_str())) {
+                error!(err = %err, id = vault.id, "Cannot update vault state");
+            }
+        }
+    }
+
+    info!(adopted = ?report.adopted, stopped = ?report.stopped, stale_mounts = ?report.stale_mounts,
+        marked_locked = ?report.marked_locked, orphans = ?report.orphans, "Reconciliation report");
+
+    handlers
+}
+
+/// Finishes data dir changes interrupted by a previous daemon stop.
+#[instrument(skip_all)]
+pub async fn resume_data_dir_migrations(handlers: &mut HashMap<u32, VaultHandler>, engine: &Arc<dyn VaultEngine>,
+                                        db_conn: Arc<Mutex<SqliteConnection>>, events: &EventBus) {
+    for migration in Migration::pending() {
+        let id = migration.vault_id as u32;
+        info!(id, "Resuming data dir migration");
+        let handler = handlers.entry(id).or_insert_with(|| VaultHandler::new(id, engine.clone(), db_conn.clone(), events.clone()));
+        if let Err(err) = handler.resume_data_dir_change(migration).await {
+            error!(id, err = %err, "Cannot resume data dir migration");
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use diesel::result::Error::NotFound;
+use diesel::SqliteConnection;
+use tokio::sync::Mutex;
+use tracing::{error, instrument};
+
+use rencfs_desktop_common::dao::{ScheduleDao, VaultDao};
+use rencfs_desktop_common::models::{NewSchedule, Schedule, UpdateSchedule};
+use rencfs_desktop_common::schedule::{ALL_DAYS, MINUTES_PER_DAY, ScheduleAction};
+use rencfs_desktop_common::vault_handler::VaultHandlerError;
+use rencfs_desktop_common::vault_service_error::VaultServiceError;
+
+use crate::deadlines::now;
+use crate::vault_service::{CreateScheduleRequest, ScheduleInfo, UpdateScheduleRequest};
+
+pub async fn list(db_conn: &Arc<Mutex<SqliteConnection>>, vault_id: u32) -> Result<Vec<Schedule>, VaultServiceError> {
+    let mut guard = db_conn.lock().await;
+    VaultDao::new(&mut *guard).get(vault_id as i32).map_err(map_vault_error)?;
+    ScheduleDao::new(&mut *guard).get_by_vault(vault_id as i32).map_err(map_db_error)
+}
+
+pub async fn get(db_conn: &Arc<Mutex<SqliteConnection>>, id: u32) -> Result<Schedule, VaultServiceError> {
+    let mut guard = db_conn.lock().await;
+    ScheduleDao::new(&mut *guard).get(id as i32).map_err(map_db_error)
+}
+
+#[instrument(skip(db_conn), err)]
+pub async fn create(db_conn: &Arc<Mutex<SqliteConnection>>, request: CreateScheduleRequest) -> Result<Schedule, VaultServiceError> {
+    validate_action(&request.action)?;
+    validate_minute_of_day(request.minute_of_day)?;
+    validate_days(request.days)?;
+
+    let new_schedule = NewSchedule {
+        vault_id: request.vault_id as i32,
+        action: request.action,
+        minute_of_day: request.minute_of_day as i32,
+        days: request.days as i32,
+        enabled: if request.enabled { 1 } else { 0 },
+        // don't run for times that passed before it existed
+        last_run: Some(now()),
+    };
+    let mut guard = db_conn.lock().await;
+    VaultDao::new(&mut *guard).get(request.vault_id as i32).map_err(map_vault_error)?;
+    ScheduleDao::new(&mut *guard).insert(&new_schedule).map_err(map_db_error)
+}
+
+#[instrument(skip(db_conn), err)]
+pub async fn update(db_conn: &Arc<Mutex<SqliteConnection>>, request: &UpdateScheduleRequest) -> Result<(), VaultServiceError> {
+    if let Some(action) = &request.action {
+        validate_action(action)?;
+    }
+    if let Some(minute_of_day) = request.minute_of_day {
+        validate_minute_of_day(minute_of_day)?;
+    }
+    if let Some(days) = request.days {
+        validate_days(days)?;
+    }
+    if request.action.is_none() && request.minute_of_day.is_none() && request.days.is_none() && request.enabled.is_none() {
+        // diesel fails on empty changesets
+        return Ok(());
+    }
+
+    let changes = UpdateSchedule {
+        action: request.action.clone(),
+        minute_of_day: request.minute_of_day.map(|v| v as i32),
+        days: request.days.map(|v| v as i32),
+        enabled: request.enabled.map(|v| if v { 1 } else { 0 }),
+        // like on create, moving it to a time that just passed should not run it right away
+        last_run: Some(now()),
+    };
+    let mut guard = db_conn.lock().await;
+    let mut dao = ScheduleDao::new(&mut *guard);
+    dao.get(request.id as i32).map_err(map_db_error)?;
+    dao.update(request.id as i32, changes).map_err(map_db_error)
+}
+
+#[instrument(skip(db_conn), err)]
+pub async fn delete(db_conn: &Arc<Mutex<SqliteConnection>>, id: u32) -> Result<(), VaultServiceError> {
+    let mut guard = db_conn.lock().await;
+    ScheduleDao::new(&mut *guard).delete(id as i32).map_err(map_db_error)
+}
+
+fn validate_action(action: &str) -> Result<(), VaultServiceError> {
+    ScheduleAction::from_str(action)
+        .map(|_| ())
+        .map_err(|_| VaultServiceError::InvalidSchedule(format!("unknown action {}", action)))
+}
+
+fn validate_minute_of_day(minute_of_day: u32) -> Result<(), VaultServiceError> {
+    if minute_of_day >= MINUTES_PER_DAY as u32 {
+        return Err(VaultServiceError::InvalidSchedule("time must be before midnight".to_string()));
+    }
+    Ok(())
+}
+
+fn validate_days(days: u32) -> Result<(), VaultServiceError> {
+    if days == 0 || days & !(ALL_DAYS as u32) != 0 {
+        return Err(VaultServiceError::InvalidSchedule("choose at least one day of the week".to_string()));
+    }
+    Ok(())
+}
+
+fn map_vault_error(err: diesel::result::Error) -> VaultServiceError {
+    match err {
+        NotFound => VaultServiceError::VaultNotFound,
+        err => map_db_error(err),
+    }
+}
+
+fn map_db_error(err: diesel::result::Error) -> VaultServiceError {
+    match err {
+        NotFound => VaultServiceError::ScheduleNotFound,
+        err => {
+            error!(err = %err, "Database error");
+            VaultHandlerError::Database { cause: err.to_string() }.into()
+        }
+    }
+}
+
+pub fn to_schedule_info(schedule: &Schedule) -> ScheduleInfo {
+    ScheduleInfo {
+        id: schedule.id as u32,
+        vault_id: schedule.vault_id as u32,
+        action: schedule.action.clone(),
+        minute_of_day: schedule.minute_of_day as u32,
+        days: schedule.days as u32,
+        enabled: schedule.enabled == 1,
+        last_run: schedule.last_run,
+    }
+}
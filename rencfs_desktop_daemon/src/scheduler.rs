@@ -0,0 +1,116 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use diesel::SqliteConnection;
+use tokio::sync::Mutex;
+use tracing::{error, info, instrument, warn};
+
+use rencfs_desktop_common::dao::{ScheduleDao, VaultDao};
+use rencfs_desktop_common::models::{Schedule, UpdateSchedule};
+use rencfs_desktop_common::progress::ProgressReporter;
+use rencfs_desktop_common::schedule::{latest_occurrence, ScheduleAction};
+use rencfs_desktop_common::vault_handler::VaultHandlerError;
+use rencfs_desktop_common::vault_state::VaultState;
+
+use crate::deadlines::now;
+use crate::handlers::Handlers;
+
+/// Unlocks missed by more than this, because we were suspended or not running, are skipped. Unlocking hours later
+/// would expose the vault when nobody expects it, while a missed lock is still wanted.
+const MISSED_UNLOCK_GRACE: i64 = 5 * 60;
+
+/// Runs the enabled schedules when their time comes.
+///
+/// Timers don't advance while suspended, so after waking up we run what was missed since each schedule's `last_run`,
+/// the same as after the daemon was stopped.
+pub fn spawn(handlers: Handlers, db_conn: Arc<Mutex<SqliteConnection>>) {
+    tokio::spawn(async move {
+        loop {
+            run_due(&handlers, &db_conn).await;
+            // schedules are in whole minutes, wake up right after the next one starts
+            let wait = 60 - now().rem_euclid(60) + 1;
+            tokio::time::sleep(Duration::from_secs(wait as u64)).await;
+        }
+    });
+}
+
+#[instrument(skip_all)]
+async fn run_due(handlers: &Handlers, db_conn: &Arc<Mutex<SqliteConnection>>) {
+    let schedules = {
+        let mut guard = db_conn.lock().await;
+        match ScheduleDao::new(&mut *guard).get_enabled() {
+            Ok(schedules) => schedules,
+            Err(err) => {
+                error!(err = %err, "Cannot get schedules");
+                return;
+            }
+        }
+    };
+    let now = now();
+    for schedule in schedules {
+        let Some(due) = latest_occurrence(schedule.minute_of_day, schedule.days, now) else { continue; };
+        if schedule.last_run.is_some_and(|last_run| due <= last_run) {
+            continue;
+        }
+        let Ok(action) = ScheduleAction::from_str(&schedule.action) else {
+            warn!(id = schedule.id, action = schedule.action, "Unknown schedule action");
+            continue;
+        };
+        if action == ScheduleAction::Unlock && now - due > MISSED_UNLOCK_GRACE {
+            info!(id = schedule.id, vault_id = schedule.vault_id, due, "Skipping missed unlock");
+        } else if !run(&schedule, action, handlers, db_conn).await {
+            // not marked as run, so it's tried again next minute
+            continue;
+        }
+        // other failures are not retried, the handler sent an error event for them
+        let mut guard = db_conn.lock().await;
+        let changes = UpdateSchedule { last_run: Some(now), ..Default::default() };
+        if let Err(err) = ScheduleDao::new(&mut *guard).update(schedule.id, changes) {
+            error!(id = schedule.id, err = %err, "Cannot update schedule last run");
+        }
+    }
+}
+
+/// Returns `false` if it should be tried again, when the vault is busy.
+async fn run(schedule: &Schedule, action: ScheduleAction, handlers: &Handlers, db_conn: &Arc<Mutex<SqliteConnection>>) -> bool {
+    let id = schedule.vault_id as u32;
+    let locked = {
+        let mut guard = db_conn.lock().await;
+        match VaultDao::new(&mut *guard).get(id as i32) {
+            Ok(vault) => vault.state() != VaultState::Unlocked,
+            Err(err) => {
+                error!(id, err = %err, "Cannot get vault");
+                return true;
+            }
+        }
+    };
+    if locked == (action == ScheduleAction::Lock) {
+        // already there
+        return true;
+    }
+
+    info!(id, schedule = schedule.id, action = action.as_str(), "Running schedule");
+    // waits for the operation running on it, if any
+    let Ok(mut handler) = handlers.get(id).await else {
+        // deleted meanwhile
+        return true;
+    };
+    let res = match action {
+        // password comes from keyring, without it we get PasswordRequired
+        ScheduleAction::Unlock => handler.unlock(None, &ProgressReporter::noop()).await,
+        ScheduleAction::Lock => handler.lock(None).await,
+    };
+    match res {
+        Ok(_) => true,
+        // files are open there, the handler sent an event telling who uses it, we try again until they're closed
+        Err(VaultHandlerError::MountPointBusy { .. }) => {
+            warn!(id, schedule = schedule.id, "Vault is in use, locking it later");
+            false
+        }
+        Err(err) => {
+            error!(id, err = %err, "Cannot run schedule");
+            true
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use diesel::connection::SimpleConnection;
+use diesel::SqliteConnection;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tracing::{error, info, instrument, warn};
+
+use rencfs_desktop_common::config::Config;
+use rencfs_desktop_common::vault_handler::VaultHandlerError;
+
+use crate::handlers::Handlers;
+
+/// How long we wait for all vaults to lock before giving up on the ones left, well within what service managers wait
+/// for a stop.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Resolves on SIGTERM or SIGINT.
+pub async fn wait_for_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("Cannot install SIGTERM handler");
+    let mut sigint = signal(SignalKind::interrupt()).expect("Cannot install SIGINT handler");
+    tokio::select! {
+        _ = sigterm.recv() => info!("Received SIGTERM"),
+        _ = sigint.recv() => info!("Received SIGINT"),
+    }
+}
+
+/// Locks all vaults, or leaves them mounted if `keep_mounted_on_exit` is set, and flushes the db.
+///
+/// Vaults in use are left mounted too, the next daemon adopts them. Vaults mounted by an engine that can't leave them
+/// mounted, like the in-process one, go away with the daemon.
+#[instrument(skip_all)]
+pub async fn shutdown(handlers: Handlers, db_conn: Arc<Mutex<SqliteConnection>>) {
+    let can_detach = handlers.engine().can_detach();
+    let keep_mounted = Config::load().keep_mounted_on_exit && can_detach;
+    info!(keep_mounted, "Shutting down");
+
+    let mut pending: HashSet<u32> = handlers.ids().into_iter().collect();
+    let mut tasks = JoinSet::new();
+    for &id in &pending {
+        let handlers = handlers.clone();
+        tasks.spawn(async move {
+            // includes waiting for the operation running on it
+            let Ok(mut handler) = handlers.get(id).await else {
+                // deleted meanwhile
+                return (id, Ok(()));
+            };
+            let res = if keep_mounted {
+                handler.detach().await;
+                Ok(())
+            } else {
+                match handler.lock(None).await {
+                    // killing it would lose what the programs using it didn't save, it's left mounted instead
+                    Err(VaultHandlerError::MountPointBusy { processes, .. }) if can_detach => {
+                        warn!(id, processes, "Vault is in use, leaving it mounted");
+                        handler.detach().await;
+                        Ok(())
+                    }
+                    res => res,
+                }
+            };
+            (id, res)
+        });
+    }
+    let all_done = tokio::time::timeout(LOCK_TIMEOUT, async {
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((id, res)) => {
+                    if let Err(err) = res {
+                        error!(id, err = %err, "Cannot lock vault");
+                    }
+                    pending.remove(&id);
+                    handlers.remove(id);
+                }
+                Err(err) => error!(err = %err, "Lock task failed"),
+            }
+        }
+    }).await;
+    if all_done.is_err() {
+        warn!(?pending, "Timeout locking vaults");
+        tasks.abort_all();
+    }
+
+    let mut conn = db_conn.lock().await;
+    if let Err(err) = conn.batch_execute("PRAGMA wal_checkpoint(TRUNCATE);") {
+        error!(err = %err, "Cannot checkpoint db");
+    }
+    info!("Shutdown complete");
+}
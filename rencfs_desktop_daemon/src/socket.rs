@@ -0,0 +1,57 @@
+use std::{fs, io};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use tokio::net::{UnixListener, UnixStream};
+use tokio_stream::{Stream, StreamExt};
+use tokio_stream::wrappers::UnixListenerStream;
+use tracing::{info, instrument, warn};
+
+/// Binds the daemon socket, only the owner can connect to it.
+///
+/// A socket file left by a daemon that didn't exit cleanly is replaced, but if another daemon is still listening on it
+/// we fail.
+#[instrument]
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another daemon is already running"));
+        }
+        info!("Removing stale socket");
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+    Ok(listener)
+}
+
+/// Accepted connections, dropping the ones from other users.
+pub fn incoming(listener: UnixListener) -> impl Stream<Item=io::Result<UnixStream>> {
+    UnixListenerStream::new(listener).filter(|conn| match conn {
+        Ok(stream) => is_owner(stream),
+        // let the server log it
+        Err(_) => true,
+    })
+}
+
+fn is_owner(stream: &UnixStream) -> bool {
+    let uid = unsafe { libc::getuid() };
+    match stream.peer_cred() {
+        Ok(cred) if cred.uid() == uid => true,
+        Ok(cred) => {
+            warn!(peer_uid = cred.uid(), peer_pid = ?cred.pid(), "Rejected connection from another user");
+            false
+        }
+        Err(err) => {
+            warn!(err = %err, "Cannot get peer credentials, rejecting connection");
+            false
+        }
+    }
+}
+
+pub fn remove(path: &Path) {
+    if let Err(err) = fs::remove_file(path) {
+        warn!(err = %err, "Cannot remove socket");
+    }
+}
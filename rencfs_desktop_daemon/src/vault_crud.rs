@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use diesel::result::DatabaseErrorKind::UniqueViolation;
+use diesel::result::Error::{DatabaseError, NotFound};
+use diesel::SqliteConnection;
+use tokio::sync::Mutex;
+use tracing::{error, instrument};
+
+use rencfs_desktop_common::dao::VaultDao;
+use rencfs_desktop_common::is_debug;
+use rencfs_desktop_common::models::{NewVault, UpdateVault, Vault};
+use rencfs_desktop_common::supervisor::RestartPolicy;
+use rencfs_desktop_common::vault_handler::VaultHandlerError;
+use rencfs_desktop_common::vault_service_error::VaultServiceError;
+use rencfs_desktop_common::vault_state::VaultState;
+
+use crate::vault_service::{CreateVaultRequest, UpdateVaultRequest, vault_info, VaultInfo};
+
+pub async fn list(db_conn: &Arc<Mutex<SqliteConnection>>) -> Result<Vec<Vault>, VaultServiceError> {
+    let mut guard = db_conn.lock().await;
+    VaultDao::new(&mut *guard).get_all(None).map_err(map_db_error)
+}
+
+pub async fn get(db_conn: &Arc<Mutex<SqliteConnection>>, id: u32) -> Result<Vault, VaultServiceError> {
+    let mut guard = db_conn.lock().await;
+    VaultDao::new(&mut *guard).get(id as i32).map_err(map_db_error)
+}
+
+#[instrument(skip(db_conn), err)]
+pub async fn create(db_conn: &Arc<Mutex<SqliteConnection>>, request: CreateVaultRequest) -> Result<Vault, VaultServiceError> {
+    let name = validate_name(&request.name)?;
+    validate_restart_policy(&request.restart_policy)?;
+    validate_idle_timeout(request.idle_timeout_mins)?;
+    validate_mount_point(db_conn, None, &request.mount_point).await?;
+    validate_data_dir(db_conn, None, &request.data_dir).await?;
+
+    let new_vault = NewVault {
+        name: name.clone(),
+        mount_point: request.mount_point,
+        data_dir: request.data_dir,
+        remember_password: if request.remember_password { 1 } else { 0 },
+        restart_policy: request.restart_policy,
+        idle_timeout_mins: request.idle_timeout_mins as i32,
+        lock_with_session: if request.lock_with_session { 1 } else { 0 },
+    };
+    let mut guard = db_conn.lock().await;
+    let mut dao = VaultDao::new(&mut *guard);
+    dao.insert(&new_vault).map_err(|err| map_name_error(err, &name))?;
+    dao.get_by_name(&name).map_err(map_db_error)
+}
+
+/// Returns `true` if the name was changed.
+#[instrument(skip(db_conn), err)]
+pub async fn update(db_conn: &Arc<Mutex<SqliteConnection>>, request: &UpdateVaultRequest) -> Result<bool, VaultServiceError> {
+    let name = request.name.as_deref().map(validate_name).transpose()?;
+    if let Some(restart_policy) = &request.restart_policy {
+        validate_restart_policy(restart_policy)?;
+    }
+    if let Some(idle_timeout_mins) = request.idle_timeout_mins {
+        validate_idle_timeout(idle_timeout_mins)?;
+    }
+
+    let mut guard = db_conn.lock().await;
+    let mut dao = VaultDao::new(&mut *guard);
+    let vault = dao.get(request.id as i32).map_err(map_db_error)?;
+    let renamed = name.as_ref().is_some_and(|name| *name != vault.name);
+    let changes = UpdateVault {
+        name: name.filter(|_| renamed),
+        remember_password: request.remember_password.map(|v| if v { 1 } else { 0 }),
+        restart_policy: request.restart_policy.clone(),
+        idle_timeout_mins: request.idle_timeout_mins.map(|v| v as i32),
+        lock_with_session: request.lock_with_session.map(|v| if v { 1 } else { 0 }),
+    };
+    if changes.name.is_none() && changes.remember_password.is_none() && changes.restart_policy.is_none() &&
+        changes.idle_timeout_mins.is_none() && changes.lock_with_session.is_none() {
+        // diesel fails on empty changesets
+        return Ok(false);
+    }
+    dao.update(vault.id, changes).map_err(|err| map_name_error(err, request.name.as_deref().unwrap_or_default()))?;
+
+    Ok(renamed)
+}
+
+#[instrument(skip(db_conn), err)]
+pub async fn delete(db_conn: &Arc<Mutex<SqliteConnection>>, id: u32) -> Result<(), VaultServiceError> {
+    let mut guard = db_conn.lock().await;
+    VaultDao::new(&mut *guard).delete(id as i32).map_err(map_db_error)
+}
+
+/// Mount point must be an empty dir not used by other vault.
+pub async fn validate_mount_point(db_conn: &Arc<Mutex<SqliteConnection>>, id: Option<u32>, mount_point: &str) -> Result<(), VaultServiceError> {
+    let path = Path::new(mount_point);
+    if !path.is_absolute() {
+        return Err(VaultServiceError::InvalidMountPoint("must be an absolute path".to_string()));
+    }
+    match fs::read_dir(path) {
+        Ok(mut entries) => if entries.next().is_some() {
+            return Err(VaultHandlerError::MountPointNotEmpty { mount_point: mount_point.to_string() }.into());
+        }
+        Err(err) => return Err(VaultServiceError::InvalidMountPoint(err.to_string())),
+    }
+    let used = list(db_conn).await?.iter()
+        .any(|v| Some(v.id as u32) != id && Path::new(&v.mount_point) == path);
+    if used {
+        return Err(VaultServiceError::InvalidMountPoint("used by another vault".to_string()));
+    }
+
+    Ok(())
+}
+
+fn validate_name(name: &str) -> Result<String, VaultServiceError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(VaultServiceError::InvalidName);
+    }
+    Ok(name.to_string())
+}
+
+fn validate_restart_policy(restart_policy: &str) -> Result<(), VaultServiceError> {
+    RestartPolicy::from_str(restart_policy)
+        .map(|_| ())
+        .map_err(|_| VaultServiceError::InvalidRestartPolicy(restart_policy.to_string()))
+}
+
+fn validate_idle_timeout(idle_timeout_mins: u32) -> Result<(), VaultServiceError> {
+    if idle_timeout_mins > VaultServiceError::MAX_IDLE_TIMEOUT_MINS {
+        return Err(VaultServiceError::InvalidIdleTimeout(idle_timeout_mins));
+    }
+    Ok(())
+}
+
+/// Data dir must be an empty dir, not used by other vault and not nested with its data dir.
+pub async fn validate_data_dir(db_conn: &Arc<Mutex<SqliteConnection>>, id: Option<u32>, data_dir: &str) -> Result<(), VaultServiceError> {
+    let path = Path::new(data_dir);
+    if !path.is_absolute() {
+        return Err(VaultServiceError::InvalidDataDir("must be an absolute path".to_string()));
+    }
+    match fs::read_dir(path) {
+        // in dev we reuse existing data dirs
+        Ok(mut entries) => if !is_debug() && entries.next().is_some() {
+            return Err(VaultServiceError::InvalidDataDir("must be empty".to_string()));
+        }
+        Err(err) => return Err(VaultServiceError::InvalidDataDir(err.to_string())),
+    }
+    let used = list(db_conn).await?.iter()
+        .filter(|v| Some(v.id as u32) != id)
+        .any(|v| path.starts_with(&v.data_dir) || Path::new(&v.data_dir).starts_with(path));
+    if used {
+        return Err(VaultServiceError::InvalidDataDir("used by another vault".to_string()));
+    }
+
+    Ok(())
+}
+
+fn map_name_error(err: diesel::result::Error, name: &str) -> VaultServiceError {
+    match err {
+        DatabaseError(UniqueViolation, _) => VaultServiceError::DuplicateName(name.to_string()),
+        err => map_db_error(err),
+    }
+}
+
+fn map_db_error(err: diesel::result::Error) -> VaultServiceError {
+    match err {
+        NotFound => VaultServiceError::VaultNotFound,
+        err => {
+            error!(err = %err, "Database error");
+            VaultHandlerError::Database { cause: err.to_string() }.into()
+        }
+    }
+}
+
+pub fn to_vault_info(vault: &Vault) -> VaultInfo {
+    VaultInfo {
+        id: vault.id as u32,
+        name: vault.name.clone(),
+        mount_point: vault.mount_point.clone(),
+        data_dir: vault.data_dir.clone(),
+        locked: vault.state() != VaultState::Unlocked,
+        remember_password: vault.remember_password == 1,
+        restart_policy: vault.restart_policy.clone(),
+        last_exit_status: vault.last_exit_status,
+        idle_timeout_mins: vault.idle_timeout_mins as u32,
+        lock_with_session: vault.lock_with_session == 1,
+        lock_at: vault.lock_at,
+        state: to_state(vault.state()).into(),
+    }
+}
+
+fn to_state(state: VaultState) -> vault_info::State {
+    match state {
+        VaultState::Locked => vault_info::State::Locked,
+        VaultState::Unlocking => vault_info::State::Unlocking,
+        VaultState::Unlocked => vault_info::State::Unlocked,
+        VaultState::Locking => vault_info::State::Locking,
+        VaultState::Migrating => vault_info::State::Migrating,
+        VaultState::Error => vault_info::State::Error,
+        VaultState::Crashed => vault_info::State::Crashed,
+    }
+}
@@ -1,29 +1,73 @@
-use std::collections::HashMap;
 use std::sync::Arc;
 
 use diesel::SqliteConnection;
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
-use tracing::{info, instrument};
+use tracing::{error, info, instrument, warn};
 
+use rencfs_desktop_common::keyring::Keyring;
+
+use rencfs_desktop_common::events::{EventBus, VaultEventKind};
+use rencfs_desktop_common::progress::ProgressReporter;
 use rencfs_desktop_common::vault_handler::{VaultHandler, VaultHandlerError};
 use rencfs_desktop_common::vault_service_error::VaultServiceError;
+use rencfs_desktop_common::vault_state::VaultState;
 
+use crate::auth::Auth;
+use crate::deadlines::{self, Deadlines};
+use crate::handlers::Handlers;
+use crate::operations::Operations;
+use crate::{schedule_crud, vault_crud};
 use crate::vault_service::vault_service_server::VaultService;
 
 tonic::include_proto!("rencfs_desktop");
 
 pub struct MyVaultService{
-    handlers: Arc<Mutex<HashMap<u32, VaultHandler>>>,
+    handlers: Handlers,
     db_conn: Arc<Mutex<SqliteConnection>>,
+    operations: Arc<Operations>,
+    events: EventBus,
+    auth: Auth,
+    deadlines: Deadlines,
 }
 
 impl MyVaultService {
-    pub fn new(db_conn: Arc<Mutex<SqliteConnection>>) -> Self {
+    pub fn new(db_conn: Arc<Mutex<SqliteConnection>>, handlers: Handlers, events: EventBus,
+               auth: Auth, deadlines: Deadlines) -> Self {
         Self {
-            handlers: Arc::new(Mutex::new(HashMap::new())),
+            handlers,
             db_conn,
+            operations: Operations::new(),
+            events,
+            auth,
+            deadlines,
+        }
+    }
+
+    /// Unlocks and sets when it's locked again, or clears it if it's not for a limited time.
+    ///
+    /// If that can't be saved it's locked back, so a time limited vault doesn't stay unlocked for good.
+    async fn unlock_for(handler: &mut VaultHandler, request: UnlockRequest, progress: &ProgressReporter,
+                        deadlines: &Deadlines, events: &EventBus) -> Result<(), VaultHandlerError> {
+        let id = request.id;
+        handler.unlock(request.password, progress).await?;
+        if let Err(err) = Self::set_lock_at(handler, id, request.duration_secs, deadlines, events).await {
+            if let Err(err) = handler.lock(None).await {
+                error!(id, err = %err, "Cannot lock vault back");
+            }
+            return Err(err);
         }
+        Ok(())
+    }
+
+    async fn set_lock_at(handler: &mut VaultHandler, id: u32, duration_secs: Option<u32>, deadlines: &Deadlines,
+                         events: &EventBus) -> Result<(), VaultHandlerError> {
+        let lock_at = duration_secs.filter(|d| *d > 0).map(|d| deadlines::now() + d as i64);
+        handler.set_lock_at(lock_at).await?;
+        deadlines.changed();
+        events.emit(id, VaultEventKind::Updated, None);
+        Ok(())
     }
 
     async fn handle_handler_empty_response(response: Result<(), VaultHandlerError>) -> Result<Response<EmptyReply>, Status> {
@@ -38,26 +82,100 @@ impl MyVaultService {
 
 #[tonic::async_trait]
 impl VaultService for MyVaultService {
+    type WatchOperationStream = ReceiverStream<Result<OperationProgress, Status>>;
+    type WatchVaultsStream = ReceiverStream<Result<VaultEvent, Status>>;
+
+    #[instrument(skip(self, _request), err)]
+    async fn list_vaults(&self, _request: Request<EmptyRequest>) -> Result<Response<VaultList>, Status> {
+        info!("List vaults request received");
+
+        let vaults = vault_crud::list(&self.db_conn).await?;
+        Ok(Response::new(VaultList { vaults: vaults.iter().map(vault_crud::to_vault_info).collect() }))
+    }
+
     #[instrument(skip(self), err)]
-    async fn lock(&self, request: Request<IdRequest>) -> Result<Response<EmptyReply>, Status> {
+    async fn get_vault(&self, request: Request<IdRequest>) -> Result<Response<VaultInfo>, Status> {
         let id = request.into_inner().id;
-        info!(id, "Vault lock request received");
+        info!(id, "Get vault request received");
 
-        let mut handlers = self.handlers.lock().await;
-        let handler = handlers.entry(id).or_insert_with(|| VaultHandler::new(id, self.db_conn.clone()));
+        let vault = vault_crud::get(&self.db_conn, id).await?;
+        Ok(Response::new(vault_crud::to_vault_info(&vault)))
+    }
+
+    #[instrument(skip(self), err)]
+    async fn create_vault(&self, request: Request<CreateVaultRequest>) -> Result<Response<VaultInfo>, Status> {
+        let request = request.into_inner();
+        info!(name = request.name, "Create vault request received");
 
-        return MyVaultService::handle_handler_empty_response(handler.lock(None).await).await;
+        let vault = vault_crud::create(&self.db_conn, request).await?;
+        self.events.emit(vault.id as u32, VaultEventKind::Created, None);
+        Ok(Response::new(vault_crud::to_vault_info(&vault)))
     }
 
     #[instrument(skip(self), err)]
-    async fn unlock(&self, request: Request<IdRequest>) -> Result<Response<EmptyReply>, Status> {
+    async fn update_vault(&self, request: Request<UpdateVaultRequest>) -> Result<Response<EmptyReply>, Status> {
+        let request = request.into_inner();
+        let id = request.id;
+        info!(id, "Update vault request received");
+
+        // forgetting the password would race with an unlock saving it
+        let handler = if request.remember_password == Some(false) { Some(self.handlers.try_get(id).await?) } else { None };
+        let renamed = vault_crud::update(&self.db_conn, &request).await?;
+        if let Some(mut handler) = handler {
+            handler.forget_password().await.map_err(VaultServiceError::from)?;
+        }
+        self.events.emit(id, if renamed { VaultEventKind::Renamed } else { VaultEventKind::Updated }, None);
+
+        Ok(Response::new(EmptyReply {}))
+    }
+
+    #[instrument(skip(self), err)]
+    async fn delete_vault(&self, request: Request<IdRequest>) -> Result<Response<EmptyReply>, Status> {
         let id = request.into_inner().id;
-        info!(id, "Vault unlock request received");
+        info!(id, "Delete vault request received");
+
+        let vault = vault_crud::get(&self.db_conn, id).await?;
+        // held until it's deleted so nothing unlocks it meanwhile
+        let mut handler = self.handlers.try_get(id).await?;
+        if vault.state() != VaultState::Locked {
+            handler.lock(None).await.map_err(VaultServiceError::from)?;
+        }
+        if vault.remember_password == 1 {
+            // don't block deleting if keyring is not available, just leave the password there
+            match Keyring::connect().await {
+                Ok(keyring) => if let Err(err) = keyring.delete_password(id).await {
+                    warn!(err = %err, "Cannot remove password from keyring");
+                }
+                Err(err) => warn!(err = %err, "Cannot remove password from keyring"),
+            }
+        }
+        vault_crud::delete(&self.db_conn, id).await?;
+        self.handlers.remove(id);
+        self.events.emit(id, VaultEventKind::Deleted, None);
+
+        Ok(Response::new(EmptyReply {}))
+    }
 
-        let mut handlers = self.handlers.lock().await;
-        let handler = handlers.entry(id).or_insert_with(|| VaultHandler::new(id, self.db_conn.clone()));
+    #[instrument(skip(self), err)]
+    async fn lock(&self, request: Request<LockRequest>) -> Result<Response<EmptyReply>, Status> {
+        let LockRequest { id, force } = request.into_inner();
+        info!(id, force, "Vault lock request received");
+
+        let mut handler = self.handlers.try_get(id).await?;
+
+        let res = if force { handler.force_lock().await } else { handler.lock(None).await };
+        MyVaultService::handle_handler_empty_response(res).await
+    }
+
+    #[instrument(skip(self, request), err)]
+    async fn unlock(&self, request: Request<UnlockRequest>) -> Result<Response<EmptyReply>, Status> {
+        let request = request.into_inner();
+        let id = request.id;
+        info!(id, "Vault unlock request received");
 
-        return MyVaultService::handle_handler_empty_response(handler.unlock().await).await;
+        let mut handler = self.handlers.try_get(id).await?;
+        let res = Self::unlock_for(&mut handler, request, &ProgressReporter::noop(), &self.deadlines, &self.events).await;
+        MyVaultService::handle_handler_empty_response(res).await
     }
 
     #[instrument(skip(self), err)]
@@ -66,10 +184,19 @@ impl VaultService for MyVaultService {
         let id = request.id;
         info!(id, "Vault change mount point request received");
 
-        let mut handlers = self.handlers.lock().await;
-        let handler = handlers.entry(id).or_insert_with(|| VaultHandler::new(id, self.db_conn.clone()));
+        let vault = vault_crud::get(&self.db_conn, id).await?;
+        if vault.mount_point == request.value {
+            return Ok(Response::new(EmptyReply {}));
+        }
+        vault_crud::validate_mount_point(&self.db_conn, Some(id), &request.value).await?;
+
+        let mut handler = self.handlers.try_get(id).await?;
 
-        return MyVaultService::handle_handler_empty_response(handler.change_mount_point(request.value).await).await;
+        let res = handler.change_mount_point(request.value).await;
+        if res.is_ok() {
+            self.events.emit(id, VaultEventKind::Updated, None);
+        }
+        MyVaultService::handle_handler_empty_response(res).await
     }
 
     #[instrument(skip(self), err)]
@@ -78,9 +205,219 @@ impl VaultService for MyVaultService {
         let id = request.id;
         info!(id, "Vault change data dir request received");
 
-        let mut handlers = self.handlers.lock().await;
-        let handler = handlers.entry(id).or_insert_with(|| VaultHandler::new(id, self.db_conn.clone()));
+        let vault = vault_crud::get(&self.db_conn, id).await?;
+        if vault.data_dir == request.value {
+            return Ok(Response::new(EmptyReply {}));
+        }
+        vault_crud::validate_data_dir(&self.db_conn, Some(id), &request.value).await?;
+
+        let mut handler = self.handlers.try_get(id).await?;
+
+        MyVaultService::handle_handler_empty_response(handler.change_data_dir(request.value, &ProgressReporter::noop()).await).await
+    }
+
+    #[instrument(skip(self), err)]
+    async fn forget_password(&self, request: Request<IdRequest>) -> Result<Response<EmptyReply>, Status> {
+        let id = request.into_inner().id;
+        info!(id, "Vault forget password request received");
+
+        let mut handler = self.handlers.try_get(id).await?;
+
+        MyVaultService::handle_handler_empty_response(handler.forget_password().await).await
+    }
+
+    #[instrument(skip(self, request), err)]
+    async fn change_password(&self, request: Request<ChangePasswordRequest>) -> Result<Response<EmptyReply>, Status> {
+        let request = request.into_inner();
+        let id = request.id;
+        info!(id, "Vault change password request received");
+
+        if request.new_password.is_empty() {
+            return Err(VaultServiceError::from(VaultHandlerError::PasswordRequired).into());
+        }
+        let mut handler = self.handlers.try_get(id).await?;
+
+        MyVaultService::handle_handler_empty_response(handler.change_password(request.old_password, request.new_password).await).await
+    }
+
+    #[instrument(skip(self), err)]
+    async fn extend_unlock(&self, request: Request<ExtendUnlockRequest>) -> Result<Response<EmptyReply>, Status> {
+        let request = request.into_inner();
+        let id = request.id;
+        info!(id, secs = request.secs, "Vault extend unlock request received");
+
+        let mut handler = self.handlers.try_get(id).await?;
+        let vault = vault_crud::get(&self.db_conn, id).await?;
+        let lock_at = match vault.lock_at {
+            Some(lock_at) if vault.state() == VaultState::Unlocked => lock_at,
+            _ => return Err(VaultServiceError::NotTimeLimited.into()),
+        };
+
+        // if it's overdue it will be locked any moment, extend from now
+        handler.set_lock_at(Some(lock_at.max(deadlines::now()) + request.secs as i64)).await.map_err(VaultServiceError::from)?;
+        self.deadlines.changed();
+        self.events.emit(id, VaultEventKind::Updated, None);
+
+        Ok(Response::new(EmptyReply {}))
+    }
+
+    #[instrument(skip(self), err)]
+    async fn list_schedules(&self, request: Request<IdRequest>) -> Result<Response<ScheduleList>, Status> {
+        let id = request.into_inner().id;
+        info!(id, "List schedules request received");
+
+        let schedules = schedule_crud::list(&self.db_conn, id).await?;
+        Ok(Response::new(ScheduleList { schedules: schedules.iter().map(schedule_crud::to_schedule_info).collect() }))
+    }
+
+    #[instrument(skip(self), err)]
+    async fn create_schedule(&self, request: Request<CreateScheduleRequest>) -> Result<Response<ScheduleInfo>, Status> {
+        let request = request.into_inner();
+        let id = request.vault_id;
+        info!(id, "Create schedule request received");
+
+        let schedule = schedule_crud::create(&self.db_conn, request).await?;
+        self.events.emit(id, VaultEventKind::Updated, None);
+        Ok(Response::new(schedule_crud::to_schedule_info(&schedule)))
+    }
+
+    #[instrument(skip(self), err)]
+    async fn update_schedule(&self, request: Request<UpdateScheduleRequest>) -> Result<Response<EmptyReply>, Status> {
+        let request = request.into_inner();
+        info!(id = request.id, "Update schedule request received");
+
+        let schedule = schedule_crud::get(&self.db_conn, request.id).await?;
+        schedule_crud::update(&self.db_conn, &request).await?;
+        self.events.emit(schedule.vault_id as u32, VaultEventKind::Updated, None);
+
+        Ok(Response::new(EmptyReply {}))
+    }
+
+    #[instrument(skip(self), err)]
+    async fn delete_schedule(&self, request: Request<IdRequest>) -> Result<Response<EmptyReply>, Status> {
+        let id = request.into_inner().id;
+        info!(id, "Delete schedule request received");
+
+        let schedule = schedule_crud::get(&self.db_conn, id).await?;
+        schedule_crud::delete(&self.db_conn, id).await?;
+        self.events.emit(schedule.vault_id as u32, VaultEventKind::Updated, None);
+
+        Ok(Response::new(EmptyReply {}))
+    }
+
+    #[instrument(skip(self, request), err)]
+    async fn start_unlock(&self, request: Request<UnlockRequest>) -> Result<Response<OperationReply>, Status> {
+        let request = request.into_inner();
+        let id = request.id;
+        info!(id, "Vault start unlock request received");
+
+        // taken before starting so a busy vault is reported right away, it's released when the operation ends
+        let mut handler = self.handlers.try_get(id).await?;
+        let (deadlines, events) = (self.deadlines.clone(), self.events.clone());
+        let operation_id = self.operations.start(move |progress| async move {
+            Self::unlock_for(&mut handler, request, &progress, &deadlines, &events).await
+        });
+
+        Ok(Response::new(OperationReply { operation_id }))
+    }
+
+    #[instrument(skip(self), err)]
+    async fn start_change_data_dir(&self, request: Request<StringIdRequest>) -> Result<Response<OperationReply>, Status> {
+        let request = request.into_inner();
+        let id = request.id;
+        info!(id, "Vault start change data dir request received");
+
+        let vault = vault_crud::get(&self.db_conn, id).await?;
+        // same dir finishes right away in the handler
+        if vault.data_dir != request.value {
+            vault_crud::validate_data_dir(&self.db_conn, Some(id), &request.value).await?;
+        }
+        let mut handler = self.handlers.try_get(id).await?;
+        let operation_id = self.operations.start(move |progress| async move {
+            handler.change_data_dir(request.value, &progress).await
+        });
+
+        Ok(Response::new(OperationReply { operation_id }))
+    }
+
+    #[instrument(skip(self), err)]
+    async fn watch_operation(&self, request: Request<OperationIdRequest>) -> Result<Response<Self::WatchOperationStream>, Status> {
+        let operation_id = request.into_inner().operation_id;
+        info!(operation_id, "Watch operation request received");
+
+        match self.operations.watch(operation_id) {
+            Some(stream) => Ok(Response::new(stream)),
+            None => Err(Status::not_found(format!("operation {} not found", operation_id))),
+        }
+    }
+
+    #[instrument(skip(self), err)]
+    async fn cancel_operation(&self, request: Request<OperationIdRequest>) -> Result<Response<EmptyReply>, Status> {
+        let operation_id = request.into_inner().operation_id;
+        info!(operation_id, "Cancel operation request received");
+
+        if self.operations.cancel(operation_id) {
+            Ok(Response::new(EmptyReply {}))
+        } else {
+            Err(Status::not_found(format!("operation {} not found", operation_id)))
+        }
+    }
+
+    #[instrument(skip(self, _request), err)]
+    async fn watch_vaults(&self, _request: Request<EmptyRequest>) -> Result<Response<Self::WatchVaultsStream>, Status> {
+        info!("Watch vaults request received");
+
+        let mut events_rx = self.events.subscribe();
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            loop {
+                let event = match events_rx.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!(n, "Vault events watcher lagging, skipped events");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                let msg = VaultEvent {
+                    id: event.vault_id,
+                    kind: to_kind(event.kind).into(),
+                    message: event.message,
+                };
+                if tx.send(Ok(msg)).await.is_err() {
+                    // client went away
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    #[instrument(skip(self, _request), err)]
+    async fn rotate_token(&self, _request: Request<EmptyRequest>) -> Result<Response<EmptyReply>, Status> {
+        info!("Rotate token request received");
+
+        match self.auth.rotate() {
+            Ok(_) => Ok(Response::new(EmptyReply {})),
+            Err(err) => Err(Status::internal(format!("cannot write token: {}", err))),
+        }
+    }
+}
 
-        return MyVaultService::handle_handler_empty_response(handler.change_data_dir(request.value).await).await;
+fn to_kind(kind: VaultEventKind) -> vault_event::Kind {
+    match kind {
+        VaultEventKind::Created => vault_event::Kind::Created,
+        VaultEventKind::Renamed => vault_event::Kind::Renamed,
+        VaultEventKind::Updated => vault_event::Kind::Updated,
+        VaultEventKind::Deleted => vault_event::Kind::Deleted,
+        VaultEventKind::Unlocking => vault_event::Kind::Unlocking,
+        VaultEventKind::Unlocked => vault_event::Kind::Unlocked,
+        VaultEventKind::Locking => vault_event::Kind::Locking,
+        VaultEventKind::Locked => vault_event::Kind::Locked,
+        VaultEventKind::Migrating => vault_event::Kind::Migrating,
+        VaultEventKind::Crashed => vault_event::Kind::Crashed,
+        VaultEventKind::Error => vault_event::Kind::Error,
+        VaultEventKind::IdleWarning => vault_event::Kind::IdleWarning,
     }
 }
@@ -0,0 +1,115 @@
+// not every test file uses all of it
+#![allow(dead_code)]
+
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use diesel::{Connection, SqliteConnection};
+use tempfile::TempDir;
+use tokio::sync::{broadcast, Mutex};
+use tokio::time::Instant;
+use tonic::Request;
+
+use rencfs_desktop_common::engine::VaultEngine;
+use rencfs_desktop_common::events::{EventBus, VaultEvent, VaultEventKind};
+use rencfs_desktop_common::fake_engine::FakeEngine;
+use rencfs_desktop_common::persistence::run_migrations;
+use rencfs_desktop_common::vault_service_error::VaultServiceError;
+use rencfs_desktop_daemon::auth::Auth;
+use rencfs_desktop_daemon::deadlines::Deadlines;
+use rencfs_desktop_daemon::handlers::Handlers;
+use rencfs_desktop_daemon::reconcile;
+use rencfs_desktop_daemon::vault_service::{CreateVaultRequest, IdRequest, LockRequest, MyVaultService, UnlockRequest, vault_info, VaultInfo};
+use rencfs_desktop_daemon::vault_service::vault_service_server::VaultService;
+
+const EVENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The daemon put together like `main` does, with a [FakeEngine] and an in-memory db.
+pub struct Daemon {
+    pub engine: FakeEngine,
+    pub handlers: Handlers,
+    pub service: MyVaultService,
+    pub db_conn: Arc<Mutex<SqliteConnection>>,
+    pub events: EventBus,
+    dirs: Arc<TempDir>,
+}
+
+impl Daemon {
+    pub async fn start() -> Self {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        run_migrations(&mut conn).unwrap();
+        Self::start_with(FakeEngine::new(), Arc::new(Mutex::new(conn)), Arc::new(TempDir::new().unwrap())).await
+    }
+
+    /// Starts another daemon on the same db and mounts, like after a restart.
+    pub async fn restart(&self) -> Self {
+        Self::start_with(self.engine.clone(), self.db_conn.clone(), self.dirs.clone()).await
+    }
+
+    async fn start_with(engine: FakeEngine, db_conn: Arc<Mutex<SqliteConnection>>, dirs: Arc<TempDir>) -> Self {
+        let events = EventBus::new();
+        let vault_engine: Arc<dyn VaultEngine> = Arc::new(engine.clone());
+        let handlers = reconcile::reconcile(&vault_engine, db_conn.clone(), &events).await;
+        let handlers = Handlers::new(handlers, vault_engine, db_conn.clone(), events.clone());
+        let deadlines = Deadlines::spawn(handlers.clone(), db_conn.clone());
+        let service = MyVaultService::new(db_conn.clone(), handlers.clone(), events.clone(), Auth::with_token("test".to_string()),
+                                          deadlines);
+        Self { engine, handlers, service, db_conn, events, dirs }
+    }
+
+    pub async fn create_vault(&self, name: &str, restart_policy: &str) -> VaultInfo {
+        let mount_point = self.dirs.path().join(name).join("mnt");
+        let data_dir = self.dirs.path().join(name).join("data");
+        fs::create_dir_all(&mount_point).unwrap();
+        fs::create_dir_all(&data_dir).unwrap();
+        self.service.create_vault(Request::new(CreateVaultRequest {
+            name: name.to_string(),
+            mount_point: mount_point.to_string_lossy().to_string(),
+            data_dir: data_dir.to_string_lossy().to_string(),
+            restart_policy: restart_policy.to_string(),
+            ..Default::default()
+        })).await.unwrap().into_inner()
+    }
+
+    pub async fn unlock(&self, id: u32, password: &str) -> Result<(), VaultServiceError> {
+        self.unlock_for(id, password, None).await
+    }
+
+    pub async fn unlock_for(&self, id: u32, password: &str, duration_secs: Option<u32>) -> Result<(), VaultServiceError> {
+        let request = UnlockRequest { id, password: Some(password.to_string()), duration_secs };
+        self.service.unlock(Request::new(request)).await.map(|_| ()).map_err(to_service_error)
+    }
+
+    pub async fn lock(&self, id: u32, force: bool) -> Result<(), VaultServiceError> {
+        self.service.lock(Request::new(LockRequest { id, force })).await.map(|_| ()).map_err(to_service_error)
+    }
+
+    pub async fn state(&self, id: u32) -> vault_info::State {
+        self.service.get_vault(Request::new(IdRequest { id })).await.unwrap().into_inner().state()
+    }
+}
+
+fn to_service_error(status: tonic::Status) -> VaultServiceError {
+    VaultServiceError::try_from(status.clone()).unwrap_or_else(|_| panic!("not a vault error: {:?}", status))
+}
+
+/// Returns the events of vault `id` up to the first one of `kind`, which is the last one.
+pub async fn events_until(events: &mut broadcast::Receiver<VaultEvent>, id: u32, kind: VaultEventKind) -> Vec<VaultEvent> {
+    let deadline = Instant::now() + EVENT_TIMEOUT;
+    let mut seen = vec![];
+    loop {
+        let event = match tokio::time::timeout_at(deadline, events.recv()).await {
+            Ok(event) => event.unwrap(),
+            Err(_) => panic!("no {:?} event for vault {}, got {:?}", kind, id, seen),
+        };
+        if event.vault_id != id {
+            continue;
+        }
+        let done = event.kind == kind;
+        seen.push(event);
+        if done {
+            return seen;
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::fs::File;
+use std::time::Duration;
+
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+use tonic::Request;
+use zbus::{interface, SignalContext};
+use zbus::zvariant::{OwnedFd, OwnedObjectPath};
+
+use rencfs_desktop_common::events::VaultEventKind;
+use rencfs_desktop_daemon::logind;
+use rencfs_desktop_daemon::vault_service::{UpdateVaultRequest, VaultInfo};
+use rencfs_desktop_daemon::vault_service::vault_info::State;
+use rencfs_desktop_daemon::vault_service::vault_service_server::VaultService;
+use rencfs_desktop_test_support::Bus;
+
+use crate::common::{Daemon, events_until};
+
+mod common;
+
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/_31";
+const INHIBIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct FakeManager {
+    inhibited: mpsc::UnboundedSender<()>,
+}
+
+#[interface(name = "org.freedesktop.login1.Manager")]
+impl FakeManager {
+    fn inhibit(&self, _what: &str, _who: &str, _why: &str, _mode: &str) -> zbus::fdo::Result<OwnedFd> {
+        let _ = self.inhibited.send(());
+        let file = File::open("/dev/null").map_err(|err| zbus::fdo::Error::Failed(err.to_string()))?;
+        Ok(std::os::fd::OwnedFd::from(file).into())
+    }
+
+    #[zbus(signal)]
+    async fn prepare_for_sleep(ctxt: &SignalContext<'_>, start: bool) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn prepare_for_shutdown(ctxt: &SignalContext<'_>, start: bool) -> zbus::Result<()>;
+}
+
+struct FakeSession {
+    uid: u32,
+}
+
+#[interface(name = "org.freedesktop.login1.Session")]
+impl FakeSession {
+    #[zbus(property)]
+    fn user(&self) -> (u32, OwnedObjectPath) {
+        (self.uid, OwnedObjectPath::try_from(format!("/org/freedesktop/login1/user/_{}", self.uid)).unwrap())
+    }
+
+    #[zbus(signal)]
+    async fn lock(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+}
+
+/// `org.freedesktop.login1` with one session, of the user running the tests.
+struct FakeLogind {
+    conn: zbus::Connection,
+    inhibited: mpsc::UnboundedReceiver<()>,
+}
+
+impl FakeLogind {
+    async fn start(bus: &Bus) -> Self {
+        let (tx, inhibited) = mpsc::unbounded_channel();
+        let conn = zbus::connection::Builder::address(bus.address.as_str()).unwrap()
+            .name("org.freedesktop.login1").unwrap()
+            .serve_at(MANAGER_PATH, FakeManager { inhibited: tx }).unwrap()
+            .serve_at(SESSION_PATH, FakeSession { uid: unsafe { libc::getuid() } }).unwrap()
+            .build().await.unwrap();
+        Self { conn, inhibited }
+    }
+
+    /// The watcher takes the inhibitor lock once it's subscribed to the signals.
+    async fn wait_for_inhibit(&mut self) {
+        tokio::time::timeout(INHIBIT_TIMEOUT, self.inhibited.recv()).await
+            .expect("logind watcher didn't take the inhibitor lock")
+            .unwrap();
+    }
+
+    async fn prepare_for_sleep(&self) {
+        let ctxt = SignalContext::new(&self.conn, MANAGER_PATH).unwrap();
+        FakeManager::prepare_for_sleep(&ctxt, true).await.unwrap();
+    }
+
+    async fn lock_session(&self) {
+        let ctxt = SignalContext::new(&self.conn, SESSION_PATH).unwrap();
+        FakeSession::lock(&ctxt).await.unwrap();
+    }
+}
+
+/// One vault locked with the session and one not, both unlocked.
+async fn unlocked_vaults(daemon: &Daemon) -> (VaultInfo, VaultInfo) {
+    let opted_in = daemon.create_vault("opted-in", "never").await;
+    let other = daemon.create_vault("other", "never").await;
+    let request = UpdateVaultRequest { id: opted_in.id, lock_with_session: Some(true), ..Default::default() };
+    daemon.service.update_vault(Request::new(request)).await.unwrap();
+    daemon.unlock(opted_in.id, "secret").await.unwrap();
+    daemon.unlock(other.id, "secret").await.unwrap();
+    (opted_in, other)
+}
+
+#[tokio::test]
+async fn sleep_locks_opted_in_vaults() {
+    let dir = TempDir::new().unwrap();
+    let bus = Bus::start(&dir.path().join("bus"));
+    let mut logind = FakeLogind::start(&bus).await;
+    let daemon = Daemon::start().await;
+    let (opted_in, other) = unlocked_vaults(&daemon).await;
+    let mut events = daemon.events.subscribe();
+
+    tokio::spawn(logind::run(Some(bus.address.clone()), daemon.handlers.clone(), daemon.db_conn.clone()));
+    logind.wait_for_inhibit().await;
+    logind.prepare_for_sleep().await;
+
+    events_until(&mut events, opted_in.id, VaultEventKind::Locked).await;
+    assert_eq!(daemon.state(opted_in.id).await, State::Locked);
+    assert_eq!(daemon.state(other.id).await, State::Unlocked);
+}
+
+#[tokio::test]
+async fn session_lock_locks_opted_in_vaults() {
+    let dir = TempDir::new().unwrap();
+    let bus = Bus::start(&dir.path().join("bus"));
+    let mut logind = FakeLogind::start(&bus).await;
+    let daemon = Daemon::start().await;
+    let (opted_in, other) = unlocked_vaults(&daemon).await;
+    let mut events = daemon.events.subscribe();
+
+    tokio::spawn(logind::run(Some(bus.address.clone()), daemon.handlers.clone(), daemon.db_conn.clone()));
+    logind.wait_for_inhibit().await;
+    logind.lock_session().await;
+
+    events_until(&mut events, opted_in.id, VaultEventKind::Locked).await;
+    assert_eq!(daemon.state(opted_in.id).await, State::Locked);
+    assert_eq!(daemon.state(other.id).await, State::Unlocked);
+}
+
+#[tokio::test]
+async fn session_lock_leaves_vaults_in_use_unlocked() {
+    let dir = TempDir::new().unwrap();
+    let bus = Bus::start(&dir.path().join("bus"));
+    let mut logind = FakeLogind::start(&bus).await;
+    let daemon = Daemon::start().await;
+    let (opted_in, _) = unlocked_vaults(&daemon).await;
+    daemon.engine.set_busy(&opted_in.mount_point, true);
+    let mut events = daemon.events.subscribe();
+
+    tokio::spawn(logind::run(Some(bus.address.clone()), daemon.handlers.clone(), daemon.db_conn.clone()));
+    logind.wait_for_inhibit().await;
+    logind.lock_session().await;
+
+    // not killed, what the programs using it didn't save would be lost
+    events_until(&mut events, opted_in.id, VaultEventKind::Error).await;
+    assert_eq!(daemon.state(opted_in.id).await, State::Unlocked);
+    assert!(daemon.engine.is_mounted(&opted_in.mount_point));
+}
+
+#[tokio::test]
+async fn reconnects_when_bus_restarts() {
+    let dir = TempDir::new().unwrap();
+    let socket = dir.path().join("bus");
+    let bus = Bus::start(&socket);
+    let mut logind = FakeLogind::start(&bus).await;
+    let daemon = Daemon::start().await;
+    let (opted_in, _) = unlocked_vaults(&daemon).await;
+    let mut events = daemon.events.subscribe();
+
+    tokio::spawn(logind::run(Some(bus.address.clone()), daemon.handlers.clone(), daemon.db_conn.clone()));
+    logind.wait_for_inhibit().await;
+    drop(logind);
+    drop(bus);
+
+    let bus = Bus::start(&socket);
+    let mut logind = FakeLogind::start(&bus).await;
+    logind.wait_for_inhibit().await;
+    logind.prepare_for_sleep().await;
+
+    events_until(&mut events, opted_in.id, VaultEventKind::Locked).await;
+    assert_eq!(daemon.state(opted_in.id).await, State::Locked);
+}
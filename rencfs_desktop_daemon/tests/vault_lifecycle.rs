@@ -0,0 +1,181 @@
+use rencfs_desktop_common::events::VaultEventKind;
+use rencfs_desktop_common::vault_handler::VaultHandlerError;
+use rencfs_desktop_common::vault_service_error::VaultServiceError;
+use rencfs_desktop_daemon::shutdown;
+use rencfs_desktop_daemon::vault_service::vault_info::State;
+
+use crate::common::{Daemon, events_until};
+
+mod common;
+
+#[tokio::test]
+async fn unlock_and_lock() {
+    let daemon = Daemon::start().await;
+    let vault = daemon.create_vault("vault", "never").await;
+
+    daemon.unlock(vault.id, "secret").await.unwrap();
+    assert_eq!(daemon.state(vault.id).await, State::Unlocked);
+    assert!(daemon.engine.is_mounted(&vault.mount_point));
+
+    daemon.lock(vault.id, false).await.unwrap();
+    assert_eq!(daemon.state(vault.id).await, State::Locked);
+    assert!(!daemon.engine.is_mounted(&vault.mount_point));
+}
+
+#[tokio::test]
+async fn wrong_password() {
+    let daemon = Daemon::start().await;
+    let vault = daemon.create_vault("vault", "never").await;
+    // the first unlock sets it
+    daemon.unlock(vault.id, "secret").await.unwrap();
+    daemon.lock(vault.id, false).await.unwrap();
+
+    let err = daemon.unlock(vault.id, "wrong").await.unwrap_err();
+    assert_eq!(err, VaultServiceError::from(VaultHandlerError::InvalidPassword));
+    assert_eq!(daemon.state(vault.id).await, State::Locked);
+    assert!(!daemon.engine.is_mounted(&vault.mount_point));
+
+    daemon.unlock(vault.id, "secret").await.unwrap();
+    assert_eq!(daemon.state(vault.id).await, State::Unlocked);
+}
+
+#[tokio::test]
+async fn crash_is_restarted() {
+    let daemon = Daemon::start().await;
+    let vault = daemon.create_vault("vault", "always").await;
+    daemon.unlock(vault.id, "secret").await.unwrap();
+    let mut events = daemon.events.subscribe();
+
+    assert!(daemon.engine.crash(&vault.mount_point, 1));
+    events_until(&mut events, vault.id, VaultEventKind::Crashed).await;
+    events_until(&mut events, vault.id, VaultEventKind::Unlocked).await;
+    assert_eq!(daemon.state(vault.id).await, State::Unlocked);
+    assert!(daemon.engine.is_mounted(&vault.mount_point));
+
+    daemon.lock(vault.id, false).await.unwrap();
+    assert_eq!(daemon.state(vault.id).await, State::Locked);
+}
+
+#[tokio::test]
+async fn crash_without_restart() {
+    let daemon = Daemon::start().await;
+    let vault = daemon.create_vault("vault", "never").await;
+    daemon.unlock(vault.id, "secret").await.unwrap();
+    let mut events = daemon.events.subscribe();
+
+    assert!(daemon.engine.crash(&vault.mount_point, 1));
+    events_until(&mut events, vault.id, VaultEventKind::Crashed).await;
+    assert_eq!(daemon.state(vault.id).await, State::Crashed);
+    assert!(!daemon.engine.is_mounted(&vault.mount_point));
+
+    daemon.lock(vault.id, false).await.unwrap();
+    assert_eq!(daemon.state(vault.id).await, State::Locked);
+}
+
+#[tokio::test]
+async fn busy_lock_then_force_lock() {
+    let daemon = Daemon::start().await;
+    let vault = daemon.create_vault("vault", "always").await;
+    daemon.unlock(vault.id, "secret").await.unwrap();
+    daemon.engine.set_busy(&vault.mount_point, true);
+
+    let err = daemon.lock(vault.id, false).await.unwrap_err();
+    assert!(matches!(err, VaultServiceError::VaultHandlerError(VaultHandlerError::MountPointBusy { .. })), "{:?}", err);
+    assert_eq!(daemon.state(vault.id).await, State::Unlocked);
+    assert!(daemon.engine.is_mounted(&vault.mount_point));
+
+    let mut events = daemon.events.subscribe();
+    daemon.lock(vault.id, true).await.unwrap();
+    let events = events_until(&mut events, vault.id, VaultEventKind::Locked).await;
+    // the mount exits once detached, that's not a crash to restart
+    assert!(events.iter().all(|e| e.kind != VaultEventKind::Crashed), "{:?}", events);
+    assert_eq!(daemon.state(vault.id).await, State::Locked);
+    assert!(!daemon.engine.is_mounted(&vault.mount_point));
+}
+
+#[tokio::test]
+async fn restart_adopts_mounted_vaults() {
+    let daemon = Daemon::start().await;
+    let vault = daemon.create_vault("vault", "never").await;
+    daemon.unlock(vault.id, "secret").await.unwrap();
+    daemon.handlers.get(vault.id).await.unwrap().detach().await;
+
+    let daemon = daemon.restart().await;
+    assert_eq!(daemon.state(vault.id).await, State::Unlocked);
+    daemon.lock(vault.id, false).await.unwrap();
+    assert_eq!(daemon.state(vault.id).await, State::Locked);
+    assert!(!daemon.engine.is_mounted(&vault.mount_point));
+}
+
+#[tokio::test]
+async fn adopted_vault_is_left_locked_after_a_change() {
+    let daemon = Daemon::start().await;
+    let vault = daemon.create_vault("vault", "never").await;
+    daemon.unlock(vault.id, "secret").await.unwrap();
+    daemon.handlers.get(vault.id).await.unwrap().detach().await;
+
+    // its password is gone with the previous daemon, it can't be unlocked back
+    let daemon = daemon.restart().await;
+    let mut events = daemon.events.subscribe();
+    let mount_point = format!("{}2", vault.mount_point);
+    daemon.handlers.get(vault.id).await.unwrap().change_mount_point(mount_point.clone()).await.unwrap();
+    events_until(&mut events, vault.id, VaultEventKind::Error).await;
+    assert_eq!(daemon.state(vault.id).await, State::Locked);
+    assert!(!daemon.engine.is_mounted(&vault.mount_point));
+    assert!(!daemon.engine.is_mounted(&mount_point));
+}
+
+#[tokio::test]
+async fn restart_cleans_up_stale_mounts() {
+    let daemon = Daemon::start().await;
+    let vault = daemon.create_vault("vault", "never").await;
+    daemon.unlock(vault.id, "secret").await.unwrap();
+    daemon.handlers.get(vault.id).await.unwrap().detach().await;
+    // dies while no daemon is watching it
+    daemon.engine.crash(&vault.mount_point, 1);
+
+    let daemon = daemon.restart().await;
+    assert_eq!(daemon.state(vault.id).await, State::Locked);
+    daemon.unlock(vault.id, "secret").await.unwrap();
+    assert_eq!(daemon.state(vault.id).await, State::Unlocked);
+}
+
+#[tokio::test]
+async fn shutdown_locks_vaults_not_in_use() {
+    let daemon = Daemon::start().await;
+    let busy = daemon.create_vault("busy", "always").await;
+    let idle = daemon.create_vault("idle", "never").await;
+    daemon.unlock(busy.id, "secret").await.unwrap();
+    daemon.unlock(idle.id, "secret").await.unwrap();
+    daemon.engine.set_busy(&busy.mount_point, true);
+
+    shutdown::shutdown(daemon.handlers.clone(), daemon.db_conn.clone()).await;
+    assert!(!daemon.engine.is_mounted(&idle.mount_point));
+    // left for the next daemon to adopt
+    assert!(daemon.engine.is_mounted(&busy.mount_point));
+    let daemon = daemon.restart().await;
+    assert_eq!(daemon.state(busy.id).await, State::Unlocked);
+}
+
+#[tokio::test]
+async fn busy_vault_does_not_block_others() {
+    let daemon = Daemon::start().await;
+    let held = daemon.create_vault("held", "never").await;
+    let other = daemon.create_vault("other", "never").await;
+
+    // like a long unlock or data dir change running on it
+    let _handler = daemon.handlers.get(held.id).await.unwrap();
+    daemon.unlock(other.id, "secret").await.unwrap();
+    assert_eq!(daemon.state(other.id).await, State::Unlocked);
+
+    assert_eq!(daemon.unlock(held.id, "secret").await.unwrap_err(), VaultServiceError::VaultBusy);
+    assert_eq!(daemon.lock(held.id, false).await.unwrap_err(), VaultServiceError::VaultBusy);
+}
+
+#[tokio::test]
+async fn unknown_vault_is_not_found() {
+    let daemon = Daemon::start().await;
+
+    assert_eq!(daemon.lock(42, false).await.unwrap_err(), VaultServiceError::VaultNotFound);
+    assert!(!daemon.handlers.ids().contains(&42));
+}
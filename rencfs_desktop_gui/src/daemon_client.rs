@@ -0,0 +1,11 @@
+use tonic::transport::Error;
+
+use rencfs_desktop_common::transport::{self, AuthChannel};
+
+use crate::daemon_service::vault_service_client::VaultServiceClient;
+
+pub(crate) type Client = VaultServiceClient<AuthChannel>;
+
+pub(crate) async fn connect() -> Result<Client, Error> {
+    Ok(VaultServiceClient::new(transport::connect().await?))
+}
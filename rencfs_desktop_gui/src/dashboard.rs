@@ -1,8 +1,8 @@
 use std::borrow::Cow;
 use std::sync;
 use std::sync::RwLock;
+use std::time::Duration;
 
-use diesel::SqliteConnection;
 use eframe::egui::{
     CentralPanel, Color32, Context, FontId, Margin, RichText, SidePanel, TopBottomPanel,
 };
@@ -10,14 +10,21 @@ use eframe::egui;
 use eframe::emath::Align;
 use egui::{Frame, Layout, Ui};
 use egui_notify::Toasts;
+use tracing::error;
 
-use rencfs_desktop_common::dao::VaultDao;
+use rencfs_desktop_common::countdown::time_left;
+use rencfs_desktop_common::error_messages::status_to_message;
 
+use crate::daemon_client::{self, Client};
+use crate::daemon_service::vault_event::Kind;
+use crate::daemon_service::vault_info::State as VaultState;
+use crate::daemon_service::{EmptyRequest, VaultEvent, VaultInfo};
 use crate::detail::ViewGroupDetail;
-use crate::ListView;
+use crate::{ListView, vault_events};
 use crate::listview::r#trait::ItemTrait;
 use crate::listview::state::State;
 use crate::util::customize_toast;
+use crate::RT;
 
 static CURRENT_VAULT_ITEM: RwLock<Option<Item>> = RwLock::new(None);
 static CURRENT_VAULT_ID: RwLock<Option<i32>> = RwLock::new(None);
@@ -28,6 +35,10 @@ pub(crate) enum UiReply {
     VaultDeleted,
     GoBack,
     Error(String),
+    /// vault changed in daemon, maybe by another client
+    VaultEvent(VaultEvent),
+    /// from [Dashboard::load_items], with the client it used
+    VaultsLoaded(Result<(Client, Vec<VaultInfo>), String>),
 }
 
 #[derive(Clone, Debug)]
@@ -37,6 +48,32 @@ pub struct Item {
     pub mount_point: String,
     pub data_dir: String,
     pub locked: bool,
+    pub remember_password: bool,
+    pub restart_policy: String,
+    pub last_exit_status: Option<i32>,
+    pub idle_timeout_mins: u32,
+    pub lock_with_session: bool,
+    pub lock_at: Option<i64>,
+    pub state: VaultState,
+}
+
+impl From<VaultInfo> for Item {
+    fn from(v: VaultInfo) -> Self {
+        Item {
+            state: v.state(),
+            id: v.id as i32,
+            name: v.name,
+            mount_point: v.mount_point,
+            data_dir: v.data_dir,
+            locked: v.locked,
+            remember_password: v.remember_password,
+            restart_policy: v.restart_policy,
+            last_exit_status: v.last_exit_status,
+            idle_timeout_mins: v.idle_timeout_mins,
+            lock_with_session: v.lock_with_session,
+            lock_at: v.lock_at,
+        }
+    }
 }
 
 impl ItemTrait for Item {
@@ -47,7 +84,11 @@ impl ItemTrait for Item {
     }
 
     fn style_clicked(&self, frame: &mut Frame) {
-        frame.fill = if self.locked { Color32::DARK_GRAY } else { Color32::DARK_GREEN };
+        frame.fill = match self.state {
+            VaultState::Error | VaultState::Crashed => Color32::DARK_RED,
+            _ if self.locked => Color32::DARK_GRAY,
+            _ => Color32::DARK_GREEN,
+        };
     }
 
     fn show(
@@ -68,6 +109,14 @@ impl ItemTrait for Item {
                     } else {
                         format!("🔓 {}", self.name)
                     }).size(20.0).strong());
+                    if let Some(lock_at) = self.lock_at.filter(|_| !self.locked) {
+                        ui.label(RichText::new(time_left(lock_at)).monospace()).on_hover_text("Time left until it's locked");
+                    }
+                    // the icon tells the rest
+                    if !matches!(self.state, VaultState::Locked | VaultState::Unlocked) {
+                        let text = RichText::new(self.state.as_str_name().to_lowercase()).italics();
+                        ui.label(if matches!(self.state, VaultState::Error | VaultState::Crashed) { text.color(Color32::RED) } else { text });
+                    }
                 });
                 ui.with_layout(Layout::right_to_left(Align::Center), |_ui| {});
             });
@@ -91,8 +140,14 @@ impl ItemTrait for Item {
 }
 
 pub(crate) struct Dashboard {
-    conn: SqliteConnection,
+    /// connected on first use
+    client: Option<Client>,
     pub(crate) items: Vec<Item>,
+    /// `false` until the first list of vaults arrives
+    loaded: bool,
+    loading: bool,
+    /// vaults changed while loading them, load again once it's done
+    reload: bool,
     pub(crate) state: Option<State>,
     prev_state: Option<State>,
 
@@ -100,52 +155,92 @@ pub(crate) struct Dashboard {
     rx: sync::mpsc::Receiver<UiReply>,
 
     toasts: Toasts,
+    ctx: Context,
 }
 
 impl Dashboard {
-    pub(crate) fn new(conn: SqliteConnection) -> Self {
+    pub(crate) fn new(ctx: Context) -> Self {
         let (tx, rx) = sync::mpsc::channel::<UiReply>();
+        vault_events::watch(tx.clone(), ctx.clone());
         let mut out = Self {
-            conn,
+            client: None,
             items: vec![],
+            loaded: false,
+            loading: false,
+            reload: false,
             state: None,
             prev_state: None,
             tx,
             rx,
             toasts: Toasts::default(),
+            ctx,
         };
-        out.items = out.load_items();
-        if out.items.len() > 0 {
-            *CURRENT_VAULT_ID.write().unwrap() = Some(out.items[0].id);
-            *CURRENT_VAULT_ITEM.write().unwrap() = Some(out.items[0].clone());
-        }
+        out.load_items();
 
         out
     }
 
-    fn load_items(&mut self) -> Vec<Item> {
-        let mut dao = VaultDao::new(&mut self.conn);
-        dao.get_all(None).unwrap().iter().map(|v| {
-            Item {
-                id: v.id,
-                name: v.name.clone(),
-                mount_point: v.mount_point.clone(),
-                data_dir: v.data_dir.clone(),
-                locked: if v.locked == 1 { true } else { false },
+    /// Lists vaults in background, they arrive as [UiReply::VaultsLoaded].
+    fn load_items(&mut self) {
+        if self.loading {
+            self.reload = true;
+            return;
+        }
+        self.loading = true;
+        let client = self.client.clone();
+        let tx = self.tx.clone();
+        let ctx = self.ctx.clone();
+        RT.spawn(async move {
+            let _ = tx.send(UiReply::VaultsLoaded(Self::list_vaults(client).await));
+            ctx.request_repaint();
+        });
+    }
+
+    fn on_items_loaded(&mut self, res: Result<(Client, Vec<VaultInfo>), String>) {
+        self.loading = false;
+        match res {
+            Ok((client, vaults)) => {
+                self.client = Some(client);
+                self.items = vaults.into_iter().map(Item::from).collect();
+                if !self.loaded && !self.items.is_empty() {
+                    *CURRENT_VAULT_ID.write().unwrap() = Some(self.items[0].id);
+                    *CURRENT_VAULT_ITEM.write().unwrap() = Some(self.items[0].clone());
+                }
+                self.loaded = true;
+            }
+            Err(err) => {
+                error!(err = %err, "Cannot load vaults");
+                // reconnect next time, daemon might have restarted
+                self.client = None;
+                customize_toast(self.toasts.error(format!("cannot load vaults: {}", err)));
             }
-        }).collect()
+        }
+        if self.reload {
+            self.reload = false;
+            self.load_items();
+        }
+    }
+
+    async fn list_vaults(client: Option<Client>) -> Result<(Client, Vec<VaultInfo>), String> {
+        let mut client = match client {
+            Some(client) => client,
+            None => daemon_client::connect().await.map_err(|err| format!("failed to connect to daemon: {}", err))?,
+        };
+        let response = client.list_vaults(tonic::Request::new(EmptyRequest {})).await
+            .map_err(|err| status_to_message(&err))?;
+        Ok((client, response.into_inner().vaults))
     }
 }
 
 impl eframe::App for Dashboard {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
-        if let Ok(msg) = self.rx.try_recv() {
+        while let Ok(msg) = self.rx.try_recv() {
             match msg {
                 UiReply::VaultUpdated(show_message) => {
                     if show_message {
                         customize_toast(self.toasts.success("vault updated"));
                     }
-                    self.items = self.load_items();
+                    self.load_items();
                 }
                 UiReply::GoBack => {
                     if let Some(state) = self.prev_state.take() {
@@ -156,13 +251,36 @@ impl eframe::App for Dashboard {
                 }
                 UiReply::VaultDeleted => {
                     self.state = None;
-                    self.items = self.load_items();
+                    self.load_items();
                 }
                 UiReply::VaultInserted => {
                     self.state = None;
-                    self.items = self.load_items();
+                    self.load_items();
                 }
+                UiReply::VaultsLoaded(res) => self.on_items_loaded(res),
                 UiReply::Error(err) => customize_toast(self.toasts.error(err)),
+                UiReply::VaultEvent(event) => {
+                    self.load_items();
+                    // a new vault isn't listed yet, a renamed one shows the old name
+                    let name = self.items.iter().find(|i| i.id == event.id as i32)
+                        .map_or_else(|| event.id.to_string(), |i| i.name.clone());
+                    match event.kind() {
+                        Kind::Crashed => customize_toast(self.toasts.error(
+                            format!("vault {} stopped unexpectedly{}", name, event.message.as_ref().map_or("".to_string(), |m| format!(": {}", m))))),
+                        Kind::Error => customize_toast(self.toasts.error(
+                            format!("vault {}: {}", name, event.message.as_deref().unwrap_or("error")))),
+                        Kind::IdleWarning => customize_toast(self.toasts.warning(
+                            format!("vault {} is not used, locking it in {}s", name, event.message.as_deref().unwrap_or("60")))),
+                        _ => {}
+                    }
+                    if let Some(State::Detail(detail)) = self.state.as_mut() {
+                        if event.kind() == Kind::Deleted && detail.id == Some(event.id as i32) {
+                            self.state = None;
+                        } else {
+                            detail.on_vault_event(&event);
+                        }
+                    }
+                }
             }
         }
 
@@ -232,7 +350,9 @@ impl eframe::App for Dashboard {
         if let Some(state) = self.state.as_mut() {
             state.as_app().update(ctx, frame);
         } else {
-            let text = if self.items.len() > 0 {
+            let text = if !self.loaded {
+                "Loading vaults"
+            } else if self.items.len() > 0 {
                 "Select a vault or add a new one"
             } else {
                 "No vaults found, add a new one"
@@ -247,6 +367,10 @@ impl eframe::App for Dashboard {
             });
         }
 
+        if self.items.iter().any(|i| !i.locked && i.lock_at.is_some()) {
+            // keep the countdowns ticking
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
         self.toasts.show(ctx);
     }
 }
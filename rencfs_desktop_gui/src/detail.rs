@@ -1,35 +1,50 @@
 use std::{fs, sync};
+use std::str::FromStr;
 use std::sync::mpsc::Sender;
 use std::time::Duration;
 use sync::mpsc::Receiver;
 
-use diesel::{ExpressionMethods, QueryResult};
-use diesel::result::DatabaseErrorKind::UniqueViolation;
-use diesel::result::Error::DatabaseError;
 use eframe::{egui, Frame};
 use eframe::egui::Context;
-use egui::{Button, ecolor, Widget};
+use egui::{Align2, Button, DragValue, ecolor, Key, ProgressBar, TextEdit, Widget};
 use egui_notify::{Toast, Toasts};
 use tracing::instrument;
 
 use daemon_service::DaemonService;
+use rencfs_desktop_common::countdown::time_left;
+use rencfs_desktop_common::error_messages::user_message;
 use rencfs_desktop_common::is_debug;
-use rencfs_desktop_common::models::NewVault;
-use rencfs_desktop_common::schema::vaults::{data_dir, mount_point, name};
+use rencfs_desktop_common::schedule::{DAY_NAMES, ScheduleAction, WEEKDAYS};
+use rencfs_desktop_common::supervisor::RestartPolicy;
+use rencfs_desktop_common::vault_handler::VaultHandlerError;
 use rencfs_desktop_common::vault_service_error::VaultServiceError;
 
-use crate::daemon_service::EmptyReply;
+use crate::daemon_service::{CreateScheduleRequest, CreateVaultRequest, EmptyReply, OperationProgress, ScheduleInfo, UpdateScheduleRequest, UpdateVaultRequest, VaultEvent, VaultInfo};
+use crate::daemon_service::vault_event::Kind;
+use crate::daemon_service::vault_info::State as VaultState;
 use crate::dashboard::{Item, UiReply};
-use crate::detail::db_service::DbService;
 
 mod daemon_service;
-mod db_service;
+
+/// Offered when unlocking, in seconds, 0 is until locked.
+const UNLOCK_DURATIONS: [u32; 5] = [0, 15 * 60, 30 * 60, 60 * 60, 4 * 60 * 60];
 
 enum ServiceReply {
+    GetVault(VaultInfo),
+    CreateVault(VaultInfo),
+    UpdateVault(EmptyReply),
+    DeleteVault(EmptyReply),
     UnlockVaultReply(EmptyReply),
     LockVaultReply(EmptyReply),
     ChangeMountPoint(EmptyReply),
     ChangeDataDir(EmptyReply),
+    ForgetPassword(EmptyReply),
+    ExtendUnlock(EmptyReply),
+    Schedules(Vec<ScheduleInfo>),
+    /// created, updated or deleted
+    ScheduleChanged,
+    OperationProgress(OperationProgress),
+    OperationCancelled,
     VaultServiceError(VaultServiceError),
     Error(String),
 }
@@ -40,15 +55,40 @@ pub struct ViewGroupDetail {
     pub(crate) mount_point: Option<String>,
     pub(crate) data_dir: Option<String>,
     pub(crate) locked: bool,
+    pub(crate) state: VaultState,
+    pub(crate) remember_password: bool,
+    pub(crate) restart_policy: RestartPolicy,
+    pub(crate) last_exit_status: Option<i32>,
+    pub(crate) idle_timeout_mins: u32,
+    pub(crate) lock_with_session: bool,
+    pub(crate) lock_at: Option<i64>,
+    /// with the user's unsaved edits
+    schedules: Vec<ScheduleInfo>,
 
     tx_parent: Sender<UiReply>,
     rx_service: Receiver<ServiceReply>,
 
     daemon_service: DaemonService,
-    db_service: DbService,
+    /// name as saved in daemon, to know if it changed when editing
+    saved_name: String,
+    /// idle timeout as saved in daemon, we save it when user is done editing
+    saved_idle_timeout_mins: u32,
+    /// schedules as saved in daemon, to know which ones were edited
+    saved_schedules: Vec<ScheduleInfo>,
 
     confirmation_delete_pending: bool,
 
+    password_prompt_open: bool,
+    password: String,
+    password_error: Option<String>,
+    /// what's keeping the vault busy when locking failed because of it, we offer to force it
+    lock_busy_message: Option<String>,
+    /// 0 to stay unlocked until locked
+    unlock_duration_secs: u32,
+
+    /// long-running operation in progress, like unlock or data dir change
+    operation: Option<OperationProgress>,
+
     toasts: Toasts,
 }
 
@@ -63,8 +103,22 @@ impl eframe::App for ViewGroupDetail {
         let customize_toast = |t: &mut Toast| {
             customize_toast_duration(t, 5);
         };
-        if let Ok(reply) = self.rx_service.try_recv() {
+        while let Ok(reply) = self.rx_service.try_recv() {
+            if !matches!(reply, ServiceReply::OperationProgress(_) | ServiceReply::ForgetPassword(_) | ServiceReply::GetVault(_) |
+                ServiceReply::UpdateVault(_) | ServiceReply::ExtendUnlock(_) | ServiceReply::Schedules(_) | ServiceReply::ScheduleChanged) {
+                self.operation = None;
+            }
             match reply {
+                ServiceReply::GetVault(vault) => self.load(vault),
+                ServiceReply::CreateVault(vault) => {
+                    self.tx_parent.send(UiReply::VaultInserted).unwrap();
+                    customize_toast(self.toasts.success(format!("vault {} saved", vault.name)));
+                }
+                ServiceReply::UpdateVault(_) => self.tx_parent.send(UiReply::VaultUpdated(true)).unwrap(),
+                ServiceReply::DeleteVault(_) => {
+                    self.tx_parent.send(UiReply::VaultDeleted).unwrap();
+                    customize_toast(self.toasts.success("vault deleted"));
+                }
                 ServiceReply::UnlockVaultReply(_) => {
                     self.locked = false;
                     customize_toast(self.toasts.success("vault unlocked"));
@@ -76,14 +130,36 @@ impl eframe::App for ViewGroupDetail {
                     self.tx_parent.send(UiReply::VaultUpdated(false)).unwrap();
                 }
                 ServiceReply::ChangeMountPoint(_) => {
-                    self.db_reload();
+                    self.reload();
                     customize_toast(self.toasts.success("mount point changed"));
                 }
                 ServiceReply::ChangeDataDir(_) => {
-                    self.db_reload();
+                    self.reload();
                     customize_toast(self.toasts.success("data dir changed"));
                 }
-                ServiceReply::VaultServiceError(err) => customize_toast(self.toasts.error(err.to_string())),
+                ServiceReply::ForgetPassword(_) => customize_toast(self.toasts.success("password forgotten")),
+                ServiceReply::ExtendUnlock(_) => customize_toast(self.toasts.success("unlock extended")),
+                ServiceReply::Schedules(schedules) => self.load_schedules(schedules),
+                ServiceReply::ScheduleChanged => {
+                    self.daemon_service.list_schedules();
+                    customize_toast(self.toasts.success("schedules saved"));
+                }
+                ServiceReply::OperationProgress(progress) => self.operation = Some(progress),
+                ServiceReply::OperationCancelled => customize_toast(self.toasts.info("cancelled")),
+                ServiceReply::VaultServiceError(VaultServiceError::VaultHandlerError(VaultHandlerError::InvalidPassword)) => {
+                    // let the user try again
+                    self.password_error = Some("invalid password, please try again".to_string());
+                    self.password_prompt_open = true;
+                }
+                ServiceReply::VaultServiceError(VaultServiceError::VaultHandlerError(VaultHandlerError::PasswordRequired)) => {
+                    // nothing saved in keyring, ask for it
+                    self.password_error = None;
+                    self.password_prompt_open = true;
+                }
+                ServiceReply::VaultServiceError(err @ VaultServiceError::VaultHandlerError(VaultHandlerError::MountPointBusy { .. })) => {
+                    self.lock_busy_message = Some(user_message(&err));
+                }
+                ServiceReply::VaultServiceError(err) => customize_toast_duration(self.toasts.error(user_message(&err)), 10),
                 ServiceReply::Error(s) => customize_toast(self.toasts.error(s.clone())),
             }
         }
@@ -94,21 +170,78 @@ impl eframe::App for ViewGroupDetail {
                     ui.horizontal(|ui| {
                         ui.set_max_width(80.0);
                         ui.vertical_centered(|ui| {
-                            if Button::new(if self.locked { "Unlock vault" } else { "Lock vault" })
+                            // the running operation tells when it's done
+                            let busy = matches!(self.state, VaultState::Unlocking | VaultState::Locking | VaultState::Migrating);
+                            if ui.add_enabled(!busy, Button::new(if self.locked { "Unlock vault" } else { "Lock vault" })
                                 .fill(if self.locked { ecolor::Color32::DARK_GRAY } else { ecolor::Color32::DARK_GREEN })
-                                .min_size(egui::vec2(80.0, 30.0))
-                                .ui(ui).on_hover_ui(|ui| {
+                                .min_size(egui::vec2(80.0, 30.0)))
+                                .on_hover_ui(|ui| {
                                 ui.label(if self.locked { "Unlock the vault" } else { "Lock the vault" });
+                            }).on_disabled_hover_ui(|ui| {
+                                ui.label(format!("The vault is {}", self.state.as_str_name().to_lowercase()));
                             }).clicked() {
                                 if self.locked {
-                                    self.daemon_service.unlock_vault();
-                                    customize_toast_duration(self.toasts.warning("please wait, it takes up to 10 seconds to unlock the vault, you will be notified"), 8);
+                                    if self.remember_password {
+                                        // try with the one from keyring first, we'll be asked for it if missing
+                                        self.daemon_service.unlock_vault(None, Some(self.unlock_duration_secs));
+                                        self.operation = Some(OperationProgress::default());
+                                    } else {
+                                        self.password_error = None;
+                                        self.password_prompt_open = true;
+                                    }
                                 } else {
-                                    self.daemon_service.lock_vault();
+                                    self.daemon_service.lock_vault(false);
                                 }
                             }
                         });
                     });
+                    if matches!(self.state, VaultState::Error | VaultState::Crashed) {
+                        ui.colored_label(ecolor::Color32::RED, format!("The vault is in {} state", self.state.as_str_name().to_lowercase()));
+                    }
+                    ui.horizontal(|ui| {
+                        if self.locked {
+                            egui::ComboBox::from_id_source("unlock_duration")
+                                .selected_text(duration_label(self.unlock_duration_secs))
+                                .show_ui(ui, |ui| {
+                                    for secs in UNLOCK_DURATIONS {
+                                        ui.selectable_value(&mut self.unlock_duration_secs, secs, duration_label(secs));
+                                    }
+                                }).response.on_hover_ui(|ui| {
+                                ui.label("Lock the vault automatically after this long");
+                            });
+                        } else if let Some(lock_at) = self.lock_at {
+                            ui.label(format!("locks in {}", time_left(lock_at)));
+                            if ui.button("+30 min").on_hover_ui(|ui| {
+                                ui.label("Keep it unlocked 30 minutes more");
+                            }).clicked() {
+                                self.daemon_service.extend_unlock(30 * 60);
+                            }
+                            ctx.request_repaint_after(Duration::from_secs(1));
+                        }
+                    });
+                }
+                if let Some(operation) = &self.operation {
+                    let mut cancel = false;
+                    ui.horizontal(|ui| {
+                        let mut text = if operation.phase.is_empty() { "starting".to_string() } else { operation.phase.clone() };
+                        if let Some(eta) = operation.eta_secs {
+                            text.push_str(&format!(", {}s left", eta));
+                        }
+                        ProgressBar::new(operation.percent / 100.0)
+                            .text(text)
+                            .desired_width(300.0)
+                            .ui(ui);
+                        // id is 0 until we get the first progress from daemon
+                        if ui.add_enabled(operation.operation_id != 0, Button::new("Cancel")).clicked() {
+                            cancel = true;
+                        }
+                    });
+                    if cancel {
+                        let operation_id = operation.operation_id;
+                        self.daemon_service.cancel_operation(operation_id);
+                    }
+                    // we get progress over a channel, keep repainting so it shows up
+                    ctx.request_repaint_after(Duration::from_millis(200));
                 }
                 ui.horizontal(|ui| {
                     ui.label("Name");
@@ -139,14 +272,14 @@ impl eframe::App for ViewGroupDetail {
                                     let path = path.display().to_string();
                                     if self.id.is_some() {
                                         if !self.locked {
-                                            customize_toast_duration(self.toasts.warning("please wait, it takes up to 10 seconds to change mount point, you will be notified"), 8);
+                                            customize_toast_duration(self.toasts.warning("please wait while changing mount point, you will be notified"), 8);
                                             customize_toast_duration(self.toasts.warning("it will lock the vault meanwhile"), 8)
                                         }
-                                        let old_path = self.mount_point.as_ref().unwrap().clone();
-                                        self.db_service.update(mount_point.eq(path.clone()));
-                                        self.daemon_service.change_mount_point(old_path);
+                                        // daemon updates db and remounts if needed, we reload when done
+                                        self.daemon_service.change_mount_point(path);
+                                    } else {
+                                        self.mount_point = Some(path);
                                     }
-                                    self.mount_point = Some(path);
                                 }
                             }
                         }
@@ -174,53 +307,103 @@ impl eframe::App for ViewGroupDetail {
                                 } else {
                                     let path = path.display().to_string();
                                     if self.id.is_some() {
+                                        self.operation = Some(OperationProgress::default());
                                         if !self.locked {
-                                            customize_toast_duration(self.toasts.warning("it could take longer to move the data to the new location, you will be notified"), 8);
                                             customize_toast_duration(self.toasts.warning("it will lock the vault meanwhile"), 8)
                                         }
-                                        let old_path = self.data_dir.as_ref().unwrap().clone();
-                                        self.db_service.update(data_dir.eq(path.clone()));
-                                        self.daemon_service.change_data_dir(old_path);
+                                        // daemon moves the content and updates db, we reload when done
+                                        self.daemon_service.change_data_dir(path);
+                                    } else {
+                                        self.data_dir = Some(path);
                                     }
-                                    self.data_dir = Some(path);
                                 }
                             }
                         }
                     }
                 });
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut self.remember_password, "Remember password").on_hover_ui(|ui| {
+                        ui.label("Save the password in the system keyring on next unlock");
+                    }).changed() {
+                        self.ui_on_remember_password_changed();
+                    }
+                    if self.id.is_some() && self.remember_password {
+                        if ui.button("Forget password").on_hover_ui(|ui| {
+                            ui.label("Remove the password from the system keyring");
+                        }).clicked() {
+                            self.daemon_service.forget_password();
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Restart on exit");
+                    let prev = self.restart_policy;
+                    egui::ComboBox::from_id_source("restart_policy")
+                        .selected_text(self.restart_policy.as_str())
+                        .show_ui(ui, |ui| {
+                            for policy in RestartPolicy::ALL {
+                                ui.selectable_value(&mut self.restart_policy, policy, policy.as_str());
+                            }
+                        }).response.on_hover_ui(|ui| {
+                        ui.label("What to do if rencfs process exits while the vault is unlocked, applies on next unlock");
+                    });
+                    if prev != self.restart_policy {
+                        self.ui_on_restart_policy_changed();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Lock after");
+                    let res = DragValue::new(&mut self.idle_timeout_mins)
+                        .clamp_range(0..=VaultServiceError::MAX_IDLE_TIMEOUT_MINS)
+                        .suffix(" min")
+                        .ui(ui).on_hover_ui(|ui| {
+                        ui.label("Lock the vault when it's not used for this long, 0 to never lock");
+                    });
+                    // don't save on every step while dragging or typing
+                    if !res.dragged() && !res.has_focus() && self.idle_timeout_mins != self.saved_idle_timeout_mins {
+                        self.ui_on_idle_timeout_changed();
+                    }
+                    if self.idle_timeout_mins == 0 {
+                        ui.label("never");
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut self.lock_with_session, "Lock with session").on_hover_ui(|ui| {
+                        ui.label("Lock the vault when the screen is locked, the system sleeps or shuts down");
+                    }).changed() {
+                        self.ui_on_lock_with_session_changed();
+                    }
+                });
+                if let Some(code) = self.last_exit_status {
+                    ui.horizontal(|ui| {
+                        ui.label("Last exit status");
+                        ui.monospace(code.to_string());
+                    });
+                }
+                if self.id.is_some() {
+                    egui::CollapsingHeader::new("Schedules")
+                        .default_open(!self.schedules.is_empty())
+                        .show(ui, |ui| self.ui_schedules(ui));
+                }
                 ui.separator();
                 ui.horizontal(|ui| {
                     if self.id.is_none() {
                         if ui.button("Save").clicked() {
                             self.name = self.name.trim().to_string();
 
-                            let mut msg = None;
                             let mut err = None;
                             if self.name.is_empty() {
-                                err = Some("invalid name".into());
+                                err = Some("invalid name");
                             } else if self.mount_point.is_none() {
-                                err = Some("invalid mount point".into());
+                                err = Some("invalid mount point");
                             } else if self.data_dir.is_none() {
-                                err = Some("invalid data dir".into());
+                                err = Some("invalid data dir");
                             } else {
-                                match self.db_insert() {
-                                    Ok(_) => {
-                                        self.tx_parent.send(UiReply::VaultInserted).unwrap();
-                                        msg = Some(format!("vault {} saved", self.name));
-                                    }
-                                    Err(DatabaseError(UniqueViolation, _)) => {
-                                        err = Some(format!("another vault named {} exists", self.name));
-                                    }
-                                    Err(err2) => {
-                                        err = Some(format!("failed to save: {:?}", err2));
-                                    }
-                                }
+                                // daemon validates the rest, we get the result as a reply
+                                self.create();
                             }
-                            if msg.is_some() {
-                                customize_toast(self.toasts.success(msg.unwrap()))
-                            }
-                            if err.is_some() {
-                                customize_toast(self.toasts.error(err.unwrap()))
+                            if let Some(err) = err {
+                                customize_toast(self.toasts.error(err))
                             }
                         }
                     }
@@ -238,13 +421,8 @@ impl eframe::App for ViewGroupDetail {
                             } else {
                                 // confirmed, delete
                                 self.confirmation_delete_pending = false;
-                                // TODO move to service
-                                if let Err(err) = self.db_service.delete() {
-                                    customize_toast(self.toasts.error(format!("failed to delete: {:?}", err)))
-                                } else {
-                                    self.tx_parent.send(UiReply::VaultDeleted).unwrap();
-                                    customize_toast(self.toasts.success("vault deleted"))
-                                }
+                                // daemon also locks it and removes the password from keyring
+                                self.daemon_service.delete_vault();
                             }
                         }
                         if self.confirmation_delete_pending {
@@ -261,6 +439,73 @@ impl eframe::App for ViewGroupDetail {
             });
         });
 
+        if self.password_prompt_open {
+            let mut unlock = false;
+            let mut cancel = false;
+            egui::Window::new("Unlock vault")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Password");
+                        let res = TextEdit::singleline(&mut self.password)
+                            .password(true)
+                            .ui(ui);
+                        res.request_focus();
+                        if res.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                            unlock = true;
+                        }
+                    });
+                    if let Some(err) = &self.password_error {
+                        ui.colored_label(ecolor::Color32::RED, err);
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Unlock").clicked() {
+                            unlock = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            cancel = true;
+                        }
+                    });
+                });
+            if unlock && !self.password.is_empty() {
+                self.password_prompt_open = false;
+                self.daemon_service.unlock_vault(Some(std::mem::take(&mut self.password)), Some(self.unlock_duration_secs));
+                self.operation = Some(OperationProgress::default());
+            } else if cancel {
+                self.password_prompt_open = false;
+                self.password.clear();
+            }
+        }
+
+        if let Some(message) = &self.lock_busy_message {
+            let mut force = false;
+            let mut cancel = false;
+            egui::Window::new("Vault in use")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label(message);
+                    ui.colored_label(ecolor::Color32::RED, "Forcing it closes the files, unsaved changes are lost.");
+                    ui.horizontal(|ui| {
+                        if ui.button("Force lock").clicked() {
+                            force = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            cancel = true;
+                        }
+                    });
+                });
+            if force {
+                self.lock_busy_message = None;
+                self.daemon_service.lock_vault(true);
+            } else if cancel {
+                self.lock_busy_message = None;
+            }
+        }
+
         self.toasts.show(ctx);
     }
 }
@@ -281,11 +526,27 @@ impl ViewGroupDetail {
             mount_point: None,
             data_dir: None,
             locked: true,
+            state: VaultState::Locked,
+            remember_password: false,
+            restart_policy: RestartPolicy::default(),
+            last_exit_status: None,
+            idle_timeout_mins: 0,
+            lock_with_session: false,
+            lock_at: None,
+            schedules: vec![],
+            saved_schedules: vec![],
             confirmation_delete_pending: false,
+            password_prompt_open: false,
+            password: "".to_string(),
+            password_error: None,
+            lock_busy_message: None,
+            unlock_duration_secs: 0,
+            operation: None,
             rx_service,
-            tx_parent: tx_parent.clone(),
+            tx_parent,
             daemon_service,
-            db_service: DbService::new(None, tx_parent),
+            saved_name: "".to_string(),
+            saved_idle_timeout_mins: 0,
             toasts: Toasts::default(),
         })
     }
@@ -297,48 +558,213 @@ impl ViewGroupDetail {
         if let Err(err) = daemon_service {
             return Err(err);
         }
-        let daemon_service = daemon_service.unwrap();
+        let mut daemon_service = daemon_service.unwrap();
+        daemon_service.list_schedules();
 
         Ok(ViewGroupDetail {
             id: Some(item.id),
+            saved_name: item.name.clone(),
             name: item.name,
             mount_point: Some(item.mount_point),
             data_dir: Some(item.data_dir),
             locked: item.locked,
+            state: item.state,
+            remember_password: item.remember_password,
+            restart_policy: RestartPolicy::from_str(&item.restart_policy).unwrap_or_default(),
+            last_exit_status: item.last_exit_status,
+            idle_timeout_mins: item.idle_timeout_mins,
+            lock_with_session: item.lock_with_session,
+            lock_at: item.lock_at,
+            schedules: vec![],
+            saved_schedules: vec![],
+            saved_idle_timeout_mins: item.idle_timeout_mins,
             confirmation_delete_pending: false,
+            password_prompt_open: false,
+            password: "".to_string(),
+            password_error: None,
+            lock_busy_message: None,
+            unlock_duration_secs: 0,
+            operation: None,
             rx_service,
-            tx_parent: tx_parent.clone(),
+            tx_parent,
             daemon_service,
-            db_service: DbService::new(Some(item.id), tx_parent),
             toasts: Toasts::default(),
         })
     }
 
-    fn db_insert(&mut self) -> QueryResult<()> {
-        let new_vault = NewVault {
+    fn create(&mut self) {
+        self.daemon_service.create_vault(CreateVaultRequest {
             name: self.name.clone(),
             mount_point: self.mount_point.as_ref().unwrap().clone(),
             data_dir: self.data_dir.as_ref().unwrap().clone(),
-        };
-        self.db_service.insert(new_vault)
+            remember_password: self.remember_password,
+            restart_policy: self.restart_policy.as_str().to_string(),
+            idle_timeout_mins: self.idle_timeout_mins,
+            lock_with_session: self.lock_with_session,
+        });
     }
 
-    fn db_reload(&mut self) {
-        let vault = self.db_service.get_vault().unwrap();
-        self.name = vault.name;
+    /// Keeps the view in sync with changes made by daemon or other clients.
+    pub(crate) fn on_vault_event(&mut self, event: &VaultEvent) {
+        // when deleted dashboard closes the view
+        if self.id == Some(event.id as i32) && event.kind() != Kind::Deleted {
+            self.reload();
+        }
+    }
+
+    fn reload(&mut self) {
+        self.daemon_service.get_vault();
+        self.daemon_service.list_schedules();
+    }
+
+    fn load(&mut self, vault: VaultInfo) {
+        self.name = vault.name.clone();
+        self.saved_name = vault.name;
         self.mount_point = Some(vault.mount_point);
         self.data_dir = Some(vault.data_dir);
-        self.locked = vault.locked == 1;
-        self.tx_parent.send(UiReply::VaultUpdated(false)).unwrap();
+        self.locked = vault.locked;
+        self.state = vault.state();
+        self.remember_password = vault.remember_password;
+        self.restart_policy = RestartPolicy::from_str(&vault.restart_policy).unwrap_or_default();
+        self.last_exit_status = vault.last_exit_status;
+        self.idle_timeout_mins = vault.idle_timeout_mins;
+        self.saved_idle_timeout_mins = vault.idle_timeout_mins;
+        self.lock_with_session = vault.lock_with_session;
+        self.lock_at = vault.lock_at;
+    }
+
+    fn load_schedules(&mut self, schedules: Vec<ScheduleInfo>) {
+        // keep the edits of the ones not changed meanwhile
+        self.schedules = schedules.iter()
+            .map(|schedule| match self.saved_schedules.iter().position(|saved| saved == schedule) {
+                Some(i) => self.schedules.get(i).cloned().unwrap_or_else(|| schedule.clone()),
+                None => schedule.clone(),
+            })
+            .collect();
+        self.saved_schedules = schedules;
+    }
+
+    fn ui_schedules(&mut self, ui: &mut egui::Ui) {
+        let mut save = None;
+        let mut revert = None;
+        let mut delete = None;
+        for (i, schedule) in self.schedules.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut schedule.enabled, "").on_hover_ui(|ui| {
+                    ui.label("Run this schedule");
+                });
+                let mut action = ScheduleAction::from_str(&schedule.action).unwrap_or_default();
+                egui::ComboBox::from_id_source(("schedule_action", schedule.id))
+                    .selected_text(action.as_str())
+                    .width(70.0)
+                    .show_ui(ui, |ui| {
+                        for a in ScheduleAction::ALL {
+                            ui.selectable_value(&mut action, a, a.as_str());
+                        }
+                    });
+                schedule.action = action.as_str().to_string();
+                ui.label("at");
+                let (mut hour, mut minute) = (schedule.minute_of_day / 60, schedule.minute_of_day % 60);
+                DragValue::new(&mut hour)
+                    .clamp_range(0..=23)
+                    .custom_formatter(|n, _| format!("{:02}", n as u32))
+                    .ui(ui);
+                ui.label(":");
+                DragValue::new(&mut minute)
+                    .clamp_range(0..=59)
+                    .custom_formatter(|n, _| format!("{:02}", n as u32))
+                    .ui(ui);
+                schedule.minute_of_day = hour * 60 + minute;
+                for (day, name) in DAY_NAMES.iter().enumerate() {
+                    let mut on = schedule.days & (1 << day) != 0;
+                    if ui.toggle_value(&mut on, *name).changed() {
+                        schedule.days ^= 1 << day;
+                    }
+                }
+                if self.saved_schedules.get(i) != Some(&*schedule) {
+                    if ui.button("Save").clicked() {
+                        save = Some(schedule.clone());
+                    }
+                    if ui.button("Revert").clicked() {
+                        revert = Some(i);
+                    }
+                }
+                if ui.button("Remove").clicked() {
+                    delete = Some(schedule.id);
+                }
+            });
+        }
+        if self.schedules.iter().any(|s| s.action == ScheduleAction::Unlock.as_str()) && !self.remember_password {
+            ui.label("Unlocking on schedule needs Remember password, as the password is read from the keyring");
+        }
+        if ui.button("Add schedule").on_hover_ui(|ui| {
+            ui.label("Lock or unlock the vault at a time of day, times missed while asleep are caught up when waking, except unlocks");
+        }).clicked() {
+            self.daemon_service.create_schedule(CreateScheduleRequest {
+                action: ScheduleAction::Lock.as_str().to_string(),
+                minute_of_day: 19 * 60,
+                days: WEEKDAYS as u32,
+                enabled: true,
+                ..Default::default()
+            });
+        }
+
+        if let Some(schedule) = save {
+            self.daemon_service.update_schedule(UpdateScheduleRequest {
+                id: schedule.id,
+                action: Some(schedule.action),
+                minute_of_day: Some(schedule.minute_of_day),
+                days: Some(schedule.days),
+                enabled: Some(schedule.enabled),
+            });
+        }
+        if let Some(i) = revert {
+            self.schedules[i] = self.saved_schedules[i].clone();
+        }
+        if let Some(id) = delete {
+            self.daemon_service.delete_schedule(id);
+        }
+    }
+
+    fn ui_on_remember_password_changed(&mut self) {
+        if let Some(_) = self.id {
+            // daemon also forgets the saved password when turned off
+            self.daemon_service.update_vault(UpdateVaultRequest { remember_password: Some(self.remember_password), ..Default::default() });
+        }
+    }
+
+    fn ui_on_restart_policy_changed(&mut self) {
+        if let Some(_) = self.id {
+            self.daemon_service.update_vault(UpdateVaultRequest { restart_policy: Some(self.restart_policy.as_str().to_string()), ..Default::default() });
+        }
+    }
+
+    fn ui_on_lock_with_session_changed(&mut self) {
+        if let Some(_) = self.id {
+            self.daemon_service.update_vault(UpdateVaultRequest { lock_with_session: Some(self.lock_with_session), ..Default::default() });
+        }
+    }
+
+    fn ui_on_idle_timeout_changed(&mut self) {
+        if let Some(_) = self.id {
+            self.saved_idle_timeout_mins = self.idle_timeout_mins;
+            self.daemon_service.update_vault(UpdateVaultRequest { idle_timeout_mins: Some(self.idle_timeout_mins), ..Default::default() });
+        }
     }
 
     fn ui_on_name_lost_focus(&mut self) {
         if let Some(_) = self.id {
-            let old_name = self.db_service.get_vault().unwrap().name;
-            if old_name != self.name {
-                self.db_service.update(name.eq(self.name.clone()));
-                self.tx_parent.send(UiReply::VaultUpdated(true)).unwrap();
+            if self.saved_name != self.name {
+                self.daemon_service.update_vault(UpdateVaultRequest { name: Some(self.name.clone()), ..Default::default() });
             }
         }
     }
 }
+
+fn duration_label(secs: u32) -> String {
+    match secs {
+        0 => "until locked".to_string(),
+        secs if secs < 3600 => format!("for {} min", secs / 60),
+        secs => format!("for {} h", secs / 3600),
+    }
+}
@@ -1,10 +1,12 @@
 use std::sync::mpsc::{Sender};
 use tonic::{Response, Status};
-use tonic::transport::{Channel, Error};
+use tonic::transport::Error;
 use tracing::{error, instrument};
+use rencfs_desktop_common::error_messages::{status_message, user_message};
 use rencfs_desktop_common::vault_service_error::VaultServiceError;
-use crate::daemon_service::{EmptyReply, IdRequest, StringIdRequest};
-use crate::daemon_service::vault_service_client::VaultServiceClient;
+use crate::daemon_service::{CreateScheduleRequest, CreateVaultRequest, EmptyReply, ExtendUnlockRequest, IdRequest, LockRequest, OperationIdRequest, OperationReply, ScheduleList, StringIdRequest, UnlockRequest, UpdateScheduleRequest, UpdateVaultRequest};
+use crate::daemon_service::operation_progress::State;
+use crate::daemon_client::{self, Client};
 use crate::dashboard::UiReply;
 use crate::detail::ServiceReply;
 use crate::RT;
@@ -13,7 +15,7 @@ pub(super) struct DaemonService {
     id: Option<i32>,
     tx_service: Sender<ServiceReply>,
     tx_parent: Sender<UiReply>,
-    client: VaultServiceClient<Channel>,
+    client: Client,
 }
 
 impl DaemonService {
@@ -28,7 +30,7 @@ impl DaemonService {
             }, |client| Ok(Self { id, tx_service, tx_parent, client }))
     }
 
-    pub(super) fn unlock_vault(&mut self) {
+    pub(super) fn get_vault(&mut self) {
         let id = self.id.as_ref().unwrap().clone() as u32;
         let tx = self.tx_service.clone();
         let tx_parent = self.tx_parent.clone();
@@ -37,11 +39,80 @@ impl DaemonService {
             let request = tonic::Request::new(IdRequest {
                 id,
             });
-            Self::handle_empty_response(client.unlock(request).await, ServiceReply::UnlockVaultReply, tx, tx_parent);
+            Self::handle_response(client.get_vault(request).await, ServiceReply::GetVault, tx, tx_parent);
         });
     }
 
-    pub(super) fn lock_vault(&mut self) {
+    pub(super) fn create_vault(&mut self, request: CreateVaultRequest) {
+        let tx = self.tx_service.clone();
+        let tx_parent = self.tx_parent.clone();
+        let mut client = self.client.clone();
+        RT.spawn(async move {
+            let request = tonic::Request::new(request);
+            Self::handle_response(client.create_vault(request).await, ServiceReply::CreateVault, tx, tx_parent);
+        });
+    }
+
+    /// Only the `Some` fields of `changes` are changed, its `id` is ignored.
+    pub(super) fn update_vault(&mut self, changes: UpdateVaultRequest) {
+        let id = self.id.as_ref().unwrap().clone() as u32;
+        let tx = self.tx_service.clone();
+        let tx_parent = self.tx_parent.clone();
+        let mut client = self.client.clone();
+        RT.spawn(async move {
+            let request = tonic::Request::new(UpdateVaultRequest {
+                id,
+                ..changes
+            });
+            Self::handle_response(client.update_vault(request).await, ServiceReply::UpdateVault, tx, tx_parent);
+        });
+    }
+
+    pub(super) fn delete_vault(&mut self) {
+        let id = self.id.as_ref().unwrap().clone() as u32;
+        let tx = self.tx_service.clone();
+        let tx_parent = self.tx_parent.clone();
+        let mut client = self.client.clone();
+        RT.spawn(async move {
+            let request = tonic::Request::new(IdRequest {
+                id,
+            });
+            Self::handle_response(client.delete_vault(request).await, ServiceReply::DeleteVault, tx, tx_parent);
+        });
+    }
+
+    /// With `duration_secs` it's locked automatically after that long.
+    pub(super) fn unlock_vault(&mut self, password: Option<String>, duration_secs: Option<u32>) {
+        let id = self.id.as_ref().unwrap().clone() as u32;
+        let tx = self.tx_service.clone();
+        let tx_parent = self.tx_parent.clone();
+        let mut client = self.client.clone();
+        RT.spawn(async move {
+            let request = tonic::Request::new(UnlockRequest {
+                id,
+                password,
+                duration_secs,
+            });
+            let res = client.start_unlock(request).await;
+            Self::watch_operation(&mut client, res, ServiceReply::UnlockVaultReply, tx, tx_parent).await;
+        });
+    }
+
+    pub(super) fn extend_unlock(&mut self, secs: u32) {
+        let id = self.id.as_ref().unwrap().clone() as u32;
+        let tx = self.tx_service.clone();
+        let tx_parent = self.tx_parent.clone();
+        let mut client = self.client.clone();
+        RT.spawn(async move {
+            let request = tonic::Request::new(ExtendUnlockRequest {
+                id,
+                secs,
+            });
+            Self::handle_response(client.extend_unlock(request).await, ServiceReply::ExtendUnlock, tx, tx_parent);
+        });
+    }
+
+    pub(super) fn list_schedules(&mut self) {
         let id = self.id.as_ref().unwrap().clone() as u32;
         let tx = self.tx_service.clone();
         let tx_parent = self.tx_parent.clone();
@@ -50,7 +121,59 @@ impl DaemonService {
             let request = tonic::Request::new(IdRequest {
                 id,
             });
-            Self::handle_empty_response(client.lock(request).await, ServiceReply::LockVaultReply, tx, tx_parent);
+            Self::handle_response(client.list_schedules(request).await, |list: ScheduleList| ServiceReply::Schedules(list.schedules), tx, tx_parent);
+        });
+    }
+
+    /// `request.vault_id` is ignored, it's added to this vault.
+    pub(super) fn create_schedule(&mut self, request: CreateScheduleRequest) {
+        let vault_id = self.id.as_ref().unwrap().clone() as u32;
+        let tx = self.tx_service.clone();
+        let tx_parent = self.tx_parent.clone();
+        let mut client = self.client.clone();
+        RT.spawn(async move {
+            let request = tonic::Request::new(CreateScheduleRequest {
+                vault_id,
+                ..request
+            });
+            Self::handle_response(client.create_schedule(request).await, |_| ServiceReply::ScheduleChanged, tx, tx_parent);
+        });
+    }
+
+    pub(super) fn update_schedule(&mut self, request: UpdateScheduleRequest) {
+        let tx = self.tx_service.clone();
+        let tx_parent = self.tx_parent.clone();
+        let mut client = self.client.clone();
+        RT.spawn(async move {
+            let request = tonic::Request::new(request);
+            Self::handle_response(client.update_schedule(request).await, |_| ServiceReply::ScheduleChanged, tx, tx_parent);
+        });
+    }
+
+    pub(super) fn delete_schedule(&mut self, id: u32) {
+        let tx = self.tx_service.clone();
+        let tx_parent = self.tx_parent.clone();
+        let mut client = self.client.clone();
+        RT.spawn(async move {
+            let request = tonic::Request::new(IdRequest {
+                id,
+            });
+            Self::handle_response(client.delete_schedule(request).await, |_| ServiceReply::ScheduleChanged, tx, tx_parent);
+        });
+    }
+
+    /// With `force` it's locked even if files are open in the vault.
+    pub(super) fn lock_vault(&mut self, force: bool) {
+        let id = self.id.as_ref().unwrap().clone() as u32;
+        let tx = self.tx_service.clone();
+        let tx_parent = self.tx_parent.clone();
+        let mut client = self.client.clone();
+        RT.spawn(async move {
+            let request = tonic::Request::new(LockRequest {
+                id,
+                force,
+            });
+            Self::handle_response(client.lock(request).await, ServiceReply::LockVaultReply, tx, tx_parent);
         });
     }
 
@@ -64,7 +187,7 @@ impl DaemonService {
                 id,
                 value,
             });
-            Self::handle_empty_response(client.change_mount_point(request).await, ServiceReply::ChangeMountPoint, tx, tx_parent);
+            Self::handle_response(client.change_mount_point(request).await, ServiceReply::ChangeMountPoint, tx, tx_parent);
         });
     }
 
@@ -78,12 +201,78 @@ impl DaemonService {
                 id,
                 value,
             });
-            Self::handle_empty_response(client.change_data_dir(request).await, ServiceReply::ChangeDataDir, tx, tx_parent);
+            let res = client.start_change_data_dir(request).await;
+            Self::watch_operation(&mut client, res, ServiceReply::ChangeDataDir, tx, tx_parent).await;
         });
     }
 
-    #[instrument(skip(f))]
-    fn handle_empty_response(result: Result<Response<EmptyReply>, Status>, f: impl FnOnce(EmptyReply) -> ServiceReply,
+    pub(super) fn forget_password(&mut self) {
+        let id = self.id.as_ref().unwrap().clone() as u32;
+        let tx = self.tx_service.clone();
+        let tx_parent = self.tx_parent.clone();
+        let mut client = self.client.clone();
+        RT.spawn(async move {
+            let request = tonic::Request::new(IdRequest {
+                id,
+            });
+            Self::handle_response(client.forget_password(request).await, ServiceReply::ForgetPassword, tx, tx_parent);
+        });
+    }
+
+    pub(super) fn cancel_operation(&mut self, operation_id: u64) {
+        let tx = self.tx_service.clone();
+        let tx_parent = self.tx_parent.clone();
+        let mut client = self.client.clone();
+        RT.spawn(async move {
+            let request = tonic::Request::new(OperationIdRequest {
+                operation_id,
+            });
+            if let Err(err) = client.cancel_operation(request).await {
+                Self::handle_response(Err::<Response<EmptyReply>, _>(err), |_| ServiceReply::OperationCancelled, tx, tx_parent);
+            }
+        });
+    }
+
+    /// Forwards progress to the component until the operation finishes, then handles the result like a unary call.
+    #[instrument(skip(client, f, tx, tx_parent))]
+    async fn watch_operation(client: &mut Client, start: Result<Response<OperationReply>, Status>,
+                             f: impl FnOnce(EmptyReply) -> ServiceReply, tx: Sender<ServiceReply>, tx_parent: Sender<UiReply>) {
+        let operation_id = match start {
+            Ok(response) => response.into_inner().operation_id,
+            Err(err) => return Self::handle_response(Err(err), f, tx, tx_parent),
+        };
+        let request = tonic::Request::new(OperationIdRequest {
+            operation_id,
+        });
+        let mut stream = match client.watch_operation(request).await {
+            Ok(response) => response.into_inner(),
+            Err(err) => return Self::handle_response(Err(err), f, tx, tx_parent),
+        };
+        loop {
+            match stream.message().await {
+                Ok(Some(progress)) => match progress.state() {
+                    State::Running => {
+                        // component could be destroyed meanwhile, we still wait for the result to notify parent
+                        let _ = tx.send(ServiceReply::OperationProgress(progress));
+                    }
+                    State::Succeeded => return Self::handle_response(Ok(Response::new(EmptyReply {})), f, tx, tx_parent),
+                    State::Cancelled => {
+                        let _ = tx.send(ServiceReply::OperationCancelled);
+                        return;
+                    }
+                },
+                Ok(None) => {
+                    error!("Operation stream ended without a result");
+                    let _ = tx.send(ServiceReply::Error("lost track of the operation".to_string()));
+                    return;
+                }
+                Err(err) => return Self::handle_response(Err(err), f, tx, tx_parent),
+            }
+        }
+    }
+
+    #[instrument(skip(result, f))]
+    fn handle_response<T>(result: Result<Response<T>, Status>, f: impl FnOnce(T) -> ServiceReply,
                              tx: Sender<ServiceReply>, tx_parent: Sender<UiReply>) {
         match result {
             Ok(response) => {
@@ -103,26 +292,25 @@ impl DaemonService {
                             .map_err(|_| {
                                 // in case the component is destroyed before the response is received we will not be able to notify service reply because the rx is closed
                                 // in that case notify parent with error because it's rx is still open
-                                let _ = tx_parent.send(UiReply::Error(err2.to_string()));
+                                let _ = tx_parent.send(UiReply::Error(user_message(&err2)));
                             });
                     }
                     _ => {
                         error!(err = %err);
-                        let res = tx.send(ServiceReply::Error(format!("Error: {}", err)));
-                        if let Err(err) = res {
-                            // in case the component is destroyed before the response is received we will not be able to notify service reply because the rx is closed
-                            // in that case notify parent with error because it's rx is still open
-                            let _ = tx_parent.send(UiReply::Error(err.to_string()));
-                        }
+                        let _ = tx.send(ServiceReply::Error(status_message(&err)))
+                            .map_err(|_| {
+                                // in case the component is destroyed before the response is received we will not be able to notify service reply because the rx is closed
+                                // in that case notify parent with error because it's rx is still open
+                                let _ = tx_parent.send(UiReply::Error(status_message(&err)));
+                            });
                     }
                 }
             }
         }
     }
 
-    async fn create_client(tx: Sender<ServiceReply>, tx_parent: Sender<UiReply>) -> Result<VaultServiceClient<Channel>, Error> {
-        // TODO: resolve port dynamically
-        VaultServiceClient::connect("http://[::1]:50051").await
+    async fn create_client(tx: Sender<ServiceReply>, tx_parent: Sender<UiReply>) -> Result<Client, Error> {
+        daemon_client::connect().await
             .map_err(|err| {
                 let _ = tx.send(ServiceReply::Error(err.to_string()))
                     .map_err(|err| {
@@ -2,12 +2,9 @@ use std::backtrace::Backtrace;
 use std::panic;
 use std::panic::catch_unwind;
 use std::str::FromStr;
-use std::sync::Mutex;
-use diesel::prelude::*;
 use dotenvy::dotenv;
 use tracing::{error, instrument, Level};
 
-use rencfs_desktop_common::persistence::run_migrations;
 use static_init::dynamic;
 use tokio::runtime::Runtime;
 use crate::dashboard::Dashboard;
@@ -16,9 +13,11 @@ mod daemon_service {
     tonic::include_proto!("rencfs_desktop");
 }
 
+mod daemon_client;
 mod dashboard;
 mod detail;
 mod listview;
+mod vault_events;
 
 pub mod util;
 
@@ -27,17 +26,6 @@ pub use listview::ListView;
 #[dynamic]
 pub(crate) static RT: Runtime = Runtime::new().expect("Cannot create tokio runtime");
 
-#[dynamic]
-pub static DB_CONN: Mutex<SqliteConnection> = {
-    match rencfs_desktop_common::persistence::establish_connection() {
-        Ok(db) => { Mutex::new(db) }
-        Err(err) => {
-            error!(err = %err, "Error connecting to database");
-            panic!("Error connecting to database: {:?}", err);
-        }
-    }
-};
-
 #[instrument]
 fn main() {
     let _ = dotenv();
@@ -61,22 +49,13 @@ fn main() {
 
 #[instrument]
 fn run_main() -> Result<(), Box<dyn std::error::Error>> {
-    let conn = rencfs_desktop_common::persistence::establish_connection();
-    let mut conn = conn.unwrap_or_else(|_| {
-        error!("Error connecting to database");
-        panic!("Error connecting to database")
-    });
-    run_migrations(&mut conn).unwrap_or_else(|_| {
-        error!("Cannot run migrations");
-        panic!("Cannot run migrations")
-    });
-
-    start_ui(conn).expect("Error starting UI");
+    // daemon owns the database, we talk to it only over gRPC
+    start_ui().expect("Error starting UI");
 
     Ok(())
 }
 
-pub fn start_ui(conn: SqliteConnection) -> Result<(), eframe::Error> {
+pub fn start_ui() -> Result<(), eframe::Error> {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1200.0, 800.0]) // wide enough for the drag-drop overlay text
@@ -89,8 +68,8 @@ pub fn start_ui(conn: SqliteConnection) -> Result<(), eframe::Error> {
     eframe::run_native(
         "EncryptedFS",
         options,
-        Box::new(|_cc| {
-            Box::new(Dashboard::new(conn))
+        Box::new(|cc| {
+            Box::new(Dashboard::new(cc.egui_ctx.clone()))
         }),
     )
 }
@@ -0,0 +1,48 @@
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use eframe::egui::Context;
+use tracing::{error, info, instrument, warn};
+
+use crate::daemon_client;
+use crate::daemon_service::EmptyRequest;
+use crate::dashboard::UiReply;
+use crate::RT;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Forwards vault events from daemon to dashboard for as long as the app runs, reconnecting if daemon restarts.
+#[instrument(skip_all)]
+pub(crate) fn watch(tx: Sender<UiReply>, ctx: Context) {
+    RT.spawn(async move {
+        loop {
+            match daemon_client::connect().await {
+                Ok(mut client) => match client.watch_vaults(tonic::Request::new(EmptyRequest {})).await {
+                    Ok(response) => {
+                        info!("Watching vault events");
+                        let mut stream = response.into_inner();
+                        loop {
+                            match stream.message().await {
+                                Ok(Some(event)) => {
+                                    if tx.send(UiReply::VaultEvent(event)).is_err() {
+                                        // dashboard is gone, app is closing
+                                        return;
+                                    }
+                                    ctx.request_repaint();
+                                }
+                                Ok(None) => break,
+                                Err(err) => {
+                                    warn!(err = %err, "Vault events stream ended");
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    Err(err) => error!(err = %err, "Cannot watch vault events"),
+                },
+                Err(err) => warn!(err = %err, "Cannot connect to daemon to watch vault events"),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
@@ -0,0 +1,38 @@
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+/// A private `dbus-daemon` listening on a unix socket, killed on drop.
+///
+/// Tests fake the D-Bus services they talk to on it, like logind or the Secret Service.
+pub struct Bus {
+    child: Child,
+    socket: PathBuf,
+    pub address: String,
+}
+
+impl Bus {
+    /// Panics if `dbus-daemon` is not installed, tests using it would otherwise pass without testing anything.
+    pub fn start(socket: &Path) -> Self {
+        let mut child = Command::new("dbus-daemon")
+            .arg("--session")
+            .arg("--nofork")
+            .arg("--print-address")
+            .arg(format!("--address=unix:path={}", socket.display()))
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|err| panic!("cannot start dbus-daemon, it's needed for this test: {}", err));
+        // it prints the address once it's listening, with a guid that changes on restart so we don't use it
+        BufReader::new(child.stdout.take().unwrap()).read_line(&mut String::new()).unwrap();
+        Self { child, socket: socket.to_path_buf(), address: format!("unix:path={}", socket.display()) }
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_file(&self.socket);
+    }
+}
@@ -0,0 +1,325 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use tokio::sync::mpsc::UnboundedSender;
+use tonic::Status;
+
+use rencfs_desktop_common::error_messages::status_to_message;
+use rencfs_desktop_common::vault_handler::VaultHandlerError;
+use rencfs_desktop_common::vault_service_error::VaultServiceError;
+
+use crate::daemon::Client;
+use crate::daemon_service::{EmptyRequest, ExtendUnlockRequest, LockRequest, OperationIdRequest, StringIdRequest, UnlockRequest, UpdateVaultRequest, VaultEvent, VaultInfo};
+use crate::daemon_service::operation_progress::State;
+use crate::daemon_service::vault_event::Kind;
+
+/// How much `e` extends a time-limited unlock.
+const EXTEND_SECS: u32 = 30 * 60;
+
+pub enum AppEvent {
+    Key(KeyEvent),
+    Redraw,
+    VaultEvent(VaultEvent),
+    /// an action finished, the message is shown in the status line
+    Done(String),
+    Failed(Status),
+    /// unlock needs a password, or the one given was wrong
+    PasswordNeeded { id: u32, invalid: bool },
+    Progress(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    Password,
+    Rename,
+    MountPoint,
+    DataDir,
+    IdleTimeout,
+}
+
+impl InputKind {
+    pub fn title(&self) -> &'static str {
+        match self {
+            InputKind::Password => "Password",
+            InputKind::Rename => "New name",
+            InputKind::MountPoint => "New mount point",
+            InputKind::DataDir => "New data dir",
+            InputKind::IdleTimeout => "Lock after minutes idle, 0 to never lock",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    List,
+    Search,
+    Input(InputKind),
+}
+
+/// Like `Dashboard` and `ViewGroupDetail` in the GUI, a list of vaults and the details of the selected one.
+pub struct App {
+    client: Client,
+    tx: UnboundedSender<AppEvent>,
+
+    pub vaults: Vec<VaultInfo>,
+    /// index in [App::filtered]
+    pub selected: usize,
+    pub search: String,
+    pub mode: Mode,
+    pub input: String,
+    /// vault the input is for, it may not be the selected one if selection changed meanwhile
+    input_vault: Option<u32>,
+    /// last message and if it's an error
+    pub status: Option<(String, bool)>,
+    pub quit: bool,
+}
+
+impl App {
+    pub fn new(client: Client, tx: UnboundedSender<AppEvent>) -> Self {
+        Self {
+            client,
+            tx,
+            vaults: vec![],
+            selected: 0,
+            search: String::new(),
+            mode: Mode::List,
+            input: String::new(),
+            input_vault: None,
+            status: None,
+            quit: false,
+        }
+    }
+
+    pub fn filtered(&self) -> Vec<&VaultInfo> {
+        self.vaults.iter().filter(|v| v.name.contains(&self.search)).collect()
+    }
+
+    pub fn selected_vault(&self) -> Option<&VaultInfo> {
+        self.filtered().get(self.selected).copied()
+    }
+
+    pub async fn reload(&mut self) {
+        match self.client.list_vaults(EmptyRequest {}).await {
+            Ok(response) => self.vaults = response.into_inner().vaults,
+            Err(status) => self.status = Some((status_to_message(&status), true)),
+        }
+        self.clamp_selection();
+    }
+
+    pub async fn handle(&mut self, event: AppEvent) {
+        match event {
+            AppEvent::Key(key) => self.on_key(key),
+            AppEvent::Redraw => {}
+            AppEvent::VaultEvent(event) => {
+                self.reload().await;
+                let name = self.vaults.iter().find(|v| v.id == event.id).map_or_else(|| event.id.to_string(), |v| v.name.clone());
+                match event.kind() {
+                    Kind::Crashed => self.status = Some((format!("vault {} stopped unexpectedly", name), true)),
+                    Kind::Error => self.status = Some((format!("vault {}: {}", name, event.message.unwrap_or_default()), true)),
+                    Kind::IdleWarning => self.status = Some((
+                        format!("vault {} is not used, locking it in {}s", name, event.message.as_deref().unwrap_or("60")), true)),
+                    _ => {}
+                }
+            }
+            AppEvent::Done(message) => self.status = Some((message, false)),
+            AppEvent::Failed(status) => self.status = Some((status_to_message(&status), true)),
+            AppEvent::PasswordNeeded { id, invalid } => {
+                self.status = invalid.then(|| ("wrong password, please try again".to_string(), true));
+                self.start_input(InputKind::Password, id, String::new());
+            }
+            AppEvent::Progress(message) => self.status = Some((message, false)),
+        }
+    }
+
+    fn on_key(&mut self, key: KeyEvent) {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.quit = true;
+            return;
+        }
+        match self.mode {
+            Mode::List => self.on_list_key(key),
+            Mode::Search => match key.code {
+                KeyCode::Enter => self.mode = Mode::List,
+                KeyCode::Esc => {
+                    self.search.clear();
+                    self.mode = Mode::List;
+                }
+                KeyCode::Backspace => { self.search.pop(); }
+                KeyCode::Char(c) => self.search.push(c),
+                _ => {}
+            },
+            Mode::Input(kind) => match key.code {
+                KeyCode::Enter => {
+                    self.mode = Mode::List;
+                    let value = std::mem::take(&mut self.input);
+                    if let Some(id) = self.input_vault.take() {
+                        self.submit(kind, id, value);
+                    }
+                }
+                KeyCode::Esc => {
+                    self.mode = Mode::List;
+                    self.input.clear();
+                    self.input_vault = None;
+                }
+                KeyCode::Backspace => { self.input.pop(); }
+                KeyCode::Char(c) => self.input.push(c),
+                _ => {}
+            },
+        }
+        self.clamp_selection();
+    }
+
+    fn on_list_key(&mut self, key: KeyEvent) {
+        let Some(vault) = self.selected_vault().cloned() else {
+            match key.code {
+                KeyCode::Char('q') => self.quit = true,
+                KeyCode::Char('/') => self.mode = Mode::Search,
+                KeyCode::Esc => self.search.clear(),
+                _ => {}
+            }
+            return;
+        };
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.selected += 1,
+            KeyCode::Char('/') => self.mode = Mode::Search,
+            KeyCode::Esc => self.search.clear(),
+            KeyCode::Enter | KeyCode::Char(' ') => if vault.locked { self.unlock(&vault) } else { self.lock(&vault, false) },
+            KeyCode::Char('u') => self.unlock(&vault),
+            KeyCode::Char('l') => self.lock(&vault, false),
+            KeyCode::Char('L') => self.lock(&vault, true),
+            KeyCode::Char('r') => self.start_input(InputKind::Rename, vault.id, vault.name.clone()),
+            KeyCode::Char('m') => self.start_input(InputKind::MountPoint, vault.id, vault.mount_point.clone()),
+            KeyCode::Char('d') => self.start_input(InputKind::DataDir, vault.id, vault.data_dir.clone()),
+            KeyCode::Char('t') => self.start_input(InputKind::IdleTimeout, vault.id, vault.idle_timeout_mins.to_string()),
+            KeyCode::Char('s') => self.toggle_lock_with_session(&vault),
+            KeyCode::Char('e') => self.extend_unlock(&vault),
+            _ => {}
+        }
+    }
+
+    fn start_input(&mut self, kind: InputKind, id: u32, value: String) {
+        self.mode = Mode::Input(kind);
+        self.input = value;
+        self.input_vault = Some(id);
+    }
+
+    fn clamp_selection(&mut self) {
+        self.selected = self.selected.min(self.filtered().len().saturating_sub(1));
+    }
+
+    fn unlock(&mut self, vault: &VaultInfo) {
+        if vault.remember_password {
+            // try with the one from keyring first, we'll be asked for it if missing
+            self.spawn_unlock(vault.id, None);
+        } else {
+            self.start_input(InputKind::Password, vault.id, String::new());
+        }
+    }
+
+    /// Forcing it locks even if files are open in the vault, they're lost if not saved.
+    fn lock(&mut self, vault: &VaultInfo, force: bool) {
+        let (mut client, id, name) = (self.client.clone(), vault.id, vault.name.clone());
+        self.status = Some((format!("locking {}...", name), false));
+        self.spawn(async move {
+            client.lock(LockRequest { id, force }).await.map(|_| format!("vault {} locked", name))
+        });
+    }
+
+    fn extend_unlock(&mut self, vault: &VaultInfo) {
+        let (mut client, id) = (self.client.clone(), vault.id);
+        self.spawn(async move {
+            client.extend_unlock(ExtendUnlockRequest { id, secs: EXTEND_SECS }).await
+                .map(|_| format!("unlock extended by {} min", EXTEND_SECS / 60))
+        });
+    }
+
+    fn toggle_lock_with_session(&mut self, vault: &VaultInfo) {
+        let (mut client, id, enabled) = (self.client.clone(), vault.id, !vault.lock_with_session);
+        self.spawn(async move {
+            client.update_vault(UpdateVaultRequest { id, lock_with_session: Some(enabled), ..Default::default() }).await
+                .map(|_| format!("lock with session {}", if enabled { "on" } else { "off" }))
+        });
+    }
+
+    fn submit(&mut self, kind: InputKind, id: u32, value: String) {
+        let mut client = self.client.clone();
+        match kind {
+            InputKind::Password => self.spawn_unlock(id, Some(value)),
+            InputKind::Rename => self.spawn(async move {
+                client.update_vault(UpdateVaultRequest { id, name: Some(value), ..Default::default() }).await
+                    .map(|_| "vault renamed".to_string())
+            }),
+            InputKind::IdleTimeout => match value.trim().parse() {
+                Ok(mins) => self.spawn(async move {
+                    client.update_vault(UpdateVaultRequest { id, idle_timeout_mins: Some(mins), ..Default::default() }).await
+                        .map(|_| "idle timeout changed".to_string())
+                }),
+                Err(_) => self.status = Some(("idle timeout must be a number of minutes".to_string(), true)),
+            },
+            InputKind::MountPoint => {
+                self.status = Some(("changing mount point...".to_string(), false));
+                self.spawn(async move {
+                    client.change_mount_point(StringIdRequest { id, value }).await.map(|_| "mount point changed".to_string())
+                })
+            }
+            InputKind::DataDir => {
+                let tx = self.tx.clone();
+                tokio::spawn(async move {
+                    let operation_id = match client.start_change_data_dir(StringIdRequest { id, value }).await {
+                        Ok(response) => response.into_inner().operation_id,
+                        Err(status) => {
+                            let _ = tx.send(AppEvent::Failed(status));
+                            return;
+                        }
+                    };
+                    let event = Self::watch_operation(&mut client, operation_id, &tx, "moving data").await
+                        .map_or_else(AppEvent::Failed, |_| AppEvent::Done("data dir changed".to_string()));
+                    let _ = tx.send(event);
+                });
+            }
+        }
+    }
+
+    fn spawn_unlock(&mut self, id: u32, password: Option<String>) {
+        self.status = Some(("unlocking...".to_string(), false));
+        let (mut client, tx) = (self.client.clone(), self.tx.clone());
+        tokio::spawn(async move {
+            let res = match client.start_unlock(UnlockRequest { id, password, duration_secs: None }).await {
+                Ok(response) => Self::watch_operation(&mut client, response.into_inner().operation_id, &tx, "unlocking").await,
+                Err(status) => Err(status),
+            };
+            let event = match res {
+                Ok(_) => AppEvent::Done("vault unlocked".to_string()),
+                Err(status) => match VaultServiceError::try_from(status.clone()) {
+                    Ok(VaultServiceError::VaultHandlerError(VaultHandlerError::PasswordRequired)) => AppEvent::PasswordNeeded { id, invalid: false },
+                    Ok(VaultServiceError::VaultHandlerError(VaultHandlerError::InvalidPassword)) => AppEvent::PasswordNeeded { id, invalid: true },
+                    _ => AppEvent::Failed(status),
+                },
+            };
+            let _ = tx.send(event);
+        });
+    }
+
+    /// Reports progress until the operation finishes.
+    async fn watch_operation(client: &mut Client, operation_id: u64, tx: &UnboundedSender<AppEvent>, what: &str) -> Result<(), Status> {
+        let mut stream = client.watch_operation(OperationIdRequest { operation_id }).await?.into_inner();
+        while let Some(progress) = stream.message().await? {
+            match progress.state() {
+                State::Running => {
+                    let phase = if progress.phase.is_empty() { what } else { progress.phase.as_str() };
+                    let _ = tx.send(AppEvent::Progress(format!("{} {:.0}%", phase, progress.percent)));
+                }
+                State::Succeeded => return Ok(()),
+                State::Cancelled => return Err(Status::cancelled("cancelled")),
+            }
+        }
+        Err(Status::unknown("lost track of the operation"))
+    }
+
+    fn spawn<F>(&self, f: F) where F: std::future::Future<Output=Result<String, Status>> + Send + 'static {
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(f.await.map_or_else(AppEvent::Failed, AppEvent::Done));
+        });
+    }
+}
@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use tokio::sync::mpsc::UnboundedSender;
+use tonic::transport::Error;
+
+use rencfs_desktop_common::transport::{self, AuthChannel};
+
+use crate::app::AppEvent;
+use crate::daemon_service::EmptyRequest;
+use crate::daemon_service::vault_service_client::VaultServiceClient;
+
+pub type Client = VaultServiceClient<AuthChannel>;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+pub async fn connect() -> Result<Client, Error> {
+    Ok(VaultServiceClient::new(transport::connect().await?))
+}
+
+/// Tells the app to reload whenever a vault changes, reconnecting if daemon restarts.
+pub fn watch_vaults(mut client: Client, tx: UnboundedSender<AppEvent>) {
+    tokio::spawn(async move {
+        loop {
+            if let Ok(response) = client.watch_vaults(EmptyRequest {}).await {
+                let mut stream = response.into_inner();
+                while let Ok(Some(event)) = stream.message().await {
+                    if tx.send(AppEvent::VaultEvent(event)).is_err() {
+                        return;
+                    }
+                }
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            if let Ok(c) = connect().await {
+                client = c;
+            }
+        }
+    });
+}
@@ -0,0 +1,109 @@
+use std::io::{self, stdout};
+use std::panic;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyEventKind};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::ExecutableCommand;
+use dotenvy::dotenv;
+use ratatui::backend::CrosstermBackend;
+use ratatui::Terminal;
+use tokio::sync::mpsc;
+
+use crate::app::{App, AppEvent};
+
+mod daemon_service {
+    tonic::include_proto!("rencfs_desktop");
+}
+
+mod app;
+mod daemon;
+mod ui;
+
+const TICK: Duration = Duration::from_secs(1);
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let _ = dotenv();
+
+    let client = match daemon::connect().await {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("error: cannot connect to daemon, make sure it's running: {}", err);
+            std::process::exit(3);
+        }
+    };
+
+    init_terminal()?;
+    // leave the terminal usable if we panic
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        hook(info);
+    }));
+
+    let res = run(client).await;
+    restore_terminal()?;
+    res
+}
+
+async fn run(client: daemon::Client) -> io::Result<()> {
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    read_keys(tx.clone());
+    daemon::watch_vaults(client.clone(), tx.clone());
+
+    let mut app = App::new(client, tx);
+    app.reload().await;
+    while !app.quit {
+        terminal.draw(|f| ui::draw(f, &app))?;
+        match rx.recv().await {
+            Some(event) => app.handle(event).await,
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// crossterm reads are blocking, so they get their own thread.
+///
+/// When no key is pressed for a second we redraw anyway, to keep countdowns ticking.
+fn read_keys(tx: mpsc::UnboundedSender<AppEvent>) {
+    std::thread::spawn(move || loop {
+        match event::poll(TICK) {
+            Ok(true) => {}
+            Ok(false) => {
+                if tx.send(AppEvent::Redraw).is_err() {
+                    return;
+                }
+                continue;
+            }
+            Err(_) => return,
+        }
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                if tx.send(AppEvent::Key(key)).is_err() {
+                    return;
+                }
+            }
+            Ok(Event::Resize(_, _)) => {
+                let _ = tx.send(AppEvent::Redraw);
+            }
+            Ok(_) => {}
+            Err(_) => return,
+        }
+    });
+}
+
+fn init_terminal() -> io::Result<()> {
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    Ok(())
+}
+
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    stdout().execute(LeaveAlternateScreen)?;
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap};
+
+use rencfs_desktop_common::countdown::time_left;
+
+use crate::app::{App, InputKind, Mode};
+use crate::daemon_service::vault_info::State as VaultState;
+use crate::daemon_service::VaultInfo;
+
+const HELP: &str = "↑/↓ select  enter/space lock/unlock  u unlock  l lock  L force lock  r rename  m mount point  d data dir  t idle timeout  s lock with session  e extend unlock  / search  q quit";
+
+pub fn draw(f: &mut Frame, app: &App) {
+    let [search, main, status, help] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(5),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ]).areas(f.size());
+
+    draw_search(f, app, search);
+
+    let [list, detail] = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+    draw_list(f, app, list);
+    draw_detail(f, app, detail);
+
+    if let Some((message, is_error)) = &app.status {
+        let style = if *is_error { Style::new().fg(Color::Red) } else { Style::new().fg(Color::Green) };
+        f.render_widget(Paragraph::new(message.as_str()).style(style), status);
+    }
+    f.render_widget(Paragraph::new(HELP).style(Style::new().fg(Color::DarkGray)), help);
+
+    if let Mode::Input(kind) = app.mode {
+        draw_input(f, app, kind);
+    }
+}
+
+fn draw_search(f: &mut Frame, app: &App, area: Rect) {
+    let style = if app.mode == Mode::Search { Style::new().fg(Color::Yellow) } else { Style::new() };
+    let search = Paragraph::new(app.search.as_str())
+        .block(Block::default().borders(Borders::ALL).title("Search").border_style(style));
+    f.render_widget(search, area);
+    if app.mode == Mode::Search {
+        f.set_cursor(area.x + 1 + app.search.chars().count() as u16, area.y + 1);
+    }
+}
+
+fn draw_list(f: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = app.filtered().iter()
+        .map(|v| {
+            let countdown = v.lock_at.filter(|_| !v.locked).map_or(String::new(), |t| format!(" {}", time_left(t)));
+            ListItem::new(format!("{}{} {}{}", if v.locked { "🔒" } else { "🔓" }, countdown, v.name, state_suffix(v)))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Vaults"))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(app.selected_vault().map(|_| app.selected));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_detail(f: &mut Frame, app: &App, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title("Details");
+    let Some(vault) = app.selected_vault() else {
+        f.render_widget(Paragraph::new("No vaults").block(block), area);
+        return;
+    };
+    let mut lines = vec![
+        field("Name", &vault.name),
+        field("State", &vault.state().as_str_name().to_lowercase()),
+        field("Mount point", &vault.mount_point),
+        field("Data dir", &vault.data_dir),
+        field("Remember password", if vault.remember_password { "yes" } else { "no" }),
+        field("Restart policy", &vault.restart_policy),
+        field("Lock after", &match vault.idle_timeout_mins {
+            0 => "never".to_string(),
+            mins => format!("{} min idle", mins),
+        }),
+        field("Lock with session", if vault.lock_with_session { "yes" } else { "no" }),
+    ];
+    if let Some(lock_at) = vault.lock_at.filter(|_| !vault.locked) {
+        lines.push(field("Locks in", &time_left(lock_at)));
+    }
+    if let Some(status) = vault.last_exit_status {
+        lines.push(field("Last exit status", &status.to_string()));
+    }
+    f.render_widget(Paragraph::new(lines).block(block).wrap(Wrap { trim: false }), area);
+}
+
+/// Only for states other than locked and unlocked, as the icon tells those.
+fn state_suffix(v: &VaultInfo) -> String {
+    match v.state() {
+        VaultState::Locked | VaultState::Unlocked => String::new(),
+        state => format!(" ({})", state.as_str_name().to_lowercase()),
+    }
+}
+
+fn field(name: &str, value: &str) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(format!("{}: ", name), Style::new().add_modifier(Modifier::BOLD)),
+        Span::raw(value.to_string()),
+    ])
+}
+
+/// Popup in the middle of the screen.
+fn draw_input(f: &mut Frame, app: &App, kind: InputKind) {
+    let area = centered(f.size(), 60, 3);
+    let text = match kind {
+        InputKind::Password => "*".repeat(app.input.chars().count()),
+        _ => app.input.clone(),
+    };
+    let input = Paragraph::new(text)
+        .block(Block::default().borders(Borders::ALL).title(kind.title()).title_bottom("enter ok, esc cancel"));
+    f.render_widget(Clear, area);
+    f.render_widget(input, area);
+    f.set_cursor(area.x + 1 + (app.input.chars().count() as u16).min(area.width.saturating_sub(2)), area.y + 1);
+}
+
+fn centered(area: Rect, percent_x: u16, height: u16) -> Rect {
+    // in u32, `area.width * percent_x` overflows u16 on wide terminals
+    let width = (area.width as u32 * percent_x.min(100) as u32 / 100) as u16;
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + area.height.saturating_sub(height) / 2,
+        width,
+        height: height.min(area.height),
+    }
+}